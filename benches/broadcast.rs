@@ -0,0 +1,113 @@
+//! the throughput of the server with 20 simulated clients
+//! every client opens the same file, moves its cursor `MESSAGES` times
+//! and waits for the cursors of the others
+//! one more client never reads its messages, it should not slow down the others
+//! run it with `cargo bench --bench broadcast`
+use std::time::{Duration, Instant};
+
+use futures::{SinkExt as _, StreamExt as _};
+use pairwriter::prelude::*;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+const CLIENTS: usize = 20;
+const MESSAGES: usize = 200;
+const PORT: u16 = 8095;
+
+type Ws = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn connect(username: String) -> Ws {
+    let url = format!("ws://127.0.0.1:{PORT}");
+    let mut ws = loop {
+        match connect_async(&url).await {
+            Ok((ws, _)) => break ws,
+            Err(_) => tokio::time::sleep(Duration::from_millis(50)).await, // the server is starting
+        }
+    };
+    ws.send(RPC::AddUsername(username).encode().unwrap())
+        .await
+        .unwrap();
+    match ws.next().await {
+        Some(Ok(Message::Binary(msg))) => {
+            assert!(matches!(RPC::decode(&msg), Ok(RPC::ResConnect { .. })))
+        }
+        msg => panic!("expected ResConnect found {msg:?}"),
+    }
+    // the cursors are only sent to the clients that have the file open
+    let rpc = RPC::OpenFile {
+        path: "./file.txt".try_into().unwrap(),
+    };
+    ws.send(rpc.encode().unwrap()).await.unwrap();
+    match ws.next().await {
+        Some(Ok(Message::Binary(msg))) => {
+            assert!(matches!(RPC::decode(&msg), Ok(RPC::ResSendFile { .. })))
+        }
+        msg => panic!("expected ResSendFile found {msg:?}"),
+    }
+    ws
+}
+
+#[tokio::main]
+async fn main() {
+    // the server shares the current directory
+    let dir = std::env::temp_dir().join(format!("pairwriter_bench_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("file.txt"), "hello world").unwrap();
+    std::env::set_current_dir(&dir).unwrap();
+    std::env::set_var("LOGFILE", dir.join("log.txt"));
+    tokio::spawn(start_server(PORT));
+
+    // the tcp buffers are filled first and then its outbox
+    let stalled = connect("stalled".to_string()).await;
+    let mut clients = Vec::with_capacity(CLIENTS);
+    for i in 0..CLIENTS {
+        clients.push(connect(format!("user{i}")).await);
+    }
+
+    let start = Instant::now();
+    let mut writers = vec![];
+    let mut readers = vec![];
+    for ws in clients {
+        let (mut send, mut res) = ws.split();
+        writers.push(tokio::spawn(async move {
+            for position in 0..MESSAGES {
+                let rpc = RPC::ReqMoveCursor {
+                    path: "./file.txt".try_into().unwrap(),
+                    position,
+                };
+                send.send(rpc.encode().unwrap()).await.unwrap();
+            }
+            send // the connection is kept open until every one is done
+        }));
+        readers.push(tokio::spawn(async move {
+            let mut received = 0;
+            while received < (CLIENTS - 1) * MESSAGES {
+                match res.next().await {
+                    Some(Ok(Message::Binary(msg))) => {
+                        if let Ok(RPC::ResMoveCursor { .. }) = RPC::decode(&msg) {
+                            received += 1;
+                        }
+                    }
+                    Some(Ok(_)) => {}
+                    msg => panic!("the connection is closed: {msg:?}"),
+                }
+            }
+            res
+        }));
+    }
+    let mut connections = vec![];
+    for (writer, reader) in writers.into_iter().zip(readers) {
+        connections.push((writer.await.unwrap(), reader.await.unwrap()));
+    }
+    let elapsed = start.elapsed();
+    let delivered = CLIENTS * (CLIENTS - 1) * MESSAGES;
+    println!(
+        "{CLIENTS} clients, {} messages sent, {delivered} delivered in {elapsed:?} ({:.0} messages/s)",
+        CLIENTS * MESSAGES,
+        delivered as f64 / elapsed.as_secs_f64()
+    );
+    let stats = server_api.lock().await.queue_stats().await;
+    println!("the stalled client: {:?}", stats.get("stalled"));
+    drop((connections, stalled));
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -1,10 +1,18 @@
 use crate::{
     client::messaging::client_send_message,
     communication::{
-        file_tree::{client_funcs::PubClientFn as _, FileTree},
-        rpc::RPC,
+        event::{ConnectionState, Event},
+        file_entry::FileEntry,
+        file_tree::{
+            client_funcs::PubClientFn as _,
+            crdt::{Edit, EditError},
+            FileTree,
+        },
+        participant::Presence,
+        permissions::{Permissions, Priviledge},
+        project_path::ProjectPath,
+        rpc::{Link, WorkspaceRoot, RPC},
     },
-    server::connection::Priviledge,
 };
 use std::io;
 
@@ -15,28 +23,65 @@ type Res<T> = io::Result<T>;
 #[derive(Debug)]
 pub struct ClientApi {
     file_tree: FileTree,
-    pub priviledge: Priviledge,
-    sender: UnboundedSender<RPC>,
-    pub receiver: Option<UnboundedReceiver<RPC>>,
+    /// the username of this client
+    username: String,
+    /// the others, their cursors and the own edits
+    presence: Presence,
+    pub permissions: Permissions,
+    /// the shared directories of the host
+    roots: Vec<WorkspaceRoot>,
+    /// apply the changes of the file tree before the server accepts them
+    optimistic: bool,
+    /// the changes that are applied optimistically and not echoed by the server yet
+    pending: Vec<RPC>,
+    sender: UnboundedSender<Event>,
+    pub receiver: Option<UnboundedReceiver<Event>>,
 }
 
 impl ClientApi {
-    pub(crate) fn new(files: Vec<String>, emty_dirs: Vec<String>, priviledge: Priviledge) -> Self {
+    pub(crate) fn new(
+        files: Vec<FileEntry>,
+        emty_dirs: Vec<ProjectPath>,
+        dirs: Vec<ProjectPath>,
+        links: Vec<Link>,
+        permissions: Permissions,
+        username: String,
+        users: Vec<String>,
+    ) -> Self {
         let (sender, receiver) = unbounded_channel();
+        let _ = sender.send(Event::ConnectionState(ConnectionState::Connected));
         let receiver = Some(receiver);
         Self {
-            file_tree: FileTree::build_tree(files, emty_dirs),
-            priviledge,
+            file_tree: FileTree::build_tree(files, emty_dirs, dirs, links),
+            presence: Presence::new(users.into_iter().filter(|user| *user != username)),
+            username,
+            permissions,
+            roots: vec![],
+            optimistic: false,
+            pending: vec![],
             sender,
             receiver,
         }
     }
 
-    pub fn get_receiver(&mut self) -> Option<UnboundedReceiver<RPC>> {
+    pub(crate) fn with_roots(mut self, roots: Vec<WorkspaceRoot>) -> Self {
+        self.roots = roots;
+        self
+    }
+
+    /// the shared directories of the host, their content is in `WorkspaceRoot::path`
+    /// (it is `./` if the host shares only one directory)
+    pub async fn roots(&self) -> Vec<WorkspaceRoot> {
+        self.roots.clone()
+    }
+
+    /// the events of the session (see `Event`)
+    pub fn get_receiver(&mut self) -> Option<UnboundedReceiver<Event>> {
         self.receiver.take()
     }
 
     pub async fn read_file(&mut self, path: String) -> Res<Vec<u8>> {
+        let path = ProjectPath::new(&path)?;
         let file_tree = &self.file_tree;
         let file = file_tree.read_buf(&path);
         let file = match file {
@@ -45,55 +90,387 @@ impl ClientApi {
                 return Err(e);
             }
             _ => {
-                let rpc = RPC::ReqBufferTree { path };
+                let rpc = RPC::OpenFile { path };
                 let msg = rpc.encode().unwrap();
                 let _ = client_send_message(msg).await;
 
-                return Err(io::Error::new(io::ErrorKind::Other, "requesting the file"));
+                return Err(io::Error::other("requesting the file"));
             }
         };
         Ok(file)
     }
 
+    /// stop getting the edits of a file, it should be read again to open it
+    pub async fn close_file(&mut self, path: String) -> Res<()> {
+        let path = ProjectPath::new(&path)?.into_file()?;
+        self.file_tree.tree.remove(path.as_str());
+        self.file_tree.views.remove(path.as_str());
+        let rpc = RPC::CloseFile { path };
+        let _ = client_send_message(rpc.encode().unwrap()).await;
+        Ok(())
+    }
+
     pub async fn read_tx(&mut self, rpc: RPC) {
-        if let RPC::ChangePriviledge { priviledge } = rpc {
-            self.priviledge = priviledge;
-            return;
+        let event = match rpc {
+            RPC::ChangePriviledge { priviledge, scopes } => {
+                self.permissions = Permissions { priviledge, scopes };
+                Some(Event::PrivilegeChanged {
+                    username: self.username.clone(),
+                    permissions: self.permissions.clone(),
+                })
+            }
+            RPC::Rejected {
+                rpc: ref rejected,
+                ref reason,
+            } => {
+                log::error!("{rejected:?} is rejected by the server: {reason}");
+                let pending = self
+                    .pending
+                    .iter()
+                    .position(|pending| *pending == rejected.echo());
+                if let Some(i) = pending {
+                    let pending = self.pending.remove(i);
+                    if let Err(e) = self.file_tree.rollback(pending) {
+                        log::error!("couldn't undo {rejected:?}: {e}");
+                    }
+                }
+                Event::from_rpc(&rpc)
+            }
+            RPC::UserJoined { ref username } if *username == self.username => None,
+            // the server accepted a change that is already applied
+            ref rpc if self.pending.contains(rpc) => {
+                let i = self.pending.iter().position(|pending| pending == rpc);
+                self.pending.remove(i.unwrap());
+                Event::from_rpc(rpc)
+            }
+            rpc => {
+                let before = self
+                    .is_listened()
+                    .then(|| Event::heads_before(&self.file_tree, &rpc))
+                    .flatten();
+                let event = Event::from_rpc(&rpc);
+                self.file_tree.handle_msg(rpc);
+                match before {
+                    Some((path, before)) => Event::changed(&self.file_tree, path, &before),
+                    None => event,
+                }
+            }
+        };
+        if let Some(event) = event {
+            self.emit(event);
+        }
+    }
+
+    /// send an event to the receiver (and the event of following if there is one)
+    fn emit(&mut self, event: Event) {
+        let following = self.presence.observe(&event);
+        for event in [Some(event), following].into_iter().flatten() {
+            let _ = self.sender.send(event);
         }
-        let file_tree = &mut self.file_tree;
-        file_tree.handle_msg(rpc.clone());
-        let _ = self.sender.send(rpc);
     }
 
-    pub async fn send_rpc(&mut self, rpc: RPC) {
-        if self.priviledge == Priviledge::ReadOnly {
-            todo!()
+    /// the receiver of the events is not dropped (the events that are costly are skipped if it is)
+    fn is_listened(&self) -> bool {
+        !self.sender.is_closed()
+    }
+
+    /// the connection to the server is closed
+    pub(crate) fn disconnected(&mut self) {
+        self.emit(Event::ConnectionState(ConnectionState::Disconnected));
+    }
+
+    /// the server dropped messages that this client couldn't receive in time
+    /// so the tree is built again from `ResConnect` and the loaded files are dropped
+    /// (they should be requested again)
+    pub(crate) fn resync(&mut self, rpc: RPC) {
+        if let RPC::ResConnect {
+            ref files,
+            ref emty_dirs,
+            ref dirs,
+            ref links,
+            priviledge,
+            ref scopes,
+            ref users,
+            ref roots,
+            ..
+        } = rpc
+        {
+            self.roots = roots.clone();
+            self.file_tree = FileTree::build_tree(
+                files.clone(),
+                emty_dirs.clone(),
+                dirs.clone(),
+                links.clone(),
+            );
+            let users = users.iter().filter(|user| **user != self.username);
+            self.presence = Presence::new(users.cloned());
+            self.permissions = Permissions {
+                priviledge,
+                scopes: scopes.clone(),
+            };
+            self.emit(Event::ConnectionState(ConnectionState::Resynced));
         }
+    }
+
+    /// ask the host for a higher role
+    /// the answer is `Event::PrivilegeChanged` (with the same role if it is denied)
+    pub async fn request_access(&self, priviledge: Priviledge) {
+        let rpc = RPC::ReqAccess { priviledge };
+        let _ = client_send_message(rpc.encode().unwrap()).await;
+    }
+
+    /// send an rpc to the server
+    /// it is not sent if the role of the client doesn't allow it (`PermissionDenied`)
+    pub async fn send_rpc(&mut self, rpc: RPC) -> Res<()> {
+        self.permissions.authorize(&rpc)?;
         let _ = client_send_message(rpc.encode().unwrap()).await; // this to stop message fluding
+        Ok(())
+    }
+
+    /// apply the changes of the file tree locally before the server accepts them
+    /// (they are undone if the server rejects them), this is off by default
+    pub fn set_optimistic(&mut self, optimistic: bool) {
+        self.optimistic = optimistic;
+    }
+
+    /// send a change of the file tree (it is applied now if the client is optimistic)
+    async fn change_tree(&mut self, rpc: RPC) -> Res<()> {
+        self.permissions.authorize(&rpc)?;
+        if self.optimistic {
+            self.file_tree.apply(rpc.clone())?;
+            // it is compared with what the server sends back
+            self.pending.push(rpc.echo());
+        }
+        let _ = client_send_message(rpc.encode().unwrap()).await;
+        Ok(())
+    }
+
+    /// `mode` is the unix permissions of the file (`None` is `0o644`)
+    pub async fn create_file(&mut self, path: String, mode: Option<u32>) -> Res<()> {
+        let path = ProjectPath::new(&path)?.into_file()?;
+        let content = None;
+        self.change_tree(RPC::CreateFile {
+            path,
+            mode,
+            content,
+        })
+        .await
+    }
+
+    /// create a file with its content (text or bytes) in one step, like for "save as" or a paste
+    pub async fn create_file_with(
+        &mut self,
+        path: String,
+        mode: Option<u32>,
+        content: impl Into<Vec<u8>>,
+    ) -> Res<()> {
+        let path = ProjectPath::new(&path)?.into_file()?;
+        let content = Some(content.into());
+        self.change_tree(RPC::CreateFile {
+            path,
+            mode,
+            content,
+        })
+        .await
+    }
+
+    /// the server copies the file, its content is not sent through the client
+    pub async fn copy_file(&mut self, path: String, new_path: String) -> Res<()> {
+        let path = ProjectPath::new(&path)?.into_file()?;
+        let new_path = ProjectPath::new(&new_path)?.into_file()?;
+        self.change_tree(RPC::CopyFile { path, new_path }).await
+    }
+
+    pub async fn delete_file(&mut self, path: String) -> Res<()> {
+        let path = ProjectPath::new(&path)?.into_file()?;
+        self.change_tree(RPC::DeleteFile { path }).await
+    }
+
+    pub async fn move_file(&mut self, path: String, new_path: String) -> Res<()> {
+        let path = ProjectPath::new(&path)?.into_file()?;
+        let new_path = ProjectPath::new(&new_path)?.into_file()?;
+        self.change_tree(RPC::MoveFile { path, new_path }).await
+    }
+
+    pub async fn create_dir(&mut self, path: String) -> Res<()> {
+        let path = ProjectPath::new(&path)?.into_dir();
+        self.change_tree(RPC::CreateDirectory { path }).await
     }
+
+    pub async fn delete_dir(&mut self, path: String) -> Res<()> {
+        let path = ProjectPath::new(&path)?.into_dir();
+        self.change_tree(RPC::DeleteDirectory { path }).await
+    }
+
+    pub async fn move_dir(&mut self, path: String, new_path: String) -> Res<()> {
+        let path = ProjectPath::new(&path)?.into_dir();
+        let new_path = ProjectPath::new(&new_path)?.into_dir();
+        self.change_tree(RPC::MoveDirectory { path, new_path })
+            .await
+    }
+
+    /// put the last deleted file or directory in `path` back from the trash of the server
+    /// (the directories end with `/`)
+    pub async fn restore(&mut self, path: String) -> Res<()> {
+        let path = ProjectPath::new(&path)?;
+        self.change_tree(RPC::Restore { path }).await
+    }
+
+    /// the server copies the directory with its content
+    pub async fn copy_dir(&mut self, path: String, new_path: String) -> Res<()> {
+        let path = ProjectPath::new(&path)?.into_dir();
+        let new_path = ProjectPath::new(&new_path)?.into_dir();
+        self.change_tree(RPC::CopyDirectory { path, new_path })
+            .await
+    }
+
+    /// save the file on the server (the ack is sent only to this client)
+    pub async fn save(&mut self, path: String) -> Res<()> {
+        let path = ProjectPath::new(&path)?.into_file()?;
+        self.send_rpc(RPC::ReqSaveFile { path }).await
+    }
+
+    /// the role of the client for a path
+    /// (a plugin could make a buffer non modifiable if it is lower than `Editor`)
+    pub async fn priviledge_for(&self, path: String) -> Res<Priviledge> {
+        Ok(self.permissions.priviledge_for(&ProjectPath::new(&path)?))
+    }
+
+    /// `true` if the client is allowed to send the rpc
+    pub async fn is_allowed(&self, rpc: &RPC) -> bool {
+        self.permissions.authorize(rpc).is_ok()
+    }
+    /// edit a text file, see `ServerApi::edit_buf`
     pub async fn edit_buf(
         &mut self,
         path: String,
         pos: Option<usize>,
         del: Option<isize>,
         text: &str,
-    ) {
-        if self.priviledge == Priviledge::ReadOnly {
-            return;
+    ) -> Result<(), EditError> {
+        let path = ProjectPath::new(&path).map_err(|e| EditError::Invalid(e.to_string()))?;
+        self.edit_text(path, pos, del, text, true).await
+    }
+
+    /// `record` is `false` for the edits of `undo` and `redo`
+    async fn edit_text(
+        &mut self,
+        path: ProjectPath,
+        pos: Option<usize>,
+        del: Option<isize>,
+        text: &str,
+        record: bool,
+    ) -> Result<(), EditError> {
+        if !self.permissions.allows(&path, Priviledge::Editor) {
+            return Err(EditError::PermissionDenied {
+                needed: Priviledge::Editor,
+            });
+        }
+        if self.file_tree.views.contains_key(path.as_str()) {
+            return Err(EditError::ViewOnly);
+        }
+        let map = &mut self.file_tree.tree;
+        let file = map.get_mut(path.as_str()).ok_or(EditError::NotLoaded)?;
+        let before = if record {
+            let text = file.read().map_err(|e| EditError::Invalid(e.to_string()))?;
+            Some((file.current_heads(), text))
+        } else {
+            None
+        };
+        let result = file.edit(pos, del, text)?;
+        if let Some((heads, text)) = before {
+            let splices = file.splices_since(&heads);
+            if let (Some(splices), Ok(text)) = (splices, String::from_utf8(text)) {
+                self.presence.edited(path.as_str(), &text, &splices);
+            }
+        }
+        self.send_edit(path, result).await;
+        Ok(())
+    }
+
+    /// replace the whole content of a binary file, see `ServerApi::replace_bin`
+    pub async fn replace_bin(&mut self, path: String, content: &[u8]) -> Result<(), EditError> {
+        let path = ProjectPath::new(&path).map_err(|e| EditError::Invalid(e.to_string()))?;
+        if !self.permissions.allows(&path, Priviledge::Editor) {
+            return Err(EditError::PermissionDenied {
+                needed: Priviledge::Editor,
+            });
+        }
+        if self.file_tree.views.contains_key(path.as_str()) {
+            return Err(EditError::ViewOnly);
         }
         let map = &mut self.file_tree.tree;
-        let file = map.get_mut(&path).unwrap();
-        let result = file.edit(pos, del, text);
+        let file = map.get_mut(path.as_str()).ok_or(EditError::NotLoaded)?;
+        let result = file.replace_bin(content)?;
+        self.send_edit(path, result).await;
+        Ok(())
+    }
 
+    async fn send_edit(&self, path: ProjectPath, (changes, old_head_idx, new_heads): Edit) {
         let rpc = RPC::EditBuffer {
             path,
-            changes: result.0,
-            old_head_idx: result.1,
-            new_heads: result.2,
+            changes,
+            old_head_idx,
+            new_heads,
         }; // this is safe because this operation is idiempotent
         let _ = client_send_message(rpc.encode().unwrap()).await;
     }
-    pub async fn get_file_maps(&self) -> (&Vec<String>, &Vec<String>) {
+
+    /// `true` if the file is too large to be edited (it could only be read)
+    pub async fn is_view_only(&self, path: String) -> bool {
+        ProjectPath::new(&path).is_ok_and(|path| self.file_tree.views.contains_key(path.as_str()))
+    }
+
+    /// `Some(true)` if the file is loaded and binary
+    pub async fn is_binary(&self, path: String) -> Option<bool> {
+        let path = ProjectPath::new(&path).ok()?;
+        self.file_tree
+            .tree
+            .get(path.as_str())
+            .map(|file| file.is_binary())
+    }
+
+    pub async fn get_file_maps(&self) -> (Vec<String>, Vec<String>) {
         self.file_tree.get_maps()
     }
+
+    /// the symlinks that are known (they can't be opened)
+    pub async fn get_links(&self) -> Vec<Link> {
+        self.file_tree.links()
+    }
+
+    /// the size, the mode and the encoding of a file that is in the tree
+    pub async fn file_entry(&self, path: String) -> Option<FileEntry> {
+        self.file_tree.file_entry(&ProjectPath::new(&path).ok()?)
+    }
+
+    /// directories that are known to exist but their content is not loaded yet
+    pub async fn get_unloaded_dirs(&self) -> Vec<String> {
+        self.file_tree.get_unloaded_dirs()
+    }
+
+    /// request the content of a directory from the server
+    /// `depth` of `None` means the whole subtree, `limit` of `None` means all the entries
+    /// the next page starts after `cursor` (the last path of the page before)
+    /// the result is applied to the tree and the receiver gets `DirChange::Listed`
+    pub async fn list_dir(
+        &mut self,
+        path: String,
+        depth: Option<usize>,
+        cursor: Option<String>,
+        limit: Option<usize>,
+    ) -> Res<()> {
+        let rpc = RPC::ReqListDir {
+            path: ProjectPath::new(&path)?.into_dir(),
+            depth,
+            cursor: cursor.map(|cursor| ProjectPath::new(&cursor)).transpose()?,
+            limit,
+        };
+        let _ = client_send_message(rpc.encode().unwrap()).await;
+        Ok(())
+    }
 }
+
+mod participant;
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,79 @@
+use super::*;
+use crate::communication::participant::{Cursor, Participant};
+
+impl Participant for ClientApi {
+    fn username(&self) -> String {
+        self.username.clone()
+    }
+
+    async fn move_cursor(&mut self, path: String, position: usize) -> Res<()> {
+        let path = ProjectPath::new(&path)?.into_file()?;
+        self.send_rpc(RPC::ReqMoveCursor { path, position }).await
+    }
+
+    async fn set_selection(&mut self, path: String, start: usize, end: usize) -> Res<()> {
+        let path = ProjectPath::new(&path)?.into_file()?;
+        let rpc = RPC::RequestMark {
+            path,
+            s_position: start.min(end),
+            e_position: start.max(end),
+        };
+        self.send_rpc(rpc).await
+    }
+
+    async fn participants(&self) -> Vec<String> {
+        let others = self.presence.users().cloned();
+        [self.username()].into_iter().chain(others).collect()
+    }
+
+    async fn cursors(&self, path: String) -> Vec<Cursor> {
+        ProjectPath::new(&path).map_or(vec![], |path| self.presence.cursors(path.as_str()))
+    }
+
+    async fn follow(&mut self, username: Option<String>) -> Res<()> {
+        if let Some(event) = self.presence.follow(username)? {
+            self.emit(event);
+        }
+        Ok(())
+    }
+
+    async fn undo(&mut self, path: String) -> Result<(), EditError> {
+        let path = ProjectPath::new(&path).map_err(|e| EditError::Invalid(e.to_string()))?;
+        match self.presence.undo(path.as_str()) {
+            Some(splices) => {
+                for (i, splice) in splices.into_iter().enumerate() {
+                    let (pos, del) = (Some(splice.pos), Some(splice.del as isize));
+                    let result = self
+                        .edit_text(path.clone(), pos, del, &splice.text, false)
+                        .await;
+                    if result.is_err() && i == 0 {
+                        self.presence.redo(path.as_str()); // it could be tried again
+                    }
+                    result?;
+                }
+                Ok(())
+            }
+            None => Ok(()), // there is nothing to undo
+        }
+    }
+
+    async fn redo(&mut self, path: String) -> Result<(), EditError> {
+        let path = ProjectPath::new(&path).map_err(|e| EditError::Invalid(e.to_string()))?;
+        match self.presence.redo(path.as_str()) {
+            Some(splices) => {
+                for (i, splice) in splices.into_iter().enumerate() {
+                    let (pos, del) = (Some(splice.pos), Some(splice.del as isize));
+                    let result = self
+                        .edit_text(path.clone(), pos, del, &splice.text, false)
+                        .await;
+                    if result.is_err() && i == 0 {
+                        self.presence.undo(path.as_str()); // it could be tried again
+                    }
+                    result?;
+                }
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
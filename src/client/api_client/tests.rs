@@ -0,0 +1,324 @@
+use super::*;
+use crate::communication::event::Event;
+use crate::communication::participant::Participant as _;
+
+fn viewer() -> ClientApi {
+    let permissions =
+        Permissions::from(Priviledge::Viewer).with_scope("docs/**", Priviledge::Editor);
+    ClientApi::new(
+        vec![],
+        vec![],
+        vec![],
+        vec![],
+        permissions,
+        "guest".to_string(),
+        vec![],
+    )
+}
+
+#[tokio::test]
+async fn viewer_cant_edit() {
+    let mut api = viewer();
+    assert_eq!(
+        api.edit_buf("./a.txt".to_string(), None, None, "text")
+            .await,
+        Err(EditError::PermissionDenied {
+            needed: Priviledge::Editor
+        })
+    );
+    assert_eq!(
+        api.replace_bin("./a.bin".to_string(), &[0]).await,
+        Err(EditError::PermissionDenied {
+            needed: Priviledge::Editor
+        })
+    );
+    // the file is not loaded but it could be edited
+    assert_eq!(
+        api.edit_buf("./docs/a.md".to_string(), None, None, "text")
+            .await,
+        Err(EditError::NotLoaded)
+    );
+
+    let rpc = RPC::CreateFile {
+        path: "./docs/b.md".try_into().unwrap(),
+        mode: None,
+        content: None,
+    };
+    assert!(!api.is_allowed(&rpc).await);
+    let e = api.send_rpc(rpc).await.unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    assert_eq!(
+        api.priviledge_for("./docs/a.md".to_string()).await.unwrap(),
+        Priviledge::Editor
+    );
+}
+
+fn maintainer() -> ClientApi {
+    let dirs = vec!["./src/".try_into().unwrap()];
+    let files = vec![ProjectPath::new("./src/a.rs").unwrap().into()];
+    ClientApi::new(
+        files,
+        vec![],
+        dirs,
+        vec![],
+        Priviledge::Maintainer.into(),
+        "guest".to_string(),
+        vec![],
+    )
+}
+
+#[tokio::test]
+async fn typed_ops_validate_paths() {
+    let mut api = maintainer();
+    let e = api
+        .create_file("./src/".to_string(), None)
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    assert!(api
+        .move_file("./src/a.rs".to_string(), "../b.rs".to_string())
+        .await
+        .is_err());
+    // not optimistic so the tree is changed only by the server
+    api.create_file("./src/b.rs".to_string(), None)
+        .await
+        .unwrap();
+    let (files, _) = api.get_file_maps().await;
+    assert!(!files.contains(&"./src/b.rs".to_string()));
+
+    let mut api = viewer();
+    let e = api.save("./docs/a.md".to_string()).await.unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    api.move_cursor("./docs/a.md".to_string(), 3).await.unwrap();
+}
+
+#[tokio::test]
+async fn optimistic_rollback() {
+    let mut api = maintainer();
+    api.set_optimistic(true);
+    // the server sends the create back without the content
+    api.create_file_with("./src/b.rs".to_string(), None, "fn b() {}")
+        .await
+        .unwrap();
+    api.move_file("./src/a.rs".to_string(), "./src/c.rs".to_string())
+        .await
+        .unwrap();
+    let (files, _) = api.get_file_maps().await;
+    assert!(files.contains(&"./src/b.rs".to_string()));
+    assert!(files.contains(&"./src/c.rs".to_string()));
+    // an op that can't be applied locally is not sent
+    assert!(api.delete_file("./src/x.rs".to_string()).await.is_err());
+
+    // the create is accepted and the move is rejected
+    let create = RPC::CreateFile {
+        path: "./src/b.rs".try_into().unwrap(),
+        mode: None,
+        content: None,
+    };
+    api.read_tx(create).await;
+    let moved = RPC::MoveFile {
+        path: "./src/a.rs".try_into().unwrap(),
+        new_path: "./src/c.rs".try_into().unwrap(),
+    };
+    let rejected = RPC::Rejected {
+        rpc: Box::new(moved),
+        reason: "the file exists".to_string(),
+    };
+    api.read_tx(rejected).await;
+    let (mut files, _) = api.get_file_maps().await;
+    files.sort();
+    let mut receiver = api.get_receiver().unwrap();
+    receiver.try_recv().unwrap(); // connected
+    assert!(matches!(receiver.try_recv(), Ok(Event::FileCreated { .. })));
+    assert!(matches!(receiver.try_recv(), Ok(Event::Error(_))));
+    assert_eq!(files, ["./src/a.rs", "./src/b.rs"]);
+    assert!(api.pending.is_empty());
+}
+
+/// load a text file like the server sends it
+async fn open(api: &mut ClientApi, path: &str, text: &str) {
+    use automerge::{transaction::Transactable as _, Automerge, ObjType, ROOT};
+
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    let id = tx.put_object(ROOT, "content", ObjType::Text).unwrap();
+    tx.splice_text(id, 0, 0, text).unwrap();
+    tx.commit();
+    let (file, heads_history, head_idx) =
+        crate::communication::file_tree::crdt::Crdt::open(doc).save();
+    api.read_tx(RPC::ResSendFile {
+        path: path.try_into().unwrap(),
+        file,
+        heads_history,
+        head_idx,
+        binary: false,
+        view_only: false,
+        chunked: false,
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn rejected_dir_move_keeps_open_files() {
+    let mut api = maintainer();
+    api.set_optimistic(true);
+    open(&mut api, "./src/a.rs", "fn a() {}").await;
+
+    api.move_dir("./src/".to_string(), "./lib/".to_string())
+        .await
+        .unwrap();
+    let moved = RPC::MoveDirectory {
+        path: "./src/".try_into().unwrap(),
+        new_path: "./lib/".try_into().unwrap(),
+    };
+    let rejected = RPC::Rejected {
+        rpc: Box::new(moved),
+        reason: "the directory exists".to_string(),
+    };
+    api.read_tx(rejected).await;
+    let (files, _) = api.get_file_maps().await;
+    assert_eq!(files, ["./src/a.rs"]);
+    let content = api.read_file("./src/a.rs".to_string()).await.unwrap();
+    assert_eq!(content, b"fn a() {}");
+    assert!(api.pending.is_empty());
+}
+
+#[tokio::test]
+async fn edit_a_renamed_open_file() {
+    let mut api = maintainer();
+    open(&mut api, "./src/a.rs", "fn a() {}").await;
+
+    // another user renamed it
+    api.read_tx(RPC::MoveFile {
+        path: "./src/a.rs".try_into().unwrap(),
+        new_path: "./src/b.rs".try_into().unwrap(),
+    })
+    .await;
+    api.edit_buf("./src/b.rs".to_string(), Some(9), Some(0), "\n")
+        .await
+        .unwrap();
+    let content = api.read_file("./src/b.rs".to_string()).await.unwrap();
+    assert_eq!(content, b"fn a() {}\n");
+}
+
+#[tokio::test]
+async fn chunked_open_reports_progress() {
+    let mut api = maintainer();
+    let mut receiver = api.get_receiver().unwrap();
+    receiver.try_recv().unwrap(); // connected
+    let content = b"a line of a large file\n".repeat(4);
+    for (i, data) in content.chunks(32).enumerate() {
+        api.read_tx(RPC::ResFileChunk {
+            path: "./src/a.rs".try_into().unwrap(),
+            offset: (i * 32) as u64,
+            total: content.len() as u64,
+            data: data.to_vec(),
+        })
+        .await;
+    }
+    let mut received = vec![];
+    while let Ok(Event::FileProgress {
+        total, received: n, ..
+    }) = receiver.try_recv()
+    {
+        assert_eq!(total, content.len() as u64);
+        received.push(n);
+    }
+    assert_eq!(received, [32, 64, 92]);
+}
+
+#[tokio::test]
+async fn events() {
+    use crate::communication::event::{ConnectionState, Splice};
+    use automerge::{transaction::Transactable as _, Automerge, ObjType, ROOT};
+
+    let mut api = maintainer();
+    let mut receiver = api.get_receiver().unwrap();
+    assert_eq!(
+        receiver.try_recv().unwrap(),
+        Event::ConnectionState(ConnectionState::Connected)
+    );
+
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    let id = tx.put_object(ROOT, "content", ObjType::Text).unwrap();
+    tx.splice_text(id, 0, 0, "hello").unwrap();
+    tx.commit();
+    let mut other = crate::communication::file_tree::crdt::Crdt::open(doc);
+    let (file, heads_history, head_idx) = other.save();
+    let path: ProjectPath = "./src/a.rs".try_into().unwrap();
+    api.read_tx(RPC::ResSendFile {
+        path: path.clone(),
+        file,
+        heads_history,
+        head_idx,
+        binary: false,
+        view_only: false,
+        chunked: false,
+    })
+    .await;
+    assert_eq!(
+        receiver.try_recv().unwrap(),
+        Event::FileOpened {
+            path: "./src/a.rs".to_string(),
+            binary: false,
+            view_only: false
+        }
+    );
+
+    // an edit of another user
+    let (changes, old_head_idx, new_heads) = other.edit(Some(5), Some(0), " world").unwrap();
+    let edit = RPC::EditBuffer {
+        path: path.clone(),
+        changes,
+        old_head_idx,
+        new_heads,
+    };
+    api.read_tx(edit.clone()).await;
+    let splice = Splice {
+        pos: 5,
+        del: 0,
+        text: " world".to_string(),
+    };
+    assert_eq!(
+        receiver.try_recv().unwrap(),
+        Event::TextChanged {
+            path: "./src/a.rs".to_string(),
+            splices: vec![splice]
+        }
+    );
+    // nothing changed
+    api.read_tx(edit).await;
+    assert!(receiver.try_recv().is_err());
+
+    api.read_tx(RPC::Undo { path }).await;
+    assert!(matches!(
+        receiver.try_recv().unwrap(),
+        Event::TextChanged { splices, .. } if splices[0].del == 6
+    ));
+
+    api.read_tx(RPC::ChangePriviledge {
+        priviledge: Priviledge::Viewer,
+        scopes: vec![],
+    })
+    .await;
+    assert_eq!(
+        receiver.try_recv().unwrap(),
+        Event::PrivilegeChanged {
+            username: "guest".to_string(),
+            permissions: Priviledge::Viewer.into()
+        }
+    );
+    // the own join is not an event
+    let joined = |username: &str| RPC::UserJoined {
+        username: username.to_string(),
+    };
+    api.read_tx(joined("guest")).await;
+    api.read_tx(joined("a")).await;
+    assert_eq!(
+        receiver.try_recv().unwrap(),
+        Event::UserJoined {
+            username: "a".to_string()
+        }
+    );
+}
@@ -25,7 +25,9 @@ pub async fn client_send_message(msg: Message) -> Result<(), Error> {
 pub(super) fn get_on_message(mut reader: ReaderWsStream) -> impl Future<Output = ()> {
     async move {
         while let Some(message) = reader.next().await {
-            let message = message.expect("Failed to get message"); // todo: handle error
+            let Ok(message) = message else {
+                break; // the connection is lost
+            };
             if let Message::Binary(ref message) = message {
                 #[cfg(feature = "integration_testing_client")]
                 {
@@ -35,25 +37,45 @@ pub(super) fn get_on_message(mut reader: ReaderWsStream) -> impl Future<Output =
                     ));
                 }
                 let rpc = RPC::decode(message.as_slice()).expect("Failed to decode message");
-                if let RPC::ResConnect {
-                    username: _username,
-                    files,
-                    emty_dirs,
-                    priviledge,
-                } = rpc
-                {
-                    API.set(Mutex::new(ClientApi::new(files, emty_dirs, priviledge)))
-                        .unwrap();
-                } else {
-                    match API.get() {
-                        Some(api) => api.lock().await.read_tx(rpc).await,
-                        None => {
-
-                       
+                match (rpc, API.get()) {
+                    // the server sends the state again if this client couldn't keep up
+                    (rpc @ RPC::ResConnect { .. }, Some(api)) => api.lock().await.resync(rpc),
+                    (
+                        RPC::ResConnect {
+                            username: _username,
+                            files,
+                            emty_dirs,
+                            dirs,
+                            links,
+                            priviledge,
+                            scopes,
+                            users,
+                            roots,
                         },
-                    };
+                        None,
+                    ) => {
+                        let permissions = Permissions { priviledge, scopes };
+                        API.set(Mutex::new(
+                            ClientApi::new(
+                                files,
+                                emty_dirs,
+                                dirs,
+                                links,
+                                permissions,
+                                USERNAME.get().cloned().unwrap_or_default(),
+                                users,
+                            )
+                            .with_roots(roots),
+                        ))
+                        .unwrap();
+                    }
+                    (rpc, Some(api)) => api.lock().await.read_tx(rpc).await,
+                    (_, None) => {}
                 }
             }
         }
+        if let Some(api) = API.get() {
+            api.lock().await.disconnected();
+        }
     }
 }
@@ -7,7 +7,7 @@ use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, Web
 pub(crate) mod api_client;
 pub(crate) mod messaging;
 
-use crate::communication::rpc::RPC;
+use crate::communication::{permissions::Permissions, rpc::RPC};
 use api_client::ClientApi;
 
 type WriterWsStream = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
@@ -18,6 +18,9 @@ static WRITER_WS_STREAM: OnceLock<Mutex<WriterWsStream>> = OnceLock::new(); // a
 
 pub static API: OnceLock<Mutex<ClientApi>> = OnceLock::new();
 
+/// the username that is sent to the server
+static USERNAME: OnceLock<String> = OnceLock::new();
+
 /// handle the connection to the server and initialize the writer
 /// for `client_send_message` function
 /// Add the url with **"ws://"** or **"wss://"** prefix
@@ -36,6 +39,7 @@ pub async fn connect_as_client(url: String, username: String) {
                                                                                // server panic is intented here
 
     let (mut writer, reader) = ws_stream.split();
+    let _ = USERNAME.set(username.clone());
     let rpc = RPC::AddUsername(username);
     let message = rpc.encode().unwrap(); // todo handle the error
 
@@ -0,0 +1,314 @@
+use crate::communication::{
+    file_entry::FileEntry,
+    file_tree::FileTree,
+    permissions::{Permissions, Priviledge},
+    project_path::ProjectPath,
+    rpc::RPC,
+};
+use automerge::ChangeHash;
+
+/// a change of a text file, the same as the arguments of `edit_buf`
+/// `pos` and `del` are in characters, the splices of an event are applied in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Splice {
+    pub pos: usize,
+    pub del: usize,
+    pub text: String,
+}
+
+/// what happened to a directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DirChange {
+    Created,
+    Deleted,
+    Moved {
+        new_path: String,
+    },
+    Copied {
+        new_path: String,
+    },
+    /// a page of its content is loaded (see `list_dir`)
+    Listed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    /// the server sent the state again, the loaded files are dropped (see `OverflowPolicy::Resync`)
+    Resynced,
+    Disconnected,
+}
+
+/// what the editor should know about, it is the same for the host and the guests
+/// (the receiver of `ServerApi` and `ClientApi`)
+/// the own changes of the editor are not sent back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// the file is loaded and could be read
+    FileOpened {
+        path: String,
+        binary: bool,
+        view_only: bool,
+    },
+    /// a chunk of a large file that is opened is received (the file is opened after the last one)
+    FileProgress {
+        path: String,
+        received: u64,
+        total: u64,
+    },
+    /// someone else changed the text of an open file
+    TextChanged {
+        path: String,
+        splices: Vec<Splice>,
+    },
+    /// someone else replaced the content of an open binary file
+    BinaryReplaced {
+        path: String,
+    },
+    FileCreated {
+        path: String,
+    },
+    FileMoved {
+        path: String,
+        new_path: String,
+    },
+    FileCopied {
+        path: String,
+        new_path: String,
+    },
+    /// a file or a link is deleted
+    FileDeleted {
+        path: String,
+    },
+    /// the size, the time or the mode of a file changed (see `FileEntry`)
+    MetadataChanged {
+        entry: FileEntry,
+    },
+    /// a symlink is found on the disk of the host
+    LinkCreated {
+        path: String,
+        target: String,
+    },
+    DirChanged {
+        path: String,
+        change: DirChange,
+    },
+    CursorMoved {
+        username: String,
+        path: String,
+        position: usize,
+    },
+    SelectionChanged {
+        username: String,
+        path: String,
+        start: usize,
+        end: usize,
+    },
+    /// the followed participant moved its cursor (see `Participant::follow`)
+    Following {
+        username: String,
+        path: String,
+        position: usize,
+    },
+    UserJoined {
+        username: String,
+    },
+    UserLeft {
+        username: String,
+    },
+    PrivilegeChanged {
+        username: String,
+        permissions: Permissions,
+    },
+    /// a client asked for a higher role (only the host gets it)
+    AccessRequested {
+        username: String,
+        priviledge: Priviledge,
+    },
+    Saved {
+        path: String,
+    },
+    Error(String),
+    ConnectionState(ConnectionState),
+}
+
+impl Event {
+    /// the event of an rpc as it is sent by the server
+    /// the edits are not here as they need the heads before them (see `heads_before`)
+    pub(crate) fn from_rpc(rpc: &RPC) -> Option<Self> {
+        let event = match rpc {
+            RPC::CreateFile { path, .. } => Event::FileCreated {
+                path: path.to_string(),
+            },
+            RPC::DeleteFile { path } => Event::FileDeleted {
+                path: path.to_string(),
+            },
+            RPC::UpdateMetadata { entry } => Event::MetadataChanged {
+                entry: entry.clone(),
+            },
+            RPC::CreateLink { path, target } => Event::LinkCreated {
+                path: path.to_string(),
+                target: target.clone(),
+            },
+            RPC::MoveFile { path, new_path } => Event::FileMoved {
+                path: path.to_string(),
+                new_path: new_path.to_string(),
+            },
+            RPC::CopyFile { path, new_path } => Event::FileCopied {
+                path: path.to_string(),
+                new_path: new_path.to_string(),
+            },
+            RPC::CreateDirectory { path } => dir(path, DirChange::Created),
+            RPC::Restore { path } if path.is_dir() => dir(path, DirChange::Created),
+            RPC::Restore { path } => Event::FileCreated {
+                path: path.to_string(),
+            },
+            RPC::DeleteDirectory { path } => dir(path, DirChange::Deleted),
+            RPC::MoveDirectory { path, new_path } => dir(
+                path,
+                DirChange::Moved {
+                    new_path: new_path.to_string(),
+                },
+            ),
+            RPC::CopyDirectory { path, new_path } => dir(
+                path,
+                DirChange::Copied {
+                    new_path: new_path.to_string(),
+                },
+            ),
+            RPC::ResListDir { path, .. } => dir(path, DirChange::Listed),
+            RPC::ResSendFile {
+                path,
+                binary,
+                view_only,
+                ..
+            } => Event::FileOpened {
+                path: path.to_string(),
+                binary: *binary,
+                view_only: *view_only,
+            },
+            RPC::ResFileChunk {
+                path,
+                offset,
+                total,
+                data,
+            } => Event::FileProgress {
+                path: path.to_string(),
+                received: offset + data.len() as u64,
+                total: *total,
+            },
+            RPC::ResMoveCursor {
+                username,
+                path,
+                position,
+            } => Event::CursorMoved {
+                username: username.clone(),
+                path: path.to_string(),
+                position: *position,
+            },
+            RPC::ResMark {
+                path,
+                s_position,
+                e_position,
+                username,
+            } => Event::SelectionChanged {
+                username: username.clone(),
+                path: path.to_string(),
+                start: *s_position,
+                end: *e_position,
+            },
+            RPC::UserJoined { username } => Event::UserJoined {
+                username: username.clone(),
+            },
+            RPC::UserLeft { username } => Event::UserLeft {
+                username: username.clone(),
+            },
+            RPC::AccessRequested {
+                username,
+                priviledge,
+            } => Event::AccessRequested {
+                username: username.clone(),
+                priviledge: *priviledge,
+            },
+            RPC::FileSaved { path } => Event::Saved {
+                path: path.to_string(),
+            },
+            RPC::Error(e) => Event::Error(e.clone()),
+            RPC::Rejected { rpc, reason } => Event::Error(format!("{rpc:?} is rejected: {reason}")),
+            _ => return None,
+        };
+        Some(event)
+    }
+
+    /// the heads of the file that `rpc` changes (it is used with `changed` after applying it)
+    pub(crate) fn heads_before(
+        tree: &FileTree,
+        rpc: &RPC,
+    ) -> Option<(ProjectPath, Vec<ChangeHash>)> {
+        match rpc {
+            RPC::EditBuffer { path, .. } | RPC::Undo { path } | RPC::Redo { path } => {
+                let heads = tree.tree.get(path.as_str())?.current_heads();
+                Some((path.clone(), heads))
+            }
+            _ => None,
+        }
+    }
+
+    /// the event of an edit, `None` if nothing changed (like the echo of an own edit)
+    /// the splices are from the changes between the heads, the text is not compared
+    pub(crate) fn changed(
+        tree: &FileTree,
+        path: ProjectPath,
+        before: &[ChangeHash],
+    ) -> Option<Self> {
+        let crdt = tree.tree.get(path.as_str())?;
+        if crdt.current_heads() == before {
+            return None;
+        }
+        if crdt.is_binary() {
+            return Some(Event::BinaryReplaced {
+                path: path.to_string(),
+            });
+        }
+        let splices = crdt.splices_since(before)?;
+        if splices.is_empty() {
+            return None;
+        }
+        Some(Event::TextChanged {
+            path: path.to_string(),
+            splices,
+        })
+    }
+}
+
+fn dir(path: &ProjectPath, change: DirChange) -> Event {
+    Event::DirChanged {
+        path: path.to_string(),
+        change,
+    }
+}
+
+/// the splice that makes `after` from `before` (the text between the common prefix and suffix)
+pub(crate) fn splice(before: &str, after: &str) -> Splice {
+    let before: Vec<char> = before.chars().collect();
+    let after: Vec<char> = after.chars().collect();
+    let prefix = before
+        .iter()
+        .zip(&after)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    Splice {
+        pos: prefix,
+        del: before.len() - prefix - suffix,
+        text: after[prefix..after.len() - suffix].iter().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,61 @@
+use super::*;
+
+fn splice_of(before: &str, after: &str) -> (usize, usize, String) {
+    let splice = splice(before, after);
+    (splice.pos, splice.del, splice.text)
+}
+
+#[test]
+fn minimal_splice() {
+    assert_eq!(splice_of("hello", "hello world"), (5, 0, " world".into()));
+    assert_eq!(splice_of("hello world", "hello"), (5, 6, "".into()));
+    assert_eq!(splice_of("abcd", "axyd"), (1, 2, "xy".into()));
+    assert_eq!(splice_of("aaa", "aaaa"), (3, 0, "a".into()));
+    // the positions are in characters
+    assert_eq!(splice_of("éa", "éba"), (1, 0, "b".into()));
+}
+
+#[test]
+fn events_of_rpcs() {
+    let path: ProjectPath = "./a.txt".try_into().unwrap();
+    let cursor = RPC::ResMoveCursor {
+        username: "a".to_string(),
+        path: path.clone(),
+        position: 3,
+    };
+    assert_eq!(
+        Event::from_rpc(&cursor),
+        Some(Event::CursorMoved {
+            username: "a".to_string(),
+            path: "./a.txt".to_string(),
+            position: 3
+        })
+    );
+    let moved = RPC::MoveDirectory {
+        path: "./d/".try_into().unwrap(),
+        new_path: "./e/".try_into().unwrap(),
+    };
+    assert_eq!(
+        Event::from_rpc(&moved),
+        Some(Event::DirChanged {
+            path: "./d/".to_string(),
+            change: DirChange::Moved {
+                new_path: "./e/".to_string()
+            }
+        })
+    );
+    let chunk = RPC::ResFileChunk {
+        path,
+        offset: 4,
+        total: 10,
+        data: vec![0; 3],
+    };
+    assert_eq!(
+        Event::from_rpc(&chunk),
+        Some(Event::FileProgress {
+            path: "./a.txt".to_string(),
+            received: 7,
+            total: 10
+        })
+    );
+}
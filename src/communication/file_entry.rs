@@ -0,0 +1,96 @@
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::communication::project_path::ProjectPath;
+
+/// the number of bytes at the start of a file that are used to guess its encoding
+pub(crate) const SNIFF_SIZE: usize = 1024;
+
+/// a file in the tree with what is known about it without opening it
+#[derive(Debug, Clone, Encode, Decode, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub path: ProjectPath,
+    pub meta: FileMeta,
+}
+
+/// the metadata of a file, it is updated by the watcher
+#[derive(Debug, Clone, Encode, Decode, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMeta {
+    /// in bytes
+    pub size: u64,
+    /// the last change on the disk in milliseconds since the unix epoch (`0` if it is not known)
+    pub mtime: u64,
+    /// the unix permissions like `0o755` (`0o644` on the other systems)
+    pub mode: u32,
+    /// the file can't be edited as text (it is replaced with `replace_bin`)
+    pub binary: bool,
+    pub encoding: Encoding,
+}
+
+/// the guess of the encoding of a file from its first bytes
+#[derive(Debug, Clone, Copy, Default, Encode, Decode, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// utf-8 that starts with a byte order mark
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    /// not text (or an encoding that is not guessed like latin-1)
+    Unknown,
+}
+
+impl Default for FileMeta {
+    /// an empty text file
+    fn default() -> Self {
+        Self {
+            size: 0,
+            mtime: 0,
+            mode: 0o644,
+            binary: false,
+            encoding: Encoding::Utf8,
+        }
+    }
+}
+
+impl FileMeta {
+    /// an empty file with `mode` (the default mode if it is `None`)
+    pub(crate) fn with_mode(mode: Option<u32>) -> Self {
+        let default = Self::default();
+        Self {
+            mode: mode.unwrap_or(default.mode),
+            ..default
+        }
+    }
+
+    /// guess if the file is text from its first bytes (see `SNIFF_SIZE`)
+    /// `whole` is `true` if `head` is the whole file
+    pub(crate) fn sniff(&mut self, head: &[u8], whole: bool) {
+        self.encoding = match head {
+            [0xEF, 0xBB, 0xBF, ..] => Encoding::Utf8Bom,
+            [0xFF, 0xFE, ..] => Encoding::Utf16Le,
+            [0xFE, 0xFF, ..] => Encoding::Utf16Be,
+            _ => match std::str::from_utf8(head) {
+                Ok(_) => Encoding::Utf8,
+                // a character could be cut at the end of the head
+                Err(e) if e.error_len().is_none() && !whole => Encoding::Utf8,
+                Err(_) => Encoding::Unknown,
+            },
+        };
+        // the files that are not utf-8 are opened as binary (see `open_file`)
+        self.binary = !matches!(self.encoding, Encoding::Utf8 | Encoding::Utf8Bom);
+    }
+}
+
+impl From<ProjectPath> for FileEntry {
+    /// an empty text file
+    fn from(path: ProjectPath) -> Self {
+        Self {
+            path,
+            meta: FileMeta::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,21 @@
+use super::*;
+
+fn sniff(head: &[u8], whole: bool) -> (bool, Encoding) {
+    let mut meta = FileMeta::default();
+    meta.sniff(head, whole);
+    (meta.binary, meta.encoding)
+}
+
+#[test]
+fn guess_the_encoding() {
+    assert_eq!(sniff(b"fn main() {}", true), (false, Encoding::Utf8));
+    assert_eq!(sniff(b"\xEF\xBB\xBFtext", true), (false, Encoding::Utf8Bom));
+    assert_eq!(sniff(b"\xFF\xFEt\0", true), (true, Encoding::Utf16Le));
+    assert_eq!(sniff(b"\x89PNG\r\n\x1a\n", true), (true, Encoding::Unknown));
+    // a character that is cut at the end of the head
+    let text = "aé".as_bytes();
+    assert_eq!(sniff(&text[..2], false), (false, Encoding::Utf8));
+    assert_eq!(sniff(&text[..2], true), (true, Encoding::Unknown));
+    // the same as `open_file` (it is opened as text)
+    assert_eq!(sniff(b"a\0b", true), (false, Encoding::Utf8));
+}
@@ -1,50 +1,70 @@
-use std::collections::HashMap;
-use std::io::Error;
-use std::path::Path;
+use super::file_entry::{FileEntry, FileMeta};
+use super::project_path::ProjectPath;
+use super::rpc::Link;
 use crdt::Crdt;
-
+use dir_tree::{DirTree, Node};
+use server_funcs::trash::Trash;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Error};
+use std::path::Path;
 
 #[derive(Debug, Clone)]
 pub(crate) struct FileTree {
     pub(crate) tree: HashMap<String, Crdt>,
-    files: Vec<String>, // this is not efficient but I don't care
-    emty_dirs: Vec<String>, // take care when working with emty_dirs
-                        // Every operation on emty_dirs will be commented with EMTY_DIRS_OP
+    /// the files that are too large to be edited (only used by the client)
+    pub(crate) views: HashMap<String, Vec<u8>>,
+    /// the chunks of the files that are being received (only used by the client)
+    pending: HashMap<String, Vec<u8>>,
+    /// the clients that files are being sent to in chunks by the paths (only used by the server)
+    /// they get the edits after the last chunk (see `ServerApi::loaded`)
+    pub(crate) loading: HashMap<String, HashSet<String>>,
+    /// the files and directories of the project
+    /// (directories that their content is not loaded yet are only used by the client,
+    /// the server always have the full tree)
+    paths: DirTree,
+    /// the deleted paths that could be restored (only used by the server)
+    trash: Trash,
+}
+
+/// what is in a path of the tree (or of the disk)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Entry {
+    File(FileMeta),
+    Dir,
+    /// a symlink and its target
+    Link(String),
+}
+
+impl Entry {
+    /// the same entry without looking at the metadata of the files
+    pub(crate) fn same_kind(&self, other: &Entry) -> bool {
+        match (self, other) {
+            (Entry::File(_), Entry::File(_)) | (Entry::Dir, Entry::Dir) => true,
+            (Entry::Link(target), Entry::Link(other)) => target == other,
+            _ => false,
+        }
+    }
+}
+
+/// a page of the content of a directory
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct DirListing {
+    pub(crate) files: Vec<FileEntry>,
+    pub(crate) emty_dirs: Vec<ProjectPath>,
+    /// directories that are not empty but their content is deeper than the requested depth
+    pub(crate) dirs: Vec<ProjectPath>,
+    pub(crate) links: Vec<Link>,
+    /// the cursor of the next page (the last path of this page) if there is one
+    pub(crate) next_cursor: Option<ProjectPath>,
 }
 impl FileTree {
     /// to work right you need to have the dir_path with ending with '/'
     /// returns `true` of the dir_path is in the tree otherwise `false`
-    fn in_dir(&self, dir_path: &String) -> bool {
+    fn in_dir(&self, dir_path: &str) -> bool {
         if !Self::valid_dir_path(dir_path) {
             return false;
         }
-        if dir_path == "./" {
-            return true;
-        }
-        let (files, emty_dirs) = (&self.files, &self.emty_dirs);
-        if files
-            .binary_search_by(|x| {
-                if x.starts_with(dir_path) {
-                    std::cmp::Ordering::Equal
-                } else {
-                    x.cmp(dir_path)
-                }
-            })
-            .is_ok()
-            || emty_dirs.binary_search(dir_path).is_ok()
-            || emty_dirs
-                .binary_search_by(|x| {
-                    if x.starts_with(dir_path) {
-                        std::cmp::Ordering::Equal
-                    } else {
-                        x.cmp(dir_path)
-                    }
-                })
-                .is_ok()
-        {
-            return true;
-        }
-        false
+        self.paths.is_dir(dir_path) || self.paths.in_unloaded(dir_path)
     }
     #[inline]
     fn valid_dir_path(dir_path: &str) -> bool {
@@ -60,16 +80,189 @@ impl FileTree {
             .to_string()
             + "/"
     }
-    fn err_msg(e: impl std::fmt::Display) {
+    /// log the error and return it as the reason that is sent back to the client
+    fn err_msg(e: impl std::fmt::Display) -> String {
         log::info!("{}", e);
+        e.to_string()
+    }
+    /// returns `true` if the path is inside an unloaded directory
+    fn in_unloaded_dir(&self, path: &str) -> bool {
+        self.paths.in_unloaded(path)
+    }
+    #[inline]
+    fn is_file(&self, path: &str) -> bool {
+        self.paths.is_file(path)
+    }
+    /// returns the sorted files and emty_dirs
+    pub(crate) fn get_maps(&self) -> (Vec<String>, Vec<String>) {
+        (self.paths.files(), self.paths.emty_dirs())
     }
-    /// build the tree from the files and emty_dirs
-    /// returns the files and emty_dirs
-    pub(crate) fn get_maps(&self) -> (&Vec<String>, &Vec<String>) {
-        (&self.files, &self.emty_dirs)
+    pub(crate) fn get_unloaded_dirs(&self) -> Vec<String> {
+        self.paths.unloaded_dirs()
+    }
+
+    /// what is in the path (it could be in the form of a file or a directory),
+    /// `None` if it is not in the tree
+    pub(crate) fn entry(&self, path: &ProjectPath) -> Option<Entry> {
+        let dir = path.clone().into_dir();
+        if self.paths.is_dir(&dir) {
+            return Some(Entry::Dir);
+        }
+        match self.paths.get(dir.trim_end_matches('/'))? {
+            Node::File(meta) => Some(Entry::File(meta.clone())),
+            Node::Link(target) => Some(Entry::Link(target.clone())),
+            Node::Dir(_) => None,
+        }
+    }
+
+    /// `path`, everything in it and its parents (the directories end with `/`)
+    /// it is compared with the disk to find the changes that the watcher missed
+    pub(crate) fn subtree(&self, path: &ProjectPath) -> BTreeMap<String, Entry> {
+        let mut paths: BTreeMap<String, Entry> = path
+            .ancestors()
+            .filter(|dir| self.paths.is_dir(dir))
+            .map(|dir| (dir, Entry::Dir))
+            .collect();
+        match self.entry(path) {
+            Some(Entry::Dir) => {
+                let dir = path.clone().into_dir();
+                let _ = self.paths.walk(&dir, None, &mut |path, node, _| {
+                    let entry = match node {
+                        Node::File(meta) => Entry::File(meta.clone()),
+                        Node::Dir(_) => Entry::Dir,
+                        Node::Link(target) => Entry::Link(target.clone()),
+                    };
+                    paths.insert(path, entry);
+                });
+                if !dir.is_root() {
+                    paths.insert(dir.into(), Entry::Dir);
+                }
+            }
+            Some(entry) => {
+                paths.insert(path.as_str().trim_end_matches('/').to_string(), entry);
+            }
+            None => {}
+        }
+        paths
+    }
+
+    pub(crate) fn file_entry(&self, path: &ProjectPath) -> Option<FileEntry> {
+        match self.paths.get(path)? {
+            Node::File(meta) => Some(FileEntry {
+                path: path.clone(),
+                meta: meta.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// update the metadata of a file that is in the tree
+    pub(crate) fn set_meta(&mut self, entry: FileEntry) -> Result<(), Error> {
+        let meta = self.paths.meta_mut(&entry.path).ok_or_else(|| {
+            Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not a file in the tree", entry.path),
+            )
+        })?;
+        *meta = entry.meta;
+        Ok(())
+    }
+
+    /// all the links in the tree sorted
+    pub(crate) fn links(&self) -> Vec<Link> {
+        let mut links = vec![];
+        let _ = self.paths.walk("./", None, &mut |path, node, _| {
+            if let (Node::Link(target), Ok(path)) = (node, ProjectPath::new(&path)) {
+                links.push(Link {
+                    path,
+                    target: target.clone(),
+                });
+            }
+        });
+        links
+    }
+
+    /// list the content of `dir_path` up to `depth` levels (`None` is the whole subtree)
+    /// the entries are in the order of the tree, a page starts after `cursor` (the last path
+    /// of the page before) and has `limit` entries, only these entries are walked
+    pub(crate) fn list_dir(
+        &self,
+        dir_path: &ProjectPath,
+        depth: Option<usize>,
+        cursor: Option<&ProjectPath>,
+        limit: Option<usize>,
+    ) -> Result<DirListing, Error> {
+        let dir_path = dir_path.clone().into_dir();
+        if !self.in_dir(&dir_path) {
+            return Err(Error::new(
+                io::ErrorKind::NotFound,
+                "The directory does not exist",
+            ));
+        }
+        let depth = depth.map(|d| d.max(1));
+        let mut listing = DirListing::default();
+        let mut listed = 0;
+        let mut last = None;
+        self.paths.walk_after(
+            &dir_path,
+            depth,
+            cursor.map(|cursor| cursor.as_str()),
+            &mut |path, node, level| {
+                let listed_dir = match node {
+                    Node::Dir(dir) if dir.is_empty() => Some(true),
+                    // the content of these directories is not sent
+                    Node::Dir(dir) if !dir.is_loaded() || depth == Some(level) => Some(false),
+                    Node::Dir(_) => return true,
+                    _ => None,
+                };
+                if limit.is_some_and(|limit| listed == limit) {
+                    // there is one more entry so there is another page
+                    listing.next_cursor = last.take();
+                    return false;
+                }
+                let Ok(path) = ProjectPath::new(&path) else {
+                    return true;
+                };
+                listed += 1;
+                last = Some(path.clone());
+                match (node, listed_dir) {
+                    (Node::File(meta), _) => listing.files.push(FileEntry {
+                        path,
+                        meta: meta.clone(),
+                    }),
+                    (Node::Link(target), _) => listing.links.push(Link {
+                        path,
+                        target: target.clone(),
+                    }),
+                    (_, Some(true)) => listing.emty_dirs.push(path),
+                    _ => listing.dirs.push(path),
+                }
+                true
+            },
+        )?;
+        Ok(listing)
+    }
+
+    /// keep the loaded files of a moved directory (with their history) under the new path
+    pub(crate) fn move_loaded(&mut self, dir: &str, new_dir: &str) {
+        move_keys(&mut self.tree, dir, new_dir);
+        move_keys(&mut self.views, dir, new_dir);
+        move_keys(&mut self.pending, dir, new_dir);
     }
 }
+
+/// move the entries of `map` that are in `dir` to `new_dir` (the keys are paths)
+pub(crate) fn move_keys<V>(map: &mut HashMap<String, V>, dir: &str, new_dir: &str) {
+    let moved: Vec<String> = map.keys().filter(|p| p.starts_with(dir)).cloned().collect();
+    for path in moved {
+        if let Some(value) = map.remove(&path) {
+            map.insert(format!("{new_dir}{}", &path[dir.len()..]), value);
+        }
+    }
+}
+
 pub(crate) mod buf_func;
 pub(crate) mod client_funcs;
-pub(crate) mod server_funcs;
 pub(crate) mod crdt;
+pub(crate) mod dir_tree;
+pub(crate) mod server_funcs;
@@ -2,14 +2,16 @@ use super::*;
 use std::io;
 use std::io::Result as Res;
 impl FileTree {
-
-
     pub(super) fn drop_buf(&mut self, path: String) {
         self.tree.remove(&path);
+        self.views.remove(&path);
     }
-    pub(crate) fn read_buf(&self, path: &String) -> Res<Vec<u8>> {
+    pub(crate) fn read_buf(&self, path: &str) -> Res<Vec<u8>> {
+        if let Some(view) = self.views.get(path) {
+            return Ok(view.clone());
+        }
         let file = self.tree.get(path);
-        if file.is_none() && self.files.binary_search(path).is_ok() {
+        if file.is_none() && self.is_file(path) {
             Err(Error::new(
                 io::ErrorKind::NotConnected,
                 "file is not in the memory tree",
@@ -1,31 +1,51 @@
 #![allow(private_bounds)]
 
-use crate::communication::rpc::RPC;
+use crate::communication::{
+    file_entry::{FileEntry, FileMeta},
+    project_path::ProjectPath,
+    rpc::{Link, RPC},
+};
 
 use super::*;
 use std::io::{self, Error};
-use std::path::Path;
 
 type Res<T> = io::Result<T>;
 
 trait PrivateClientFn {
     /// this opens a file and add it to the tree
     /// load the file from the Server
-    fn create_file(&mut self, filename: String) -> Res<()>; // EMTY_DIRS_OP
+    fn create_file(&mut self, filename: ProjectPath, mode: Option<u32>) -> Res<()>;
     /// move the file from old path to the new path
-    fn move_file(&mut self, old_path: String, new_path: String) -> Res<()>; //EMTY_DIRS_OP
+    fn move_file(&mut self, old_path: ProjectPath, new_path: ProjectPath) -> Res<()>;
+    /// add a copy of the file, it is not loaded
+    fn copy_file(&mut self, path: ProjectPath, new_path: ProjectPath) -> Res<()>;
+    /// add a copy of the directory, its content is listed again if it is not known
+    fn copy_dir(&mut self, path: ProjectPath, new_path: ProjectPath) -> Res<()>;
+    /// a file or a directory is back from the trash of the server, the content of a directory
+    /// is listed when it is needed
+    fn restored(&mut self, path: ProjectPath) -> Res<()>;
     /// remove the file from the tree
-    fn rm_file(&mut self, path: String) -> Res<()>; // EMTY_DIRS_OP
+    fn rm_file(&mut self, path: ProjectPath) -> Res<()>;
     /// move the directror from old path to the new path
-    fn move_dir(&mut self, old_path: String, new_path: String) -> Res<()>; // EMTY_DIRS_OP
+    fn move_dir(&mut self, old_path: ProjectPath, new_path: ProjectPath) -> Res<()>;
     /// remove the directory from the tree
-    fn rm_dir(&mut self, path: String) -> Res<()>; // EMTY_DIRS_OP
+    fn rm_dir(&mut self, path: ProjectPath) -> Res<()>;
     /// make a new directory in the tree
-    fn make_dir(&mut self, path: String) -> Res<()>; // EMTY_DIRS_OP
+    fn make_dir(&mut self, path: ProjectPath) -> Res<()>;
+    /// add a page of the content of a directory to the tree
+    fn load_dir(
+        &mut self,
+        path: ProjectPath,
+        files: Vec<FileEntry>,
+        emty_dirs: Vec<ProjectPath>,
+        dirs: Vec<ProjectPath>,
+        links: Vec<Link>,
+        last_page: bool,
+    );
 
     fn update_buf(
         &mut self,
-        path: String,
+        path: ProjectPath,
         changes: &[u8],
         old_head_idx: usize,
         heads: &[Vec<[u8; 32]>],
@@ -33,316 +53,327 @@ trait PrivateClientFn {
 }
 
 pub trait PubClientFn: PrivateClientFn {
-    fn build_tree(files: Vec<String>, emty_dirs: Vec<String>) -> Self;
+    fn build_tree(
+        files: Vec<FileEntry>,
+        emty_dirs: Vec<ProjectPath>,
+        dirs: Vec<ProjectPath>,
+        links: Vec<Link>,
+    ) -> Self;
     fn handle_msg(&mut self, tx: RPC);
+    /// apply a change of the file tree that is not accepted by the server yet
+    fn apply(&mut self, rpc: RPC) -> Res<()>;
+    /// undo a change that is applied with `apply` and rejected by the server
+    /// the content of directories can't be restored so they are listed again when needed
+    /// and the files that were open should be opened again
+    fn rollback(&mut self, rpc: RPC) -> Res<()>;
 }
 
 impl PrivateClientFn for FileTree {
     /// add a file to FileTree
-
-    fn create_file(&mut self, path: String) -> Res<()> {
+    fn create_file(&mut self, path: ProjectPath, mode: Option<u32>) -> Res<()> {
+        let path = path.into_file()?;
         // you should have a message
-        let parrent_path = Path::new(&path)
-            .parent()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string()
-            + "/";
-
+        let parrent_path = Self::parent_dir(&path);
         if !self.in_dir(&parrent_path) {
             return Err(Error::new(
                 io::ErrorKind::NotFound,
                 "The directory does not exist",
             ));
         }
-
-        let (files, emty_dir) = (&mut self.files, &mut self.emty_dirs);
-        match emty_dir.binary_search(&parrent_path) {
-            Ok(_) => (),
-            Err(i) => emty_dir.insert(i, parrent_path),
-        }
-
-        match files.binary_search(&path) {
-            Ok(_) => Err(Error::new(
+        if self.paths.get(&path).is_some() {
+            return Err(Error::new(
                 io::ErrorKind::AlreadyExists,
                 "The file already exists",
-            )),
-            Err(i) => {
-                files.insert(i, path);
-                Ok(())
-            }
+            ));
         }
+        self.paths
+            .insert(&path, Node::File(FileMeta::with_mode(mode)), false)
     }
-    fn move_file(&mut self, old_path: String, new_path: String) -> Res<()> {
-        // you know borrow checker
-        let files = &self.files;
-        let old_index = match files.binary_search(&old_path) {
-            Err(_) => return Err(Error::new(io::ErrorKind::NotFound, "file not found")),
-            Ok(old_index) => old_index,
-        };
-
-        let new_dir_path = Path::new(&new_path)
-            .parent()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string()
-            + "/"; // no need to check old path parent
-        let old_dir_path = Path::new(&old_path)
-            .parent()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string()
-            + "/";
+    fn move_file(&mut self, old_path: ProjectPath, new_path: ProjectPath) -> Res<()> {
+        let new_path = new_path.into_file()?;
+        if !self.is_file(&old_path) {
+            return Err(Error::new(io::ErrorKind::NotFound, "file not found"));
+        }
+        let new_dir_path = Self::parent_dir(&new_path); // no need to check old path parent
         if !self.in_dir(&new_dir_path) {
             return Err(Error::new(
                 io::ErrorKind::NotFound,
                 "The directory does not exist",
             ));
         }
-        let files = &mut self.files;
-
-        files.remove(old_index);
-        match files.binary_search(&new_path) {
-            Ok(_) => {
-                return Err(Error::new(
-                    io::ErrorKind::AlreadyExists,
-                    "file path already exists",
-                ))
-            }
-            Err(i) => files.insert(i, new_path),
+        if self.paths.get(&new_path).is_some() {
+            return Err(Error::new(
+                io::ErrorKind::AlreadyExists,
+                "file path already exists",
+            ));
         }
+        self.paths.rename(&old_path, &new_path, false)?;
 
-        match self.emty_dirs.binary_search(&new_dir_path) {
-            Err(_) => (),
-            Ok(i) => {
-                self.emty_dirs.remove(i);
-            }
+        // the open file is kept in the new path (like `move_loaded` does for a directory)
+        if let Some(crdt) = self.tree.remove(old_path.as_str()) {
+            self.tree.insert(new_path.to_string(), crdt);
         }
-        if !self.in_dir(&old_dir_path) {
-            let emty_dirs = &mut self.emty_dirs;
-            match emty_dirs.binary_search(&old_dir_path) {
-                Ok(_) => unreachable!(),
-                Err(i) => emty_dirs.insert(i, old_dir_path),
-            }
+        if let Some(view) = self.views.remove(old_path.as_str()) {
+            self.views.insert(new_path.to_string(), view);
+        }
+        if let Some(chunks) = self.pending.remove(old_path.as_str()) {
+            self.pending.insert(new_path.to_string(), chunks);
         }
-
-        self.tree.remove(&old_path);
         Ok(())
     }
-    fn move_dir(&mut self, old_path: String, new_path: String) -> Res<()> {
-        if !(Self::valid_dir_path(&new_path) && Self::valid_dir_path(&old_path)) {
-            return Err(Error::new(
-                io::ErrorKind::InvalidInput,
-                "The path should start with './' and end with '/'",
-            ));
-        }
+    fn move_dir(&mut self, old_path: ProjectPath, new_path: ProjectPath) -> Res<()> {
+        let (old_path, new_path) = (old_path.into_dir(), new_path.into_dir());
         if !self.in_dir(&old_path) {
             return Err(Error::new(
                 io::ErrorKind::NotFound,
                 "The old directory does not exist",
             ));
         }
-        if self.in_dir(&new_path) {
+        if self.in_dir(&new_path) || self.paths.get(&new_path).is_some() {
             return Err(Error::new(
                 io::ErrorKind::AlreadyExists,
                 "The new directory does exist",
             ));
         }
-        let (files, emty_dirs) = (&mut self.files, &mut self.emty_dirs);
-        if let Ok(i) = emty_dirs.binary_search(&old_path) {
-            // EMTY_DIRS_OP
-            emty_dirs.remove(i);
-            match emty_dirs.binary_search(&new_path) {
-                Ok(_) => unreachable!(),
-                Err(i) => {
-                    self.emty_dirs.insert(i, new_path);
-                }
-            }
-            return Ok(());
-        }
-
-        let start = files.binary_search(&old_path).unwrap_err();
-
-        // check if the directory is empty
-        let mut r = files.len();
-        let mut l = start;
-        // binary search for the end of the directory
-        while l < r {
-            let mid = l + (r - l) / 2;
-            if files[mid].starts_with(&old_path) {
-                l = mid + 1;
-            } else {
-                r = mid;
-            }
-        }
-        let end = r;
-
-        let new_files: Vec<String> = files
-            .drain(start..end)
-            .map(|s| {
-                self.tree.remove(&s);
-                s.replacen(&old_path, &new_path, 1)
-            }) // replacen is a must
-            // make sure to replace the old_path with the new_path for the frist string
-            .collect::<Vec<String>>();
-
-        match files.binary_search(&new_path) {
-            Ok(_) => unreachable!(),
-            Err(i) => {
-                let tail = files.split_off(i);
-                files.extend(new_files);
-                files.extend(tail);
-            }
-        };
-
+        self.paths.rename(&old_path, &new_path, true)?;
+        self.move_loaded(&old_path, &new_path);
         Ok(())
     }
-    fn rm_dir(&mut self, path: String) -> Res<()> {
-        if !self.in_dir(&path) {
+    fn copy_file(&mut self, path: ProjectPath, new_path: ProjectPath) -> Res<()> {
+        let new_path = new_path.into_file()?;
+        if !self.in_dir(&Self::parent_dir(&new_path)) {
             return Err(Error::new(
                 io::ErrorKind::NotFound,
                 "The directory does not exist",
             ));
         }
-        let (files, emty_dirs) = (&mut self.files, &mut self.emty_dirs);
-        let parent_dir = Self::parent_dir(&path);
-
-        if let Ok(i) = emty_dirs.binary_search(&path) {
-            // EMTY_DIRS_OP
-            emty_dirs.remove(i);
-            if !self.in_dir(&parent_dir) {
-                match self.emty_dirs.binary_search(&parent_dir) {
-                    Ok(_) => {}
-                    Err(i) => {
-                        self.emty_dirs.insert(i, parent_dir);
-                    }
-                }
-            }
-            return Ok(());
+        if self.paths.get(&new_path).is_some() {
+            return Err(Error::new(
+                io::ErrorKind::AlreadyExists,
+                "file path already exists",
+            ));
         }
-
-        let start = files.binary_search(&path).unwrap_err();
-
-        // check if the directory is empty
-        let mut r = files.len();
-        let mut l = start;
-        // binary search for the end of the directory
-        while l < r {
-            let mid = (l + r) / 2;
-            if files[mid].starts_with(&path) {
-                l = mid + 1;
-            } else {
-                r = mid;
-            }
+        // the copied file could be in a directory that is not loaded
+        let meta = match self.paths.get(&path) {
+            Some(Node::File(meta)) => meta.clone(),
+            _ => FileMeta::default(),
+        };
+        self.paths.insert(&new_path, Node::File(meta), false)
+    }
+    fn restored(&mut self, path: ProjectPath) -> Res<()> {
+        if !path.is_dir() {
+            return self.create_file(path, None);
         }
-        let end = r;
-
-        files.drain(start..end).for_each(|s| {
-            self.tree.remove(&s);
-            drop(s);
-        });
-
-        if !self.in_dir(&parent_dir) {
-            match self.emty_dirs.binary_search(&parent_dir) {
-                Ok(_) => unreachable!(),
-                Err(i) => {
-                    self.emty_dirs.insert(i, parent_dir);
-                }
-            }
+        if self.paths.get(&path).is_some() {
+            return Err(Error::new(
+                io::ErrorKind::AlreadyExists,
+                "The directory already exists",
+            ));
         }
-
-        Ok(())
+        self.paths.make_dir(&path, false)
     }
-    fn make_dir(&mut self, path: String) -> Res<()> {
-        if !Self::valid_dir_path(&path) {
+    fn copy_dir(&mut self, path: ProjectPath, new_path: ProjectPath) -> Res<()> {
+        let (path, new_path) = (path.into_dir(), new_path.into_dir());
+        if self.in_dir(&new_path) || self.paths.get(&new_path).is_some() {
             return Err(Error::new(
-                io::ErrorKind::InvalidInput,
-                "The path should start with './' and end with '/'",
+                io::ErrorKind::AlreadyExists,
+                "The new directory does exist",
+            ));
+        }
+        match self.paths.get(&path) {
+            Some(Node::Dir(dir)) if dir.is_loaded() => self.paths.copy(&path, &new_path, true),
+            _ => self.paths.make_dir(&new_path, false),
+        }
+    }
+    fn rm_dir(&mut self, path: ProjectPath) -> Res<()> {
+        let path = path.into_dir();
+        if !self.in_dir(&path) {
+            return Err(Error::new(
+                io::ErrorKind::NotFound,
+                "The directory does not exist",
             ));
         }
+        self.paths.remove(&path)?;
+        self.tree.retain(|file, _| !file.starts_with(path.as_str()));
+        self.views
+            .retain(|file, _| !file.starts_with(path.as_str()));
+        Ok(())
+    }
+    fn make_dir(&mut self, path: ProjectPath) -> Res<()> {
+        let path = path.into_dir();
         if self.in_dir(&path) {
             return Err(Error::new(
                 io::ErrorKind::AlreadyExists,
                 "The directory already exists",
             ));
         }
-        if let Ok(i) = self.emty_dirs.binary_search_by(|x| {
-            if path.starts_with(x) {
-                std::cmp::Ordering::Equal
-            } else {
-                x.cmp(&path)
-            }
-        }) {
-            self.emty_dirs.remove(i);
-        }
-
-        // path in an emty_directory
-        match self.emty_dirs.binary_search(&path) {
-            Ok(_) => unreachable!(),
-            Err(i) => {
-                self.emty_dirs.insert(i, path.clone());
-            }
-        }
-
-        Ok(())
+        self.paths.make_dir(&path, true)
     }
     fn update_buf(
         &mut self,
-        path: String,
+        path: ProjectPath,
         changes: &[u8],
         old_head_idx: usize,
         heads: &[Vec<[u8; 32]>],
     ) -> Res<()> {
-        if self.files.binary_search(&path).is_err() {
+        if !self.is_file(&path) {
             return Err(Error::new(io::ErrorKind::NotFound, "File Not Found"));
         }
-        if let Some(file) = self.tree.get_mut(&path) {
+        if let Some(file) = self.tree.get_mut(path.as_str()) {
             let _ = file.update(changes, old_head_idx, heads);
         }
         Ok(()) // here there is no error that is not the case in client
     }
-    fn rm_file(&mut self, path: String) -> Res<()> {
-        let files = &mut self.files;
-        if let Ok(i) = files.binary_search(&path) {
-            let path = self.files.remove(i);
-            let dir_path = Self::parent_dir(&path);
-            if !self.in_dir(&dir_path) {
-                // EMTY_DIRS_OP
-                match self.emty_dirs.binary_search(&dir_path) {
-                    Ok(_) => {
-                        unreachable!()
-                    }
-                    Err(i) => {
-                        self.emty_dirs.insert(i, dir_path);
-                    }
-                }
-            }
-            self.tree.remove(&path);
-            Ok(())
-        } else {
-            Err(Error::new(
+    fn load_dir(
+        &mut self,
+        path: ProjectPath,
+        files: Vec<FileEntry>,
+        emty_dirs: Vec<ProjectPath>,
+        dirs: Vec<ProjectPath>,
+        links: Vec<Link>,
+        last_page: bool,
+    ) {
+        let path = path.into_dir();
+        // the directory stays unloaded until the last page
+        let _ = self.paths.make_dir(&path, false);
+        for file in files {
+            let _ = self.paths.insert(&file.path, Node::File(file.meta), true);
+        }
+        for dir in emty_dirs {
+            let _ = self.paths.make_dir(&dir, true);
+        }
+        for dir in dirs {
+            let _ = self.paths.make_dir(&dir, false); // does nothing if it is already loaded
+        }
+        for link in links {
+            let _ = self.paths.insert(&link.path, Node::Link(link.target), true);
+        }
+        if last_page {
+            self.paths.set_loaded(&path, true);
+        }
+    }
+    /// the links are deleted like the files
+    fn rm_file(&mut self, path: ProjectPath) -> Res<()> {
+        if !self.is_file(&path) && !self.paths.is_link(&path) {
+            return Err(Error::new(
                 io::ErrorKind::NotFound,
                 "The file does not exist",
-            ))
+            ));
         }
+        self.paths.remove(&path)?;
+        self.tree.remove(path.as_str());
+        self.views.remove(path.as_str());
+        Ok(())
     }
 }
 
 impl PubClientFn for FileTree {
-    fn build_tree(mut files: Vec<String>, mut emty_dirs: Vec<String>) -> Self {
-        files.sort_unstable();
-        emty_dirs.sort_unstable();
+    fn apply(&mut self, rpc: RPC) -> Res<()> {
+        match rpc {
+            RPC::CreateFile { path, mode, .. } => self.create_file(path, mode),
+            RPC::CopyFile { path, new_path } => self.copy_file(path, new_path),
+            RPC::CopyDirectory { path, new_path } => self.copy_dir(path, new_path),
+            RPC::Restore { path } => self.restored(path),
+            RPC::DeleteFile { path } => self.rm_file(path),
+            RPC::MoveFile { path, new_path } => self.move_file(path, new_path),
+            RPC::CreateDirectory { path } => self.make_dir(path),
+            RPC::DeleteDirectory { path } => self.rm_dir(path),
+            RPC::MoveDirectory { path, new_path } => self.move_dir(path, new_path),
+            rpc => Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{rpc:?} is not a change of the file tree"),
+            )),
+        }
+    }
+    fn rollback(&mut self, rpc: RPC) -> Res<()> {
+        match rpc {
+            RPC::CreateFile { path, .. } => self.rm_file(path),
+            RPC::DeleteFile { path } => self.create_file(path, None),
+            RPC::MoveFile { path, new_path } => self.move_file(new_path, path),
+            RPC::CopyFile { new_path, .. } => self.rm_file(new_path),
+            RPC::CopyDirectory { new_path, .. } => self.rm_dir(new_path),
+            RPC::Restore { path } if path.is_dir() => self.rm_dir(path),
+            RPC::Restore { path } => self.rm_file(path),
+            RPC::CreateDirectory { path } => self.rm_dir(path),
+            RPC::DeleteDirectory { path } => self.paths.make_dir(&path.into_dir(), false),
+            RPC::MoveDirectory { path, new_path } => self.move_dir(new_path, path),
+            _ => Ok(()),
+        }
+    }
+
+    fn build_tree(
+        files: Vec<FileEntry>,
+        emty_dirs: Vec<ProjectPath>,
+        dirs: Vec<ProjectPath>,
+        links: Vec<Link>,
+    ) -> Self {
+        let mut paths =
+            DirTree::from_paths(vec![], emty_dirs.into_iter().map(String::from).collect());
+        for file in files {
+            let _ = paths.insert(&file.path, Node::File(file.meta), true);
+        }
+        for dir in dirs {
+            let _ = paths.make_dir(&dir, false);
+        }
+        for link in links {
+            let _ = paths.insert(&link.path, Node::Link(link.target), true);
+        }
         FileTree {
-            files,
-            emty_dirs,
+            paths,
             tree: HashMap::new(),
+            views: HashMap::new(),
+            pending: HashMap::new(),
+            loading: HashMap::new(),
+            trash: Trash::default(),
         }
     }
     fn handle_msg(&mut self, rpc: RPC) {
         match rpc {
+            // the content of unloaded directories will be fetched when they are listed
+            RPC::EditBuffer { ref path, .. }
+            | RPC::CreateFile { ref path, .. }
+            | RPC::DeleteFile { ref path }
+            | RPC::CreateDirectory { ref path }
+            | RPC::DeleteDirectory { ref path }
+            | RPC::CreateLink { ref path, .. }
+                if self.in_unloaded_dir(path) => {}
+            RPC::UpdateMetadata { ref entry } if self.in_unloaded_dir(&entry.path) => {}
+            RPC::CopyFile { ref new_path, .. }
+            | RPC::CopyDirectory { ref new_path, .. }
+            | RPC::Restore { path: ref new_path }
+                if self.in_unloaded_dir(new_path) => {}
+            RPC::MoveFile { path, new_path }
+                if self.in_unloaded_dir(&path) || self.in_unloaded_dir(&new_path) =>
+            {
+                match (self.in_unloaded_dir(&path), self.in_unloaded_dir(&new_path)) {
+                    (true, false) => self.create_file(new_path, None),
+                    (false, true) => self.rm_file(path),
+                    _ => Ok(()),
+                }
+                .unwrap_or_else(|e| log::error!("{}", e));
+            }
+            RPC::MoveDirectory { path, new_path }
+                if self.in_unloaded_dir(&path) || self.in_unloaded_dir(&new_path) =>
+            {
+                match (self.in_unloaded_dir(&path), self.in_unloaded_dir(&new_path)) {
+                    (true, false) if self.in_dir(&new_path.parent()) => {
+                        self.paths.make_dir(&new_path.into_dir(), false)
+                    }
+                    (false, true) => self.rm_dir(path),
+                    _ => Ok(()),
+                }
+                .unwrap_or_else(|e| log::error!("{}", e));
+            }
+            RPC::ResListDir {
+                path,
+                files,
+                emty_dirs,
+                dirs,
+                links,
+                next_cursor,
+            } => {
+                self.load_dir(path, files, emty_dirs, dirs, links, next_cursor.is_none());
+            }
             RPC::EditBuffer {
                 path,
                 changes,
@@ -352,14 +383,25 @@ impl PubClientFn for FileTree {
                 self.update_buf(path, changes.as_slice(), old_head_idx, heads.as_slice())
                     .unwrap_or_else(|e| log::error!("{}", e));
             }
-            RPC::CreateFile { path } => {
-                self.create_file(path)
+            RPC::CreateFile { path, mode, .. } => {
+                self.create_file(path, mode)
                     .unwrap_or_else(|e| log::error!("{}", e));
             }
             RPC::MoveFile { path, new_path } => {
                 self.move_file(path, new_path)
                     .unwrap_or_else(|e| log::error!("{}", e));
             }
+            RPC::CopyFile { path, new_path } => {
+                self.copy_file(path, new_path)
+                    .unwrap_or_else(|e| log::error!("{}", e));
+            }
+            RPC::CopyDirectory { path, new_path } => {
+                self.copy_dir(path, new_path)
+                    .unwrap_or_else(|e| log::error!("{}", e));
+            }
+            RPC::Restore { path } => {
+                self.restored(path).unwrap_or_else(|e| log::error!("{}", e));
+            }
             RPC::DeleteFile { path } => {
                 self.rm_file(path).unwrap_or_else(|e| log::error!("{}", e));
             }
@@ -374,48 +416,81 @@ impl PubClientFn for FileTree {
                 self.rm_dir(path).unwrap_or_else(|e| log::error!("{}", e));
             }
             RPC::Undo { path } => {
-                if let Some(crdt) = self.tree.get_mut(&path) {
+                if let Some(crdt) = self.tree.get_mut(path.as_str()) {
                     crdt.undo();
                 }
             }
             RPC::Redo { path } => {
-                if let Some(crdt) = self.tree.get_mut(&path) {
+                if let Some(crdt) = self.tree.get_mut(path.as_str()) {
                     crdt.redo();
                 }
             }
+            RPC::CreateLink { path, target } => {
+                self.paths
+                    .insert(&path, Node::Link(target), false)
+                    .unwrap_or_else(|e| log::error!("{}", e));
+            }
+            RPC::UpdateMetadata { entry } => {
+                self.set_meta(entry)
+                    .unwrap_or_else(|e| log::error!("{}", e));
+            }
             RPC::FileSaved { .. } => {
                 // todo
                 // should call the api to remove the dirty
                 // bit
             }
 
+            RPC::ResFileChunk {
+                path, offset, data, ..
+            } => {
+                let buf = self.pending.entry(path.into()).or_default();
+                if offset == 0 {
+                    buf.clear(); // the file is sent again
+                }
+                if buf.len() as u64 == offset {
+                    buf.extend_from_slice(&data);
+                } else {
+                    log::error!("a chunk of the file is missing");
+                }
+            }
             RPC::ResSendFile {
                 path,
                 file,
                 heads_history,
                 head_idx,
+                view_only,
+                chunked,
+                ..
             } => {
-                self.tree
-                    .insert(path, Crdt::new(file, heads_history, head_idx));
-            }
-            #[allow(unused_variables)]
-            RPC::ResMoveCursor {
-                username,
-                path,
-                position,
-            } => todo!(),
-
-            #[allow(unused_variables)]
-            RPC::ResMark {
-                path,
-                s_position,
-                e_position,
-                username,
-            } => {
-                todo!() // should call the api of user
+                // the header of a chunked file has the changes after the chunks
+                let file = if chunked {
+                    let mut chunks = self.pending.remove(path.as_str()).unwrap_or_default();
+                    chunks.extend(file);
+                    chunks
+                } else {
+                    file
+                };
+                if view_only {
+                    self.views.insert(path.into(), file);
+                } else {
+                    match Crdt::new(file, heads_history, head_idx) {
+                        Ok(crdt) => {
+                            self.tree.insert(path.into(), crdt);
+                        }
+                        Err(e) => log::error!("{}", e),
+                    }
+                }
             }
+            // these are sent to the editor as events
+            RPC::ResMoveCursor { .. }
+            | RPC::ResMark { .. }
+            | RPC::UserJoined { .. }
+            | RPC::UserLeft { .. } => {}
 
             m => log::error!("Invalid RPC message {m:?}"),
         }
     }
 }
+
+#[cfg(test)]
+mod client_tests;
@@ -0,0 +1,147 @@
+use super::*;
+use automerge::transaction::Transactable as _;
+
+fn path(path: &str) -> ProjectPath {
+    ProjectPath::new(path).unwrap()
+}
+
+fn tree() -> FileTree {
+    FileTree::build_tree(
+        vec![path("./file.txt").into(), path("./large.log").into()],
+        vec![],
+        vec![],
+        vec![],
+    )
+}
+
+/// the chunks like the server sends them
+fn chunks(path_name: &str, content: &[u8], size: usize) -> Vec<RPC> {
+    content
+        .chunks(size)
+        .enumerate()
+        .map(|(i, data)| RPC::ResFileChunk {
+            path: path(path_name),
+            offset: (i * size) as u64,
+            total: content.len() as u64,
+            data: data.to_vec(),
+        })
+        .collect()
+}
+
+#[test]
+fn receive_chunked_file() {
+    let mut buf = automerge::Automerge::new();
+    let mut tx = buf.transaction();
+    let id = tx
+        .put_object(automerge::ROOT, "content", automerge::ObjType::Text)
+        .unwrap();
+    tx.splice_text(id, 0, 0, "hello world").unwrap();
+    tx.commit();
+    let (file, heads_history, head_idx) = Crdt::open(buf).save();
+
+    let mut ft = tree();
+    for rpc in chunks("./file.txt", &file, 7) {
+        ft.handle_msg(rpc);
+    }
+    assert!(ft.read_buf("./file.txt").is_err()); // not yet
+    ft.handle_msg(RPC::ResSendFile {
+        path: path("./file.txt"),
+        file: vec![],
+        heads_history,
+        head_idx,
+        binary: false,
+        view_only: false,
+        chunked: true,
+    });
+    assert_eq!(ft.read_buf("./file.txt").unwrap(), b"hello world");
+    assert!(ft.pending.is_empty());
+}
+
+#[test]
+fn receive_view_only_file() {
+    let content = b"a line of a large log file\n".repeat(10);
+    let mut ft = tree();
+    for rpc in chunks("./large.log", &content, 16) {
+        ft.handle_msg(rpc);
+    }
+    ft.handle_msg(RPC::ResSendFile {
+        path: path("./large.log"),
+        file: vec![],
+        heads_history: vec![],
+        head_idx: 0,
+        binary: false,
+        view_only: true,
+        chunked: true,
+    });
+    assert_eq!(ft.read_buf("./large.log").unwrap(), content);
+    assert!(!ft.tree.contains_key("./large.log"));
+
+    ft.handle_msg(RPC::DeleteFile {
+        path: path("./large.log"),
+    });
+    assert!(ft.views.is_empty());
+}
+
+#[test]
+fn missing_chunk_is_not_loaded() {
+    let mut ft = tree();
+    let mut chunks = chunks("./large.log", &[1; 64], 16);
+    chunks.remove(1);
+    for rpc in chunks {
+        ft.handle_msg(rpc);
+    }
+    assert_eq!(ft.pending["./large.log"].len(), 16);
+}
+
+#[test]
+fn copy_loaded_and_unloaded_dirs() {
+    let mut ft = FileTree::build_tree(
+        vec![path("./src/a.rs").into()],
+        vec![],
+        vec![path("./docs/")],
+        vec![],
+    );
+    ft.handle_msg(RPC::CopyDirectory {
+        path: path("./src/"),
+        new_path: path("./lib/"),
+    });
+    ft.handle_msg(RPC::CopyDirectory {
+        path: path("./docs/"),
+        new_path: path("./guide/"),
+    });
+    assert!(ft.is_file("./lib/a.rs"));
+    // the content of the copy is listed when it is needed
+    assert_eq!(ft.get_unloaded_dirs(), ["./docs/", "./guide/"]);
+
+    ft.handle_msg(RPC::CopyFile {
+        path: path("./docs/b.md"),
+        new_path: path("./b.md"),
+    });
+    ft.handle_msg(RPC::CopyFile {
+        path: path("./src/a.rs"),
+        new_path: path("./docs/a.rs"),
+    });
+    assert!(ft.is_file("./b.md"));
+    assert!(!ft.is_file("./docs/a.rs"));
+}
+
+#[test]
+fn move_dir_keeps_open_files() {
+    let mut ft = FileTree::build_tree(
+        vec![path("./src/a.rs").into(), path("./src/b/c.rs").into()],
+        vec![],
+        vec![],
+        vec![],
+    );
+    for file in ["./src/a.rs", "./src/b/c.rs"] {
+        let buf = automerge::Automerge::new();
+        ft.tree.insert(file.to_string(), Crdt::open(buf));
+    }
+    ft.handle_msg(RPC::MoveDirectory {
+        path: path("./src/"),
+        new_path: path("./lib/src/"),
+    });
+    let mut open: Vec<&String> = ft.tree.keys().collect();
+    open.sort();
+    assert_eq!(open, ["./lib/src/a.rs", "./lib/src/b/c.rs"]);
+}
@@ -1,13 +1,81 @@
+use std::fmt;
 use std::io::{self, Error};
 
+use crate::communication::{
+    event::{splice, Splice},
+    permissions::Priviledge,
+};
 use automerge::{
-    transaction::Transactable as _, Automerge, ChangeHash, ObjType, ReadDoc as _, Value, ROOT,
+    patches::TextRepresentation, transaction::Transactable as _, Automerge, ChangeHash, ObjType,
+    PatchAction, ReadDoc as _, ScalarValue, Value, ROOT,
 };
+
+/// the biggest binary file that could be sent with `replace_bin`
+pub const MAX_BIN_SIZE: usize = 8 * 1024 * 1024;
+
+/// the errors of editing a buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditError {
+    /// the file is not loaded (it should be read first)
+    NotLoaded,
+    /// text edits can't be done on a binary file, use `replace_bin` instead
+    Binary,
+    /// the file is too large to be edited
+    ViewOnly,
+    /// the content is bigger than `max`
+    TooLarge { size: usize, max: usize },
+    /// the role of the user for the file is lower than `needed`
+    PermissionDenied { needed: Priviledge },
+    /// the edit could not be applied (wrong position or a corrupted document)
+    Invalid(String),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::NotLoaded => write!(f, "the file is not loaded"),
+            EditError::Binary => write!(f, "the file is binary, it can only be replaced"),
+            EditError::ViewOnly => write!(f, "the file is too large to be edited"),
+            EditError::TooLarge { size, max } => {
+                write!(
+                    f,
+                    "the content is too large ({size} bytes, the limit is {max})"
+                )
+            }
+            EditError::Invalid(e) => write!(f, "invalid edit: {e}"),
+            EditError::PermissionDenied { needed } => {
+                write!(f, "permission denied, {needed:?} is needed")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+impl From<EditError> for io::Error {
+    fn from(e: EditError) -> Self {
+        let kind = match e {
+            EditError::NotLoaded => io::ErrorKind::NotConnected,
+            EditError::Binary | EditError::Invalid(_) => io::ErrorKind::InvalidData,
+            EditError::TooLarge { .. } => io::ErrorKind::InvalidInput,
+            EditError::ViewOnly => io::ErrorKind::Unsupported,
+            EditError::PermissionDenied { .. } => io::ErrorKind::PermissionDenied,
+        };
+        Error::new(kind, e)
+    }
+}
+
+/// the output of an edit that should be sent to the others:
+/// the changes, the old head index and the new heads
+pub(crate) type Edit = (Vec<u8>, usize, Vec<Vec<[u8; 32]>>);
+
 #[derive(Debug, Clone)]
 pub(crate) struct Crdt {
     automerge: Automerge,
     heads_history: Vec<Vec<ChangeHash>>,
     head_idx: usize,
+    /// the heads of the content that is on the disk
+    saved: Vec<ChangeHash>,
 }
 
 impl Crdt {
@@ -16,6 +84,7 @@ impl Crdt {
     pub(crate) fn open(automerge: Automerge) -> Self {
         let heads_history = vec![automerge.get_heads()];
         Self {
+            saved: heads_history[0].clone(),
             automerge,
             heads_history,
             head_idx: 0,
@@ -25,20 +94,29 @@ impl Crdt {
     fn bytes_to_head_history(bytes: Vec<Vec<[u8; 32]>>) -> Vec<Vec<ChangeHash>> {
         bytes
             .into_iter()
-            .map(|x| x.into_iter().map(|y| ChangeHash(y)).collect())
+            .map(|x| x.into_iter().map(ChangeHash).collect())
             .collect()
     }
+    /// load a crdt that was sent by the server
     pub(crate) fn new(
         automerge: Vec<u8>,
         heads_history: Vec<Vec<[u8; 32]>>,
         head_idx: usize,
-    ) -> Self {
+    ) -> Result<Self, Error> {
         let heads_history = Self::bytes_to_head_history(heads_history);
-        Self {
-            automerge: Automerge::load(automerge.as_slice()).unwrap(),
+        if head_idx >= heads_history.len() {
+            return Err(Error::new(
+                io::ErrorKind::InvalidData,
+                "head_idx is out of bounds",
+            ));
+        }
+        Ok(Self {
+            automerge: Automerge::load(automerge.as_slice())
+                .map_err(|e| Error::new(io::ErrorKind::InvalidData, e))?,
+            saved: heads_history[head_idx].clone(),
             head_idx,
             heads_history,
-        }
+        })
     }
     /// this will update the crdt with the change
     /// this also takes into account the undo and redo
@@ -47,22 +125,33 @@ impl Crdt {
         changes: &[u8],
         old_head_idx: usize,
         new_heads: &[Vec<[u8; 32]>],
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        if !old_head_idx < self.heads_history.len() {
-            return Err(Box::new(Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "old_head_idx is out of bounds",
-            )));
+    ) -> Result<(), EditError> {
+        if old_head_idx >= self.heads_history.len() {
+            return Err(EditError::Invalid(
+                "old_head_idx is out of bounds".to_string(),
+            ));
         }
+        let invalid = |e: automerge::AutomergeError| EditError::Invalid(e.to_string());
 
         let new_heads = Self::bytes_to_head_history(new_heads.to_vec());
         let new_heads = new_heads.as_slice();
         // get the last common head after that there is changes
         let fork_head = self.heads_history[old_head_idx].as_slice();
 
-        self.automerge = self.automerge.fork_at(fork_head)?;
-
-        (&mut self.automerge).load_incremental(changes)?;
+        let mut automerge = self.automerge.fork_at(fork_head).map_err(invalid)?;
+        automerge.load_incremental(changes).map_err(invalid)?;
+        // the size of binary files is checked as they are sent as a whole
+        if let Ok(Some((Value::Scalar(val), _))) = automerge.get(ROOT, Self::CONTENT) {
+            if let ScalarValue::Bytes(bytes) = val.as_ref() {
+                if bytes.len() > MAX_BIN_SIZE {
+                    return Err(EditError::TooLarge {
+                        size: bytes.len(),
+                        max: MAX_BIN_SIZE,
+                    });
+                }
+            }
+        }
+        self.automerge = automerge;
 
         self.heads_history = [&self.heads_history[..=old_head_idx], new_heads]
             .concat()
@@ -94,30 +183,29 @@ impl Crdt {
                 "head_idx is out of bounds",
             ));
         }
+        self.read_at(&self.heads_history[self.head_idx])
+    }
+
+    /// the content that is on the disk (without the changes that are not saved)
+    /// this fails if the saved heads are dropped by an edit after an undo
+    pub(crate) fn read_saved(&self) -> Result<Vec<u8>, io::Error> {
+        self.read_at(&self.saved)
+    }
+
+    fn read_at(&self, heads: &[ChangeHash]) -> Result<Vec<u8>, io::Error> {
         let buf = &self.automerge;
-        match buf.get_at(
-            ROOT,
-            Self::CONTENT,
-            self.heads_history[self.head_idx].as_slice(),
-        ) {
-            Ok(content) => {
-                let (val, id) = content.unwrap();
-                if val.is_object() && matches!(val, Value::Object(ObjType::Text)) {
-                    Ok(buf
-                        .text_at(id, self.heads_history[self.head_idx].as_slice())
-                        .unwrap()
-                        .as_bytes()
-                        .to_vec())
-                } else if val.is_bytes() {
-                    Ok(val.to_bytes().unwrap().to_vec()) // there is some issue there
-                                                         // Ok(vec![])
-                } else {
-                    Err(Error::new(
-                        io::ErrorKind::InvalidData,
-                        "The file could be corrupted",
-                    ))
-                }
+        match buf.get_at(ROOT, Self::CONTENT, heads) {
+            Ok(Some((Value::Object(ObjType::Text), id))) => buf
+                .text_at(id, heads)
+                .map(|text| text.into_bytes())
+                .map_err(|e| Error::new(io::ErrorKind::InvalidData, e)),
+            Ok(Some((Value::Scalar(val), _))) if val.is_bytes() => {
+                Ok(val.into_owned().into_bytes().unwrap_or_default())
             }
+            Ok(_) => Err(Error::new(
+                io::ErrorKind::InvalidData,
+                "The file could be corrupted",
+            )),
             Err(_) => Err(Error::new(
                 io::ErrorKind::InvalidData,
                 "The file is corrupted",
@@ -125,28 +213,103 @@ impl Crdt {
         }
     }
 
+    /// `true` if the content is the same as the one on the disk
+    pub(crate) fn is_clean(&self) -> bool {
+        self.heads_history[self.head_idx] == self.saved
+    }
+
+    /// the current content is written to the disk
+    pub(crate) fn mark_saved(&mut self) {
+        self.saved = self.heads_history[self.head_idx].clone();
+    }
+
+    /// `true` if the content is binary (at the current head)
+    pub(crate) fn is_binary(&self) -> bool {
+        matches!(
+            self.automerge
+                .get_at(ROOT, Self::CONTENT, self.heads_history[self.head_idx].as_slice()),
+            Ok(Some((Value::Scalar(val), _))) if val.is_bytes()
+        )
+    }
+
+    /// go back to the current head if there was an undo
+    fn fork_at_head(&mut self) -> Result<(), EditError> {
+        if self.head_idx < self.heads_history.len() - 1 {
+            self.automerge = self
+                .automerge
+                .fork_at(self.heads_history[self.head_idx].as_slice())
+                .map_err(|e| EditError::Invalid(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// edit a text file
+    /// if `pos` and `del` are `None` the whole text is updated, otherwise it is a splice
     pub(crate) fn edit(
         &mut self,
         pos: Option<usize>,
         del: Option<isize>,
         text: &str,
-    ) -> (Vec<u8>, usize, Vec<Vec<[u8; 32]>>) {
-        let obj_id = self.automerge.get(ROOT, Self::CONTENT).unwrap().unwrap().1; // to do
-        if self.head_idx < self.heads_history.len() - 1 {
-            self.automerge = self
-                .automerge
-                .fork_at(self.heads_history[self.head_idx].as_slice())
-                .unwrap();
+    ) -> Result<Edit, EditError> {
+        if self.is_binary() {
+            return Err(EditError::Binary);
         }
+        let obj_id = match self.automerge.get_at(
+            ROOT,
+            Self::CONTENT,
+            self.heads_history[self.head_idx].as_slice(),
+        ) {
+            Ok(Some((Value::Object(ObjType::Text), id))) => id,
+            _ => return Err(EditError::Invalid("the file is corrupted".to_string())),
+        };
+        self.fork_at_head()?;
         {
             let mut tx = self.automerge.transaction();
-            if pos.is_none() && del.is_none() {
-                let _ = tx.update_text(&obj_id, text);
-            } else {
-                let _ = tx.splice_text(obj_id, pos.unwrap(), del.unwrap(), text);
+            let res = match (pos, del) {
+                (None, None) => tx.update_text(&obj_id, text),
+                (Some(pos), Some(del)) => tx.splice_text(obj_id, pos, del, text),
+                _ => {
+                    tx.rollback();
+                    return Err(EditError::Invalid(
+                        "pos and del should be both set or both None".to_string(),
+                    ));
+                }
+            };
+            if let Err(e) = res {
+                tx.rollback();
+                return Err(EditError::Invalid(e.to_string()));
             }
             tx.commit();
         }
+        Ok(self.commit_edit())
+    }
+
+    /// replace the whole content of a binary file
+    /// if two users replace the file at the same time the last one wins
+    pub(crate) fn replace_bin(&mut self, content: &[u8]) -> Result<Edit, EditError> {
+        if !self.is_binary() {
+            return Err(EditError::Invalid("the file is not binary".to_string()));
+        }
+        if content.len() > MAX_BIN_SIZE {
+            return Err(EditError::TooLarge {
+                size: content.len(),
+                max: MAX_BIN_SIZE,
+            });
+        }
+        self.fork_at_head()?;
+        {
+            let mut tx = self.automerge.transaction();
+            if let Err(e) = tx.put(ROOT, Self::CONTENT, content.to_vec()) {
+                tx.rollback();
+                return Err(EditError::Invalid(e.to_string()));
+            }
+            tx.commit();
+        }
+        Ok(self.commit_edit())
+    }
+
+    /// add the new heads to the history and return the changes since the last head
+    fn commit_edit(&mut self) -> Edit {
         let changes = self
             .automerge
             .save_after(self.heads_history[self.head_idx].as_slice());
@@ -178,4 +341,86 @@ impl Crdt {
             self.head_idx,
         )
     }
+
+    /// the heads of the content now (they are compared with `splices_since`)
+    pub(crate) fn current_heads(&self) -> Vec<ChangeHash> {
+        self.heads_history[self.head_idx].clone()
+    }
+
+    /// the splices that make the text now from the text at `before`, in the order they are applied
+    /// only the changed parts are read (from the patches of automerge),
+    /// `None` if the content is not text (like a binary file)
+    pub(crate) fn splices_since(&self, before: &[ChangeHash]) -> Option<Vec<Splice>> {
+        let after = self.heads_history[self.head_idx].as_slice();
+        let text = match self.automerge.get_at(ROOT, Self::CONTENT, after) {
+            Ok(Some((Value::Object(ObjType::Text), id))) => id,
+            _ => return None,
+        };
+        let patches = self
+            .automerge
+            .diff(before, after, TextRepresentation::String);
+        let mut splices: Vec<Splice> = vec![];
+        for patch in patches {
+            if patch.obj != text {
+                return self.replaced_since(before);
+            }
+            match patch.action {
+                PatchAction::SpliceText { index, value, .. } => {
+                    let text = value.make_string();
+                    match splices.last_mut() {
+                        // a deletion and an insertion at the same place is a replacement
+                        Some(last) if last.pos == index && last.text.is_empty() => {
+                            last.text = text;
+                        }
+                        _ => splices.push(Splice {
+                            pos: index,
+                            del: 0,
+                            text,
+                        }),
+                    }
+                }
+                PatchAction::DeleteSeq { index, length } => match splices.last_mut() {
+                    // or an insertion and a deletion after it
+                    Some(last)
+                        if last.del == 0 && index == last.pos + last.text.chars().count() =>
+                    {
+                        last.del = length;
+                    }
+                    _ => splices.push(Splice {
+                        pos: index,
+                        del: length,
+                        text: String::new(),
+                    }),
+                },
+                _ => return self.replaced_since(before),
+            }
+        }
+        Some(splices)
+    }
+
+    /// the text object is not the same at `before` (the texts are compared)
+    fn replaced_since(&self, before: &[ChangeHash]) -> Option<Vec<Splice>> {
+        let before = self.read_at(before).ok()?;
+        let after = self.read().ok()?;
+        let before = String::from_utf8_lossy(&before);
+        Some(vec![splice(&before, &String::from_utf8_lossy(&after))])
+    }
+
+    /// the heads of the document (with the changes that are undone)
+    pub(crate) fn heads(&self) -> Vec<ChangeHash> {
+        self.automerge.get_heads()
+    }
+
+    /// the same as `save` but only with the changes after `heads`,
+    /// they are loaded after a document that is saved at `heads`
+    pub(crate) fn save_after(&self, heads: &[ChangeHash]) -> (Vec<u8>, Vec<Vec<[u8; 32]>>, usize) {
+        (
+            self.automerge.save_after(heads),
+            self.get_heads_history(),
+            self.head_idx,
+        )
+    }
 }
+
+#[cfg(test)]
+mod tests;
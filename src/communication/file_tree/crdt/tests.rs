@@ -0,0 +1,163 @@
+use super::*;
+
+fn text_file(text: &str) -> Crdt {
+    let mut buf = Automerge::new();
+    let mut tx = buf.transaction();
+    let id = tx.put_object(ROOT, Crdt::CONTENT, ObjType::Text).unwrap();
+    tx.splice_text(id, 0, 0, text).unwrap();
+    tx.commit();
+    Crdt::open(buf)
+}
+
+fn bin_file(content: &[u8]) -> Crdt {
+    let mut buf = Automerge::new();
+    let mut tx = buf.transaction();
+    tx.put(ROOT, Crdt::CONTENT, content.to_vec()).unwrap();
+    tx.commit();
+    Crdt::open(buf)
+}
+
+/// a copy of the file like the one the client gets
+fn copy(file: &Crdt) -> Crdt {
+    let (automerge, heads_history, head_idx) = file.save();
+    Crdt::new(automerge, heads_history, head_idx).unwrap()
+}
+
+#[test]
+fn edit_text() {
+    let mut file = text_file("hello");
+    assert!(!file.is_binary());
+    file.edit(Some(5), Some(0), " world").unwrap();
+    assert_eq!(file.read().unwrap(), b"hello world");
+    file.edit(None, None, "bye").unwrap();
+    assert_eq!(file.read().unwrap(), b"bye");
+    assert!(matches!(
+        file.edit(Some(0), None, "x"),
+        Err(EditError::Invalid(_))
+    ));
+    assert!(matches!(
+        file.edit(Some(100), Some(0), "x"),
+        Err(EditError::Invalid(_))
+    ));
+    assert_eq!(file.read().unwrap(), b"bye");
+}
+
+#[test]
+fn edit_binary_is_an_error() {
+    let mut file = bin_file(&[0, 159, 146, 150]);
+    assert!(file.is_binary());
+    assert_eq!(file.edit(None, None, "text"), Err(EditError::Binary));
+    assert_eq!(file.read().unwrap(), [0, 159, 146, 150]);
+}
+
+#[test]
+fn replace_binary() {
+    let mut file = bin_file(&[0, 1, 2]);
+    let mut other = copy(&file);
+    let (changes, old_head_idx, heads) = file.replace_bin(&[3, 4]).unwrap();
+    assert_eq!(file.read().unwrap(), [3, 4]);
+
+    other.update(&changes, old_head_idx, &heads).unwrap();
+    assert_eq!(other.read().unwrap(), [3, 4]);
+
+    file.undo();
+    assert_eq!(file.read().unwrap(), [0, 1, 2]);
+
+    assert!(matches!(
+        text_file("text").replace_bin(&[1]),
+        Err(EditError::Invalid(_))
+    ));
+}
+
+#[test]
+fn replace_binary_size_cap() {
+    let mut file = bin_file(&[0]);
+    let big = vec![0; MAX_BIN_SIZE + 1];
+    assert_eq!(
+        file.replace_bin(&big),
+        Err(EditError::TooLarge {
+            size: MAX_BIN_SIZE + 1,
+            max: MAX_BIN_SIZE
+        })
+    );
+
+    // a client that doesn't check the size
+    let mut other = copy(&file);
+    let mut tx = other.automerge.transaction();
+    tx.put(ROOT, Crdt::CONTENT, big).unwrap();
+    tx.commit();
+    let (changes, old_head_idx, heads) = other.commit_edit();
+    assert!(matches!(
+        file.update(&changes, old_head_idx, &heads),
+        Err(EditError::TooLarge { .. })
+    ));
+    assert_eq!(file.read().unwrap(), [0]);
+}
+
+#[test]
+fn concurrent_replace_last_writer_wins() {
+    let mut server = bin_file(&[0]);
+    let (mut a, mut b) = (copy(&server), copy(&server));
+    let from_a = a.replace_bin(&[1]).unwrap();
+    let from_b = b.replace_bin(&[2]).unwrap();
+    // the server gets the edit of `a` first and sends both edits to everyone in the same order
+    for file in [&mut server, &mut a, &mut b] {
+        file.update(&from_a.0, from_a.1, &from_a.2).unwrap();
+        file.update(&from_b.0, from_b.1, &from_b.2).unwrap();
+        assert_eq!(file.read().unwrap(), [2]);
+    }
+}
+
+#[test]
+fn clean_after_save() {
+    let mut file = text_file("hello");
+    assert!(file.is_clean());
+    file.edit(None, None, "bye").unwrap();
+    assert!(!file.is_clean());
+    file.undo();
+    assert!(file.is_clean());
+    file.redo();
+    file.mark_saved();
+    assert!(file.is_clean());
+}
+
+#[test]
+fn splices_of_the_changes() {
+    let splices = |file: &Crdt, before: &[ChangeHash]| -> Vec<(usize, usize, String)> {
+        let splices = file.splices_since(before).unwrap();
+        splices
+            .into_iter()
+            .map(|s| (s.pos, s.del, s.text))
+            .collect()
+    };
+    let mut file = text_file("aa");
+    let start = file.current_heads();
+    // the position of the edit, not the end of the common prefix
+    file.edit(Some(0), Some(0), "a").unwrap();
+    assert_eq!(splices(&file, &start), [(0, 0, "a".into())]);
+
+    // two edits of another copy are received in one update
+    let mut other = copy(&file);
+    let before = file.current_heads();
+    let (_, old_head_idx, first) = other.edit(Some(0), Some(1), "x").unwrap();
+    let (_, _, second) = other.edit(Some(3), Some(0), "yz").unwrap();
+    let changes = other.save_after(&before).0;
+    file.update(&changes, old_head_idx, &[first, second].concat())
+        .unwrap();
+    assert_eq!(file.read().unwrap(), b"xaayz");
+    assert_eq!(
+        splices(&file, &before),
+        [(0, 1, "x".into()), (3, 0, "yz".into())]
+    );
+
+    // an undo goes back to the heads of the first edit
+    let after = file.current_heads();
+    file.undo();
+    assert_eq!(splices(&file, &after), [(3, 2, "".into())]);
+    file.undo();
+    assert_eq!(
+        splices(&file, &after),
+        [(0, 1, "a".into()), (3, 2, "".into())]
+    );
+    assert!(bin_file(&[0]).splices_since(&start).is_none());
+}
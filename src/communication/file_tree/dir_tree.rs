@@ -0,0 +1,431 @@
+use std::collections::BTreeMap;
+use std::io::{self, Error};
+use std::ops::Bound;
+
+use crate::communication::file_entry::FileMeta;
+
+type Res<T> = io::Result<T>;
+
+/// a node of the directory tree
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Node {
+    File(FileMeta),
+    Dir(Dir),
+    /// a symlink and its target, it can't be opened (see `SymlinkPolicy::Link`)
+    Link(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Dir {
+    children: BTreeMap<String, Node>,
+    /// `false` when the content of the directory is not known yet (this is only used by the client)
+    loaded: bool,
+}
+
+impl Dir {
+    fn new(loaded: bool) -> Self {
+        Self {
+            children: BTreeMap::new(),
+            loaded,
+        }
+    }
+    /// a loaded directory without children
+    pub(crate) fn is_empty(&self) -> bool {
+        self.loaded && self.children.is_empty()
+    }
+    pub(crate) fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+}
+
+/// the directory tree of the project
+/// paths are the same as the rest of the code: files are `./dir/file` and directories are `./dir/`
+/// every lookup is `O(depth)`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DirTree {
+    root: Dir,
+}
+
+impl Default for DirTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DirTree {
+    pub(crate) fn new() -> Self {
+        Self {
+            root: Dir::new(true),
+        }
+    }
+
+    /// build the tree from a list of files and empty directories
+    /// the parents directories are created if they are not there
+    pub(crate) fn from_paths(files: Vec<String>, emty_dirs: Vec<String>) -> Self {
+        let mut tree = Self::new();
+        for dir in emty_dirs {
+            let _ = tree.insert(&dir, Node::Dir(Dir::new(true)), true);
+        }
+        for file in files {
+            let _ = tree.insert(&file, Node::File(FileMeta::default()), true);
+        }
+        tree
+    }
+
+    /// split the path into its components, `"./"` is the root
+    fn parts(path: &str) -> Res<Vec<&str>> {
+        let rest = path.strip_prefix("./").ok_or_else(|| {
+            Error::new(
+                io::ErrorKind::InvalidInput,
+                "The path should start with './'",
+            )
+        })?;
+        Ok(rest.split('/').filter(|x| !x.is_empty()).collect())
+    }
+
+    fn dir(&self, parts: &[&str]) -> Option<&Dir> {
+        let mut dir = &self.root;
+        for part in parts {
+            match dir.children.get(*part) {
+                Some(Node::Dir(child)) => dir = child,
+                _ => return None,
+            }
+        }
+        Some(dir)
+    }
+
+    fn dir_mut(&mut self, parts: &[&str], make_parents: bool) -> Option<&mut Dir> {
+        let mut dir = &mut self.root;
+        for part in parts {
+            if make_parents && !dir.children.contains_key(*part) {
+                dir.children
+                    .insert(part.to_string(), Node::Dir(Dir::new(true)));
+            }
+            match dir.children.get_mut(*part) {
+                Some(Node::Dir(child)) => dir = child,
+                _ => return None,
+            }
+        }
+        Some(dir)
+    }
+
+    /// check that a node could be inserted in the directory `parts` without changing the tree
+    fn can_insert_in(&self, parts: &[&str], make_parents: bool) -> bool {
+        let mut dir = &self.root;
+        for part in parts {
+            match dir.children.get(*part) {
+                Some(Node::Dir(child)) => dir = child,
+                Some(_) => return false,
+                None => return make_parents, // the rest will be created
+            }
+        }
+        true
+    }
+
+    pub(crate) fn get(&self, path: &str) -> Option<&Node> {
+        let parts = Self::parts(path).ok()?;
+        match parts.split_last() {
+            None => None, // the root is not a node
+            Some((name, parents)) => self.dir(parents)?.children.get(*name),
+        }
+    }
+
+    pub(crate) fn is_file(&self, path: &str) -> bool {
+        matches!(self.get(path), Some(Node::File(_)))
+    }
+
+    /// the metadata of a file to change it
+    pub(crate) fn meta_mut(&mut self, path: &str) -> Option<&mut FileMeta> {
+        let parts = Self::parts(path).ok()?;
+        let (name, parents) = parts.split_last()?;
+        match self.dir_mut(parents, false)?.children.get_mut(*name)? {
+            Node::File(meta) => Some(meta),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn is_link(&self, path: &str) -> bool {
+        matches!(self.get(path), Some(Node::Link(_)))
+    }
+
+    /// returns `true` if `path` is a directory in the tree (the root is always there)
+    pub(crate) fn is_dir(&self, path: &str) -> bool {
+        Self::parts(path).is_ok_and(|parts| self.dir(&parts).is_some())
+    }
+
+    /// returns `true` if one of the parents of `path` is an unloaded directory
+    pub(crate) fn in_unloaded(&self, path: &str) -> bool {
+        let Ok(parts) = Self::parts(path) else {
+            return false;
+        };
+        let mut dir = &self.root;
+        for part in parts.iter().take(parts.len().saturating_sub(1)) {
+            if !dir.loaded {
+                return true;
+            }
+            match dir.children.get(*part) {
+                Some(Node::Dir(child)) => dir = child,
+                _ => return false,
+            }
+        }
+        !dir.loaded && !parts.is_empty()
+    }
+
+    /// insert a node in `path`
+    /// if `make_parents` is `false` the parent directory should exist
+    pub(crate) fn insert(&mut self, path: &str, node: Node, make_parents: bool) -> Res<()> {
+        let parts = Self::parts(path)?;
+        let (name, parents) = parts
+            .split_last()
+            .ok_or_else(|| Error::new(io::ErrorKind::AlreadyExists, "The root directory exists"))?;
+        let dir = self
+            .dir_mut(parents, make_parents)
+            .ok_or_else(|| Error::new(io::ErrorKind::NotFound, "The directory does not exist"))?;
+        if dir.children.contains_key(*name) {
+            return Err(Error::new(
+                io::ErrorKind::AlreadyExists,
+                "The path already exists",
+            ));
+        }
+        dir.children.insert(name.to_string(), node);
+        Ok(())
+    }
+
+    /// make a directory and all of its parents
+    pub(crate) fn make_dir(&mut self, path: &str, loaded: bool) -> Res<()> {
+        self.insert(path, Node::Dir(Dir::new(loaded)), true)
+    }
+
+    /// remove the node in `path` with all of its content
+    pub(crate) fn remove(&mut self, path: &str) -> Res<Node> {
+        let parts = Self::parts(path)?;
+        let (name, parents) = parts.split_last().ok_or_else(|| {
+            Error::new(
+                io::ErrorKind::InvalidInput,
+                "The root directory can't be removed",
+            )
+        })?;
+        self.dir_mut(parents, false)
+            .and_then(|dir| dir.children.remove(*name))
+            .ok_or_else(|| Error::new(io::ErrorKind::NotFound, "The path does not exist"))
+    }
+
+    /// move the node in `old_path` with all of its content to `new_path`
+    /// if `make_parents` is `false` the parent directory of `new_path` should exist
+    pub(crate) fn rename(&mut self, old_path: &str, new_path: &str, make_parents: bool) -> Res<()> {
+        let (old_parts, new_parts) = (Self::parts(old_path)?, Self::parts(new_path)?);
+        if new_parts.starts_with(&old_parts) {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                "can't move a directory inside itself",
+            ));
+        }
+        if self.get(old_path).is_none() {
+            return Err(Error::new(
+                io::ErrorKind::NotFound,
+                "The path does not exist",
+            ));
+        }
+        let Some((_, new_parents)) = new_parts
+            .split_last()
+            .filter(|_| self.get(new_path).is_none())
+        else {
+            return Err(Error::new(
+                io::ErrorKind::AlreadyExists,
+                "The new path already exists",
+            ));
+        };
+        if !self.can_insert_in(new_parents, make_parents) {
+            return Err(Error::new(
+                io::ErrorKind::NotFound,
+                "The directory does not exist",
+            ));
+        }
+        let node = self.remove(old_path)?;
+        self.insert(new_path, node, make_parents)
+    }
+
+    /// copy the node in `old_path` with all of its content to `new_path`
+    pub(crate) fn copy(&mut self, old_path: &str, new_path: &str, make_parents: bool) -> Res<()> {
+        let (old_parts, new_parts) = (Self::parts(old_path)?, Self::parts(new_path)?);
+        if new_parts.starts_with(&old_parts) {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                "can't copy a directory inside itself",
+            ));
+        }
+        let node = self
+            .get(old_path)
+            .cloned()
+            .ok_or_else(|| Error::new(io::ErrorKind::NotFound, "The path does not exist"))?;
+        self.insert(new_path, node, make_parents)
+    }
+
+    /// mark a directory as loaded or not
+    pub(crate) fn set_loaded(&mut self, path: &str, loaded: bool) {
+        if let Some(dir) = Self::parts(path)
+            .ok()
+            .and_then(|parts| self.dir_mut(&parts, false))
+        {
+            dir.loaded = loaded;
+        }
+    }
+
+    /// visit every node under `dir_path` up to `depth` levels (`None` is the whole subtree)
+    /// the visitor gets the path of the node, the node and its level (starting from 1)
+    pub(crate) fn walk<'a>(
+        &'a self,
+        dir_path: &str,
+        depth: Option<usize>,
+        visitor: &mut impl FnMut(String, &'a Node, usize),
+    ) -> Res<()> {
+        fn walk_dir<'a>(
+            dir: &'a Dir,
+            path: &str,
+            level: usize,
+            depth: Option<usize>,
+            visitor: &mut impl FnMut(String, &'a Node, usize),
+        ) {
+            for (name, node) in dir.children.iter() {
+                match node {
+                    Node::File(_) | Node::Link(_) => visitor(format!("{path}{name}"), node, level),
+                    Node::Dir(child) => {
+                        let child_path = format!("{path}{name}/");
+                        visitor(child_path.clone(), node, level);
+                        if depth.is_none_or(|depth| level < depth) {
+                            walk_dir(child, &child_path, level + 1, depth, visitor);
+                        }
+                    }
+                }
+            }
+        }
+        let parts = Self::parts(dir_path)?;
+        let dir = self
+            .dir(&parts)
+            .ok_or_else(|| Error::new(io::ErrorKind::NotFound, "The directory does not exist"))?;
+        let path = if parts.is_empty() {
+            "./".to_string()
+        } else {
+            format!("./{}/", parts.join("/"))
+        };
+        walk_dir(dir, &path, 1, depth, visitor);
+        Ok(())
+    }
+
+    /// the same as `walk` but it starts after the node in `after` (a path in `dir_path`)
+    /// and stops when the visitor returns `false`, the nodes before `after` are not visited
+    /// (`after` doesn't have to be in the tree any more)
+    pub(crate) fn walk_after<'a>(
+        &'a self,
+        dir_path: &str,
+        depth: Option<usize>,
+        after: Option<&str>,
+        visitor: &mut impl FnMut(String, &'a Node, usize) -> bool,
+    ) -> Res<()> {
+        /// `false` if the visitor stopped the walk
+        fn walk_dir<'a>(
+            dir: &'a Dir,
+            path: &str,
+            level: usize,
+            depth: Option<usize>,
+            after: &[&str],
+            visitor: &mut impl FnMut(String, &'a Node, usize) -> bool,
+        ) -> bool {
+            let descend = depth.is_none_or(|depth| level < depth);
+            let children = match after.split_first() {
+                None => dir.children.range::<str, _>(..),
+                Some((name, rest)) => {
+                    // `after` is already visited but its content comes after it
+                    if let Some(Node::Dir(child)) = dir.children.get(*name) {
+                        let child_path = format!("{path}{name}/");
+                        if descend && !walk_dir(child, &child_path, level + 1, depth, rest, visitor)
+                        {
+                            return false;
+                        }
+                    }
+                    dir.children
+                        .range::<str, _>((Bound::Excluded(*name), Bound::Unbounded))
+                }
+            };
+            for (name, node) in children {
+                match node {
+                    Node::File(_) | Node::Link(_) => {
+                        if !visitor(format!("{path}{name}"), node, level) {
+                            return false;
+                        }
+                    }
+                    Node::Dir(child) => {
+                        let child_path = format!("{path}{name}/");
+                        if !visitor(child_path.clone(), node, level) {
+                            return false;
+                        }
+                        if descend && !walk_dir(child, &child_path, level + 1, depth, &[], visitor)
+                        {
+                            return false;
+                        }
+                    }
+                }
+            }
+            true
+        }
+        let parts = Self::parts(dir_path)?;
+        let dir = self
+            .dir(&parts)
+            .ok_or_else(|| Error::new(io::ErrorKind::NotFound, "The directory does not exist"))?;
+        let path = if parts.is_empty() {
+            "./".to_string()
+        } else {
+            format!("./{}/", parts.join("/"))
+        };
+        let after = match after {
+            Some(after) => after.strip_prefix(path.as_str()).ok_or_else(|| {
+                Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{after} is not in {path}"),
+                )
+            })?,
+            None => "",
+        };
+        let after: Vec<&str> = after.split('/').filter(|part| !part.is_empty()).collect();
+        walk_dir(dir, &path, 1, depth, &after, visitor);
+        Ok(())
+    }
+
+    /// all the files in the tree sorted
+    pub(crate) fn files(&self) -> Vec<String> {
+        let mut files = vec![];
+        let _ = self.walk("./", None, &mut |path, node, _| {
+            if matches!(node, Node::File(_)) {
+                files.push(path)
+            }
+        });
+        files.sort_unstable();
+        files
+    }
+
+    /// all the empty directories in the tree sorted (`"./"` if the tree is empty)
+    pub(crate) fn emty_dirs(&self) -> Vec<String> {
+        if self.root.is_empty() {
+            return vec!["./".to_string()];
+        }
+        let mut dirs = vec![];
+        let _ = self.walk("./", None, &mut |path, node, _| {
+            if matches!(node, Node::Dir(dir) if dir.is_empty()) {
+                dirs.push(path)
+            }
+        });
+        dirs.sort_unstable();
+        dirs
+    }
+
+    /// all the directories that are not loaded yet
+    pub(crate) fn unloaded_dirs(&self) -> Vec<String> {
+        let mut dirs = vec![];
+        let _ = self.walk("./", None, &mut |path, node, _| {
+            if matches!(node, Node::Dir(dir) if !dir.loaded) {
+                dirs.push(path)
+            }
+        });
+        dirs
+    }
+}
@@ -1,11 +1,21 @@
 #![allow(private_bounds)]
-use crate::server::{connection::Priviledge, messageing};
+use crate::communication::file_entry::SNIFF_SIZE;
+use crate::communication::file_tree::crdt::MAX_BIN_SIZE;
+use crate::communication::permissions::Permissions;
+use crate::communication::project_path::ProjectPath;
+use crate::server::{
+    config::{config, ShareMode},
+    disk, messageing, roots,
+};
 use automerge::{transaction::Transactable, ROOT};
-use futures::SinkExt as _;
+#[cfg(not(test))]
+use std::path::Path;
 use std::{
     fs::{self, File},
-    io::{self, Error, Write},
+    io::{self, Error, Read as _, Write},
+    path::PathBuf,
 };
+use tokio::io::{AsyncRead, AsyncReadExt as _};
 
 type Res<T> = io::Result<T>;
 
@@ -17,80 +27,243 @@ use crate::communication::rpc::RPC;
 #[cfg(test)]
 mod server_tests;
 
+pub(crate) mod trash;
+
 trait PrivateServerFn {
     /// add file to the tree
-    fn open_file(&mut self, path: String) -> Res<()>;
-    fn create_file(&mut self, path: String) -> Res<()>;
-    fn move_file(&mut self, old_path: String, new_path: String) -> Res<()>;
-
-    fn rm_file(&mut self, path: String) -> Res<()>; // dir operations
+    fn open_file(&mut self, path: ProjectPath) -> Res<()>;
+    fn create_file(
+        &mut self,
+        path: ProjectPath,
+        mode: Option<u32>,
+        content: Option<Vec<u8>>,
+    ) -> Res<()>;
+    fn move_file(&mut self, old_path: ProjectPath, new_path: ProjectPath) -> Res<()>;
+    fn copy_file(&mut self, path: ProjectPath, new_path: ProjectPath) -> Res<()>;
 
-    fn move_dir(&mut self, old_path: String, new_path: String) -> Res<()>; // dir operation
-    fn rm_dir(&mut self, path: String) -> Res<()>;
-    fn make_dir(&mut self, path: String) -> Res<()>;
+    fn move_dir(&mut self, old_path: ProjectPath, new_path: ProjectPath) -> Res<()>; // dir operation
+    fn copy_dir(&mut self, path: ProjectPath, new_path: ProjectPath) -> Res<()>;
+    fn make_dir(&mut self, path: ProjectPath) -> Res<()>;
     fn update_buf(
         &mut self,
-        path: String,
+        path: ProjectPath,
         changes: &[u8],
         old_head_idx: usize,
         heads: &[Vec<[u8; 32]>],
     ) -> Res<()>;
 
-    fn save_buf(&mut self, path: String) -> Res<()>;
-    #[allow(clippy::ptr_arg)] // this is because of the binary_search
-    fn get_automerge(&mut self, path: &String) -> Res<(Vec<u8>, Vec<Vec<[u8; 32]>>, usize)>;
+    fn save_buf(&mut self, path: ProjectPath) -> Res<()>;
+    fn get_automerge(&mut self, path: &ProjectPath) -> Res<SavedCrdt>;
 }
 
+/// the automerge document, the heads history and the head index
+type SavedCrdt = (Vec<u8>, Vec<Vec<[u8; 32]>>, usize);
+
 pub(crate) trait PubServerFn: PrivateServerFn {
     fn build_file_tree() -> Self;
     async fn handle_msg(
         &mut self,
         tx: RPC,
-        client: Option<Priviledge>,
+        client: Option<&Permissions>,
         username: &str,
-    ) -> Result<Message, ()>;
-    fn open_file(&mut self, path: String) -> Res<()>;
+    ) -> Result<Message, String>;
+    fn open_file(&mut self, path: ProjectPath) -> Res<()>;
+    /// apply a change that is already on the disk (like the ones that the watcher finds)
+    /// only the tree is changed, the missing parents are made
+    fn sync(&mut self, change: &RPC) -> Res<()>;
+}
+
+/// how a file is sent to the clients
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OpenMode {
+    /// a crdt is made for the file
+    Edit,
+    /// the file is too large to be edited, it is sent as it is
+    ViewOnly,
+}
+
+/// check the size of the file against the config
+fn open_mode(path: &ProjectPath) -> Res<OpenMode> {
+    let config = config();
+    let metadata = fs::metadata(on_disk(path)?)?;
+    // sockets, fifos and devices could block when they are read
+    if !metadata.is_file() {
+        return Err(Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{path} is not a regular file"),
+        ));
+    }
+    let size = metadata.len();
+    if size > config.max_file_size {
+        Err(Error::new(
+            io::ErrorKind::FileTooLarge,
+            format!(
+                "{path} is too large ({size} bytes, the limit is {})",
+                config.max_file_size
+            ),
+        ))
+    } else if size > config.view_only_size || size > MAX_BIN_SIZE as u64 && is_binary(path)? {
+        // a binary file is replaced as a whole so it can't be bigger than `MAX_BIN_SIZE`
+        Ok(OpenMode::ViewOnly)
+    } else {
+        Ok(OpenMode::Edit)
+    }
+}
+
+/// the content is not text (see `crdt_of`), it is guessed from the first bytes (see `FileMeta::sniff`)
+fn is_binary(path: &ProjectPath) -> Res<bool> {
+    let mut head = vec![];
+    File::open(on_disk(path)?)?
+        .take(SNIFF_SIZE as u64)
+        .read_to_end(&mut head)?;
+    let mut meta = FileMeta::default();
+    meta.sniff(&head, head.len() < SNIFF_SIZE);
+    Ok(meta.binary)
+}
+
+/// send `content` to a client in `ResFileChunk`s, the header is sent after them by the caller
+/// this returns `true` if the content is not valid utf-8 or `None` if it is not sent
+/// this should run in its own task so a large file doesn't block the other messages
+async fn send_chunked(
+    username: &str,
+    path: &ProjectPath,
+    mut content: impl AsyncRead + Unpin,
+    total: u64,
+    chunk_size: usize,
+) -> Option<bool> {
+    let mut buf = vec![0; chunk_size.max(1)];
+    // the end of the last chunk if it is in the middle of a utf-8 character
+    let mut tail = vec![];
+    let mut binary = false;
+    let mut offset = 0;
+    while offset < total {
+        let n = match content.read(&mut buf).await {
+            Ok(0) => break, // the file got smaller
+            Ok(n) => n,
+            Err(e) => {
+                log::error!("failed to read {path}: {e}");
+                return None;
+            }
+        };
+        if !binary {
+            tail.extend_from_slice(&buf[..n]);
+            match std::str::from_utf8(&tail) {
+                Ok(_) => tail.clear(),
+                Err(e) if e.error_len().is_none() => {
+                    tail.drain(..e.valid_up_to());
+                }
+                Err(_) => binary = true,
+            }
+        }
+        let rpc = RPC::ResFileChunk {
+            path: path.clone(),
+            offset,
+            total,
+            data: buf[..n].to_vec(),
+        };
+        let sent = match rpc.encode() {
+            Ok(msg) => messageing::send_to_client_when_ready(username, msg).await,
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = sent {
+            log::error!("failed to send {path} to {username}: {e}");
+            return None;
+        }
+        offset += n as u64;
+    }
+    Some(binary || !tail.is_empty())
+}
+
+/// the crdt of the content of a file, it is text if the content is valid utf-8
+fn crdt_of(content: Vec<u8>) -> Crdt {
+    let mut buf = automerge::Automerge::new();
+    let mut tx = buf.transaction();
+    match String::from_utf8(content) {
+        Ok(text) => {
+            let i = tx
+                .put_object(ROOT, "content", automerge::ObjType::Text)
+                .unwrap(); // todo: check the error
+            tx.splice_text(i, 0, 0, &text).unwrap(); // todo: check the error
+        }
+        Err(e) => {
+            tx.put(ROOT, "content", e.into_bytes()).unwrap(); // todo: check the error
+        }
+    }
+    tx.commit();
+    Crdt::open(buf)
+}
+
+/// copy a directory with its content, the links are copied as links and the special files are skipped
+fn copy_dir_on_disk(from: &Path, to: &Path) -> Res<()> {
+    fs::create_dir_all(to)?;
+    for entry in walkdir::WalkDir::new(from).min_depth(1) {
+        let entry = entry?;
+        let target = to.join(entry.path().strip_prefix(from).map_err(Error::other)?);
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            fs::create_dir_all(&target)?;
+        } else if file_type.is_file() {
+            fs::copy(entry.path(), &target)?;
+        } else if file_type.is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// the path on the disk (in its root)
+/// this rejects the paths that are symlinks to outside of their root and the ignored paths
+fn on_disk(path: &ProjectPath) -> Res<PathBuf> {
+    let mount = roots::mount_of(path).ok_or_else(|| {
+        Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{path} is not in a shared directory"),
+        )
+    })?;
+    if mount.is_ignored(path) {
+        return Err(Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{path} is ignored"),
+        ));
+    }
+    mount.resolve(path)
 }
 
 impl PrivateServerFn for FileTree {
-    fn open_file(&mut self, path: String) -> Res<()> {
-        if self.files.binary_search(&path).is_err() {
+    fn open_file(&mut self, path: ProjectPath) -> Res<()> {
+        if !self.is_file(&path) {
             return Err(Error::new(
                 io::ErrorKind::NotFound,
                 "The file does not exist",
             ));
         }
-        enum FileType {
-            Text(String),
-            Bin(Vec<u8>),
+        if open_mode(&path)? == OpenMode::ViewOnly {
+            return Err(Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{path} is too large to be edited, it is view only"),
+            ));
         }
-
-        let file_content: FileType = match fs::read_to_string(&path) {
-            Ok(text) => FileType::Text(text),
-            Err(ref e) if e.kind() == io::ErrorKind::InvalidData => FileType::Bin(fs::read(&path)?),
-            Err(e) => return Err(e),
-        };
-        let mut buf = automerge::Automerge::new();
-        match file_content {
-            FileType::Text(file_text) => {
-                let mut tx = buf.transaction();
-                let i = tx
-                    .put_object(ROOT, "content", automerge::ObjType::Text)
-                    .unwrap(); // todo: check the error
-                tx.splice_text(i, 0, 0, &file_text).unwrap(); // todo: check the error
-                tx.commit();
-            }
-            FileType::Bin(file_bin) => {
-                let mut tx = buf.transaction();
-                tx.put(ROOT, "content", file_bin).unwrap(); // todo: check the error
-                tx.commit();
-            }
-        }
-        self.tree.insert(path, Crdt::open(buf));
+        let content = fs::read(on_disk(&path)?)?;
+        // only the start of the file is sniffed by `open_mode`
+        if content.len() > MAX_BIN_SIZE && std::str::from_utf8(&content).is_err() {
+            return Err(Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{path} is too large to be edited, it is view only"),
+            ));
+        }
+        self.tree.insert(path.into(), crdt_of(content));
         Ok(())
     }
 
-    fn create_file(&mut self, path: String) -> Res<()> {
+    /// the content is written and loaded in the crdt before the file is added to the tree
+    fn create_file(
+        &mut self,
+        path: ProjectPath,
+        mode: Option<u32>,
+        content: Option<Vec<u8>>,
+    ) -> Res<()> {
+        let path = path.into_file()?;
         // check if the directory exists
         let dir_path = Self::parent_dir(&path);
         if !self.in_dir(&dir_path) {
@@ -99,115 +272,131 @@ impl PrivateServerFn for FileTree {
                 "The directory does not exist",
             ));
         }
-        let (files, emty_dirs) = (&mut self.files, &mut self.emty_dirs);
-
-        let i = files.binary_search(&path);
-        if i.is_ok() {
+        if self.paths.get(&path).is_some() {
             return Err(Error::new(
                 io::ErrorKind::AlreadyExists,
                 "The file already exists",
             ));
         }
-        let i = i.unwrap_err(); // todo: check the errors
 
-        #[cfg(not(test))]
-        File::create(&path)?; // this order is important as faliure in creating the file
-                              // would result of the file not being added the tree
-        if let Ok(i) = emty_dirs.binary_search(&dir_path) {
-            emty_dirs.remove(i); // EMTY_DIRS_OP
-        };
+        let mut meta = FileMeta::with_mode(mode);
+        let mut crdt = None;
+        if let Some(content) = &content {
+            let size = content.len() as u64;
+            if size > config().max_file_size {
+                return Err(Error::new(
+                    io::ErrorKind::FileTooLarge,
+                    format!("{path} is too large ({size} bytes)"),
+                ));
+            }
+            meta.size = size;
+            let whole = content.len() <= SNIFF_SIZE;
+            meta.sniff(&content[..content.len().min(SNIFF_SIZE)], whole);
+            // the large files are view only (see `open_mode`)
+            let binary = std::str::from_utf8(content).is_err();
+            if size <= config().view_only_size && !(binary && content.len() > MAX_BIN_SIZE) {
+                crdt = Some(crdt_of(content.clone()));
+            }
+        }
 
-        files.insert(i, path);
+        #[cfg(not(test))]
+        {
+            let disk_path = on_disk(&path)?;
+            let mut file = File::create(&disk_path)?; // this order is important as faliure in creating the file
+                                                      // would result of the file not being added the tree
+            let written = match &content {
+                Some(content) => file.write_all(content),
+                None => Ok(()),
+            };
+            #[cfg(unix)]
+            let written = written.and_then(|_| match mode {
+                Some(mode) => {
+                    use std::os::unix::fs::PermissionsExt;
+                    file.set_permissions(fs::Permissions::from_mode(mode))
+                }
+                None => Ok(()),
+            });
+            // a half written file is not left on the disk
+            if let Err(e) = written {
+                let _ = fs::remove_file(&disk_path);
+                return Err(e);
+            }
+        }
+        // the watcher updates the metadata when it sees the file
+        self.paths.insert(&path, Node::File(meta), false)?;
+        if let Some(crdt) = crdt {
+            self.tree.insert(path.into(), crdt);
+        }
         Ok(())
     }
 
-    fn move_file(&mut self, old_path: String, new_path: String) -> Res<()> {
-        // you know borrow checker
-        let files = &self.files;
-        let old_index = match files.binary_search(&old_path) {
-            Err(_) => return Err(Error::new(io::ErrorKind::NotFound, "file not found")),
-            Ok(old_index) => old_index,
-        };
-
-        let new_dir_path = Self::parent_dir(&new_path); // no need to check old path parent
-        let old_dir_path = Self::parent_dir(&old_path);
-        if !self.in_dir(&new_dir_path) {
+    fn copy_file(&mut self, path: ProjectPath, new_path: ProjectPath) -> Res<()> {
+        let new_path = new_path.into_file()?;
+        if !self.is_file(&path) {
+            return Err(Error::new(io::ErrorKind::NotFound, "file not found"));
+        }
+        if !self.in_dir(&Self::parent_dir(&new_path)) {
             return Err(Error::new(
                 io::ErrorKind::NotFound,
                 "The directory does not exist",
             ));
         }
-        let files = &mut self.files;
+        if self.paths.get(&new_path).is_some() {
+            return Err(Error::new(
+                io::ErrorKind::AlreadyExists,
+                "file path already exists",
+            ));
+        }
         #[cfg(not(test))]
         {
-            fs::rename(old_path.clone(), new_path.clone())?;
+            // the open file is copied with the changes that are not saved
+            match self.tree.get(path.as_str()) {
+                Some(file) => fs::write(on_disk(&new_path)?, file.read()?)?,
+                None => {
+                    fs::copy(on_disk(&path)?, on_disk(&new_path)?)?;
+                }
+            }
         }
+        self.paths.copy(&path, &new_path, false)
+    }
 
-        files.remove(old_index);
-        match files.binary_search(&new_path) {
-            Ok(_) => {
-                // clean up the mess (this is also an expensive clean up)
-                files.insert(old_index, old_path);
-                return Err(Error::new(
-                    io::ErrorKind::AlreadyExists,
-                    "file path already exists",
-                ));
-            }
-            Err(i) => files.insert(i, new_path.clone()),
+    fn move_file(&mut self, old_path: ProjectPath, new_path: ProjectPath) -> Res<()> {
+        let new_path = new_path.into_file()?;
+        if !self.is_file(&old_path) {
+            return Err(Error::new(io::ErrorKind::NotFound, "file not found"));
         }
 
-        match self.emty_dirs.binary_search(&new_dir_path) {
-            Err(_) => (),
-            Ok(i) => {
-                self.emty_dirs.remove(i);
-            }
+        let new_dir_path = Self::parent_dir(&new_path); // no need to check old path parent
+        if !self.in_dir(&new_dir_path) {
+            return Err(Error::new(
+                io::ErrorKind::NotFound,
+                "The directory does not exist",
+            ));
         }
-        if !self.in_dir(&old_dir_path) {
-            let emty_dirs = &mut self.emty_dirs;
-            match emty_dirs.binary_search(&old_dir_path) {
-                Ok(_) => unreachable!(),
-                Err(i) => emty_dirs.insert(i, old_dir_path),
-            }
+        if self.paths.get(&new_path).is_some() {
+            return Err(Error::new(
+                io::ErrorKind::AlreadyExists,
+                "file path already exists",
+            ));
+        }
+        #[cfg(not(test))]
+        {
+            fs::rename(on_disk(&old_path)?, on_disk(&new_path)?)?;
         }
-        if let Some(file) = self.tree.remove(&old_path) {
-            self.tree.insert(new_path, file);
+        self.paths.rename(&old_path, &new_path, false)?;
+
+        if let Some(file) = self.tree.remove(old_path.as_str()) {
+            self.tree.insert(new_path.into(), file);
         };
         Ok(())
     }
 
-    fn rm_file(&mut self, path: String) -> Res<()> {
-        let files = &mut self.files;
-        if let Ok(i) = files.binary_search(&path) {
-            #[cfg(not(test))]
-            fs::remove_file(&path)?;
-            let path = self.files.remove(i);
-            let dir_path = Self::parent_dir(&path);
-            if !self.in_dir(&dir_path) {
-                // EMTY_DIRS_OP
-                match self.emty_dirs.binary_search(&dir_path) {
-                    Ok(_) => {
-                        unreachable!()
-                    }
-                    Err(i) => {
-                        self.emty_dirs.insert(i, dir_path);
-                    }
-                }
-            }
-            self.tree.remove(&path);
-            Ok(())
-        } else {
-            Err(Error::new(
-                io::ErrorKind::NotFound,
-                "The file does not exist",
-            ))
-        }
-    }
-
-    fn move_dir(&mut self, old_path: String, new_path: String) -> Res<()> {
-        if !(Self::valid_dir_path(&new_path) && Self::valid_dir_path(&old_path)) {
+    fn move_dir(&mut self, old_path: ProjectPath, new_path: ProjectPath) -> Res<()> {
+        let (old_path, new_path) = (old_path.into_dir(), new_path.into_dir());
+        if new_path.starts_with(old_path.as_str()) {
             return Err(Error::new(
                 io::ErrorKind::InvalidInput,
-                "The path should start with './' and end with '/'",
+                "can't move a directory inside itself",
             ));
         }
         if !self.in_dir(&old_path) {
@@ -216,132 +405,60 @@ impl PrivateServerFn for FileTree {
                 "The old directory does not exist",
             ));
         }
-        if self.in_dir(&new_path) {
+        if self.in_dir(&new_path) || self.paths.get(&new_path).is_some() {
             return Err(Error::new(
                 io::ErrorKind::AlreadyExists,
                 "The new directory does exist",
             ));
         }
         #[cfg(not(test))]
-        fs::create_dir_all(&new_path)?; // this will create a new directory if the one doesn't
-                                        // this will work in nested case
+        fs::create_dir_all(on_disk(&new_path)?)?; // this will create a new directory if the one doesn't
+                                                  // this will work in nested case
         #[cfg(not(test))]
-        fs::rename(&old_path, &new_path)?;
-        // this is awkward
-        // help me ")
-        let (files, emty_dirs) = (&mut self.files, &mut self.emty_dirs);
-        if let Ok(i) = emty_dirs.binary_search(&old_path) {
-            // EMTY_DIRS_OP
-            emty_dirs.remove(i);
-            match emty_dirs.binary_search(&new_path) {
-                Ok(_) => unreachable!(),
-                Err(i) => {
-                    self.emty_dirs.insert(i, new_path);
-                }
-            }
-            return Ok(());
-        }
-
-        let start = files.binary_search(&old_path).unwrap_err();
-
-        // check if the directory is empty
-        let mut r = files.len();
-        let mut l = start;
-        // binary search for the end of the directory
-        while l < r {
-            let mid = l + (r - l) / 2;
-            if files[mid].starts_with(&old_path) {
-                l = mid + 1;
-            } else {
-                r = mid;
-            }
-        }
-        let end = r;
-
-        let new_files: Vec<String> = files
-            .drain(start..end)
-            .map(|s| {
-                self.tree.remove(&s);
-                s.replacen(&old_path, &new_path, 1)
-            }) // replacen is a must
-            // make sure to replace the old_path with the new_path for the frist string
-            .collect::<Vec<String>>();
-
-        match files.binary_search(&new_path) {
-            Ok(_) => unreachable!(),
-            Err(i) => {
-                let tail = files.split_off(i);
-                files.extend(new_files);
-                files.extend(tail);
-            }
-        };
+        fs::rename(on_disk(&old_path)?, on_disk(&new_path)?)?;
 
+        self.paths.rename(&old_path, &new_path, true)?;
+        // the open files are kept so the editing goes on in the new path
+        self.move_loaded(&old_path, &new_path);
         Ok(())
     }
 
-    fn rm_dir(&mut self, path: String) -> Res<()> {
+    fn copy_dir(&mut self, path: ProjectPath, new_path: ProjectPath) -> Res<()> {
+        let (path, new_path) = (path.into_dir(), new_path.into_dir());
+        if new_path.starts_with(path.as_str()) {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                "can't copy a directory inside itself",
+            ));
+        }
         if !self.in_dir(&path) {
             return Err(Error::new(
                 io::ErrorKind::NotFound,
                 "The directory does not exist",
             ));
         }
-        let (files, emty_dirs) = (&mut self.files, &mut self.emty_dirs);
-        let parent_dir = Self::parent_dir(&path);
-
-        if let Ok(i) = emty_dirs.binary_search(&path) {
-            // EMTY_DIRS_OP
-            #[cfg(not(test))]
-            fs::remove_dir_all(&path)?;
-            emty_dirs.remove(i);
-            if !self.in_dir(&parent_dir) {
-                match self.emty_dirs.binary_search(&parent_dir) {
-                    Ok(_) => {}
-                    Err(i) => {
-                        self.emty_dirs.insert(i, parent_dir);
-                    }
-                }
-            }
-            return Ok(());
-        }
-
-        let start = files.binary_search(&path).unwrap_err();
-
-        // check if the directory is empty
-        let mut r = files.len();
-        let mut l = start;
-        // binary search for the end of the directory
-        while l < r {
-            let mid = (l + r) / 2;
-            if files[mid].starts_with(&path) {
-                l = mid + 1;
-            } else {
-                r = mid;
-            }
+        if self.in_dir(&new_path) || self.paths.get(&new_path).is_some() {
+            return Err(Error::new(
+                io::ErrorKind::AlreadyExists,
+                "The new directory does exist",
+            ));
         }
-        let end = r;
-
         #[cfg(not(test))]
-        fs::remove_dir_all(&path)?;
-
-        files.drain(start..end).for_each(|s| {
-            self.tree.remove(&s);
-            drop(s);
-        });
-
-        if !self.in_dir(&parent_dir) {
-            match self.emty_dirs.binary_search(&parent_dir) {
-                Ok(_) => unreachable!(),
-                Err(i) => {
-                    self.emty_dirs.insert(i, parent_dir);
-                }
+        {
+            copy_dir_on_disk(&on_disk(&path)?, &on_disk(&new_path)?)?;
+            // the open files are copied with the changes that are not saved
+            let open = self.tree.iter();
+            for (file, crdt) in open.filter(|(file, _)| file.starts_with(path.as_str())) {
+                let copy = ProjectPath::new(&file.replacen(path.as_str(), new_path.as_str(), 1))?;
+                fs::write(on_disk(&copy)?, crdt.read()?)?;
             }
         }
-
-        Ok(())
+        self.paths.copy(&path, &new_path, true)
     }
+
     /// should be ending with '/'
-    fn make_dir(&mut self, path: String) -> Res<()> {
+    fn make_dir(&mut self, path: ProjectPath) -> Res<()> {
+        let path = path.into_dir();
         if self.in_dir(&path) {
             return Err(Error::new(
                 io::ErrorKind::AlreadyExists,
@@ -349,33 +466,18 @@ impl PrivateServerFn for FileTree {
             ));
         }
         #[cfg(not(test))]
-        fs::create_dir_all(&path)?;
-        if let Ok(i) = self.emty_dirs.binary_search_by(|x| {
-            if path.starts_with(x) {
-                std::cmp::Ordering::Equal
-            } else {
-                x.cmp(&path)
-            }
-        }) {
-            self.emty_dirs.remove(i);
-        }
-
-        // path in an emty_directory
-        match self.emty_dirs.binary_search(&path) {
-            Ok(_) => unreachable!(),
-            Err(i) => {
-                self.emty_dirs.insert(i, path.clone());
-            }
-        }
-
-        Ok(())
+        fs::create_dir_all(on_disk(&path)?)?;
+        self.paths.make_dir(&path, true)
     }
 
-    fn save_buf(&mut self, path: String) -> Res<()> {
-        if self.files.binary_search(&path).is_err() {
+    fn save_buf(&mut self, path: ProjectPath) -> Res<()> {
+        if !self.is_file(&path) {
             Err(Error::new(io::ErrorKind::NotFound, "file is not found"))
-        } else if let Some(_file) = self.tree.get(&path) {
-            File::create(&path)?.write_all(self.read_buf(&path)?.as_slice())?;
+        } else if self.tree.contains_key(path.as_str()) {
+            File::create(on_disk(&path)?)?.write_all(self.read_buf(&path)?.as_slice())?;
+            if let Some(file) = self.tree.get_mut(path.as_str()) {
+                file.mark_saved();
+            }
             Ok(())
         } else {
             Err(Error::new(
@@ -387,17 +489,18 @@ impl PrivateServerFn for FileTree {
 
     fn update_buf(
         &mut self,
-        path: String,
+        path: ProjectPath,
         changes: &[u8],
         old_head_idx: usize,
         heads: &[Vec<[u8; 32]>],
     ) -> Res<()> {
         // here error should be sent but in the case of client there shouldn't be any erros
-        if self.files.binary_search(&path).is_err() {
+        if !self.is_file(&path) {
             return Err(Error::new(io::ErrorKind::NotFound, "File Not Found"));
         }
-        if let Some(file) = self.tree.get_mut(&path) {
-            let _ = file.update(changes, old_head_idx, heads);
+        if let Some(file) = self.tree.get_mut(path.as_str()) {
+            // the edit is rejected (and not sent to the others) if it can't be applied
+            file.update(changes, old_head_idx, heads)?;
             Ok(())
         } else {
             Err(Error::new(
@@ -407,16 +510,16 @@ impl PrivateServerFn for FileTree {
         }
     }
 
-    fn get_automerge(&mut self, path: &String) -> Res<(Vec<u8>, Vec<Vec<[u8; 32]>>, usize)> {
-        if self.files.binary_search(path).is_err() {
+    fn get_automerge(&mut self, path: &ProjectPath) -> Res<SavedCrdt> {
+        if !self.is_file(path) {
             Err(Error::new(
                 io::ErrorKind::NotFound,
                 format!("file is not found {path}"),
             ))
-        } else if let Some(file) = self.tree.get(path) {
+        } else if let Some(file) = self.tree.get(path.as_str()) {
             Ok(file.save())
         } else {
-            let _ = PrivateServerFn::open_file(self, path.clone());
+            PrivateServerFn::open_file(self, path.clone())?;
             self.get_automerge(path)
         }
     }
@@ -424,47 +527,69 @@ impl PrivateServerFn for FileTree {
 
 impl PubServerFn for FileTree {
     fn build_file_tree() -> Self {
-        // get all files
-        use walkdir::WalkDir;
-        let mut files = WalkDir::new("./")
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
-            .map(|e| e.path().display().to_string().replace("\\", "/"))
-            // make it unix style paths
-            .collect::<Vec<String>>();
-        files.sort_unstable();
-        let mut res = Self {
-            files,
-            tree: HashMap::new(),
-            emty_dirs: Vec::new(),
+        // get all files and directories (and the links with the symlink policy)
+        let entries = match config().share {
+            ShareMode::Everything => disk::walk_tree("./"),
+            // nothing is shared at first if the host selects what is shared
+            ShareMode::Selected => vec![],
         };
-        fn is_directory_empty(path: &str) -> Res<bool> {
-            let mut entries = fs::read_dir(path)?;
-            Ok(entries.next().is_none())
-        }
-        let mut emty_dirs = WalkDir::new("./")
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_dir())
-            .map(|e| e.path().display().to_string().replace("\\", "/") + "/") // this result in problems is "./"
-            // directory
-            .filter(|e| is_directory_empty(e).unwrap_or(false))
-            .collect::<Vec<String>>();
-        emty_dirs.sort_unstable();
-
-        // this is the fix of the "./" problem
-        if let Ok(i) = emty_dirs.binary_search(&".//".to_string()) {
-            emty_dirs.remove(i);
-        }
-        // if the current directory is empty
-        if is_directory_empty("./").unwrap() {
-            if let Err(i) = emty_dirs.binary_search(&"./".to_string()) {
-                emty_dirs.insert(i, "./".to_string());
-            }
-        }
-        res.emty_dirs = emty_dirs;
-        res
+        let mut paths = DirTree::new();
+        for (path, entry) in entries {
+            // the parents are always walked before their content
+            let _ = match entry {
+                Entry::Dir => paths.make_dir(&path, true),
+                Entry::File(meta) => paths.insert(&path, Node::File(meta), false),
+                Entry::Link(target) => paths.insert(&path, Node::Link(target), false),
+            };
+        }
+        Self {
+            tree: HashMap::new(),
+            views: HashMap::new(),
+            pending: HashMap::new(),
+            loading: HashMap::new(),
+            trash: Trash::default(),
+            paths,
+        }
+    }
+
+    fn sync(&mut self, change: &RPC) -> Res<()> {
+        match change {
+            RPC::CreateFile { path, mode, .. } => {
+                let meta = FileMeta::with_mode(*mode);
+                self.paths.insert(path, Node::File(meta), true)
+            }
+            RPC::UpdateMetadata { entry } => self.set_meta(entry.clone()),
+            RPC::CreateDirectory { path } => self.paths.make_dir(path, true),
+            RPC::CreateLink { path, target } => {
+                self.paths.insert(path, Node::Link(target.clone()), true)
+            }
+            RPC::DeleteFile { path } => {
+                self.paths.remove(path)?;
+                self.tree.remove(path.as_str());
+                Ok(())
+            }
+            RPC::DeleteDirectory { path } => {
+                self.paths.remove(path)?;
+                self.tree.retain(|file, _| !file.starts_with(path.as_str()));
+                Ok(())
+            }
+            RPC::MoveFile { path, new_path } => {
+                self.paths.rename(path, new_path, true)?;
+                if let Some(file) = self.tree.remove(path.as_str()) {
+                    self.tree.insert(new_path.to_string(), file);
+                }
+                Ok(())
+            }
+            RPC::MoveDirectory { path, new_path } => {
+                self.paths.rename(path, new_path, true)?;
+                self.move_loaded(&path.clone().into_dir(), &new_path.clone().into_dir());
+                Ok(())
+            }
+            rpc => Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{rpc:?} is not a change of the disk"),
+            )),
+        }
     }
 
     /// this handles the message from the client or the server and returns the response
@@ -472,18 +597,16 @@ impl PubServerFn for FileTree {
     async fn handle_msg(
         &mut self,
         tx: RPC,
-        priviledge: Option<Priviledge>,
+        permissions: Option<&Permissions>,
         username: &str,
-    ) -> Result<Message, ()> {
-        match tx {
-            RPC::EditBuffer { .. } | RPC::ReqSaveFile { .. }
-                if priviledge.is_some() && priviledge.unwrap() == Priviledge::ReadOnly =>
-            {
-                log::error!("Unauthorized access by user {username}");
-                log::error!("user trying to edit file without access {username}");
-                Err(())
+    ) -> Result<Message, String> {
+        if let Some(permissions) = permissions {
+            if let Err(e) = permissions.authorize(&tx) {
+                log::error!("Unauthorized access by user {username}: {e}");
+                return Err(e.to_string());
             }
-
+        }
+        match tx {
             RPC::EditBuffer {
                 path,
                 changes,
@@ -511,35 +634,24 @@ impl PubServerFn for FileTree {
                 Ok(rpc.encode().map_err(Self::err_msg)?)
             }
 
-            RPC::CreateFile { .. }
-            | RPC::CreateDirectory { .. }
-            | RPC::DeleteFile { .. }
-            | RPC::DeleteDirectory { .. }
-            | RPC::MoveFile { .. }
-            | RPC::MoveDirectory { .. }
-                if priviledge.is_some() && priviledge.unwrap() == Priviledge::ReadOnly =>
-            {
-                log::error!("Unauthorized access by user {username}");
-                log::error!("user trying to edit directory structure without access {username}");
-                // client
-                //     .unwrap()
-                //     .send_message(
-                //         RPC::Error("Unauthorized access".to_string())
-                //             .encode()
-                //             .map_err(Self::err_msg)?,
-                //     )
-                //     .await
-                //     .map_err(Self::err_msg)?; // await is needed as I think
-                Err(())
-            }
-
-            RPC::CreateFile { path } => {
-                self.create_file(path.clone()).map_err(Self::err_msg)?;
-                let rpc = RPC::CreateFile { path };
+            RPC::CreateFile {
+                path,
+                mode,
+                content,
+            } => {
+                self.create_file(path.clone(), mode, content)
+                    .map_err(Self::err_msg)?;
+                // the others get the content when they open the file
+                let rpc = RPC::CreateFile {
+                    path,
+                    mode,
+                    content: None,
+                };
                 Ok(rpc.encode().unwrap())
             }
 
             RPC::CreateDirectory { path } => {
+                let path = path.into_dir();
                 self.make_dir(path.clone()).map_err(Self::err_msg)?;
                 let rpc = RPC::CreateDirectory { path };
                 Ok(rpc.encode().map_err(Self::err_msg)?)
@@ -553,24 +665,59 @@ impl PubServerFn for FileTree {
             }
 
             RPC::MoveDirectory { path, new_path } => {
+                let (path, new_path) = (path.into_dir(), new_path.into_dir());
+                roots::check_not_root(&path).map_err(Self::err_msg)?;
+                roots::check_not_root(&new_path).map_err(Self::err_msg)?;
                 self.move_dir(path.clone(), new_path.clone())
                     .map_err(Self::err_msg)?;
                 let rpc = RPC::MoveDirectory { path, new_path };
                 Ok(rpc.encode().map_err(Self::err_msg)?)
             }
 
+            RPC::CopyFile { path, new_path } => {
+                self.copy_file(path.clone(), new_path.clone())
+                    .map_err(Self::err_msg)?;
+                let rpc = RPC::CopyFile { path, new_path };
+                Ok(rpc.encode().map_err(Self::err_msg)?)
+            }
+
+            RPC::CopyDirectory { path, new_path } => {
+                let (path, new_path) = (path.into_dir(), new_path.into_dir());
+                roots::check_not_root(&path).map_err(Self::err_msg)?;
+                roots::check_not_root(&new_path).map_err(Self::err_msg)?;
+                self.copy_dir(path.clone(), new_path.clone())
+                    .map_err(Self::err_msg)?;
+                let rpc = RPC::CopyDirectory { path, new_path };
+                Ok(rpc.encode().map_err(Self::err_msg)?)
+            }
+
+            // the deleted paths could be restored until the trash is purged
             RPC::DeleteFile { path } => {
-                self.rm_file(path.clone()).map_err(Self::err_msg)?;
+                self.trash(path.clone(), username).map_err(Self::err_msg)?;
                 let rpc = RPC::DeleteFile { path };
                 Ok(rpc.encode().map_err(Self::err_msg)?)
             }
 
             RPC::DeleteDirectory { path } => {
-                self.rm_dir(path.clone()).map_err(Self::err_msg)?;
+                let path = path.into_dir();
+                roots::check_not_root(&path).map_err(Self::err_msg)?;
+                self.trash(path.clone(), username).map_err(Self::err_msg)?;
                 let rpc = RPC::DeleteDirectory { path };
                 Ok(rpc.encode().map_err(Self::err_msg)?)
             }
 
+            RPC::Restore { path } => {
+                let id = self.trashed_at(&path).ok_or_else(|| {
+                    Self::err_msg(Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("{path} is not in the trash"),
+                    ))
+                })?;
+                let path = self.restore(id).map_err(Self::err_msg)?;
+                let rpc = RPC::Restore { path };
+                Ok(rpc.encode().map_err(Self::err_msg)?)
+            }
+
             RPC::RequestMark {
                 path,
                 s_position,
@@ -594,33 +741,109 @@ impl PubServerFn for FileTree {
                 Ok(rpc.encode().map_err(Self::err_msg)?)
             }
 
-            RPC::ReqBufferTree { path } if priviledge.is_some() => {
-                let result = self.get_automerge(&path).map_err(Self::err_msg)?;
-
+            RPC::ReqBufferTree { path } | RPC::OpenFile { path } if permissions.is_some() => {
+                let chunk_size = config().chunk_size;
+                let opened = self.tree.contains_key(path.as_str());
+                if !opened
+                    && self.is_file(&path)
+                    && open_mode(&path).map_err(Self::err_msg)? == OpenMode::ViewOnly
+                {
+                    // the file is sent from the disk without making a crdt
+                    let file = tokio::fs::File::open(on_disk(&path).map_err(Self::err_msg)?)
+                        .await
+                        .map_err(Self::err_msg)?;
+                    let total = file.metadata().await.map_err(Self::err_msg)?.len();
+                    let username = username.to_string();
+                    tokio::spawn(async move {
+                        let Some(binary) =
+                            send_chunked(&username, &path, file, total, chunk_size).await
+                        else {
+                            return;
+                        };
+                        let rpc = RPC::ResSendFile {
+                            path,
+                            file: vec![],
+                            heads_history: vec![],
+                            head_idx: 0,
+                            binary,
+                            view_only: true,
+                            chunked: true,
+                        };
+                        let msg = rpc.encode().unwrap();
+                        if let Err(e) = messageing::send_to_client_when_ready(&username, msg).await
+                        {
+                            log::error!("failed to send the header of a file to {username}: {e}");
+                        }
+                    });
+                    return Ok(messageing::RESET_WAITING);
+                }
+                let (file, heads_history, head_idx) =
+                    self.get_automerge(&path).map_err(Self::err_msg)?;
+                let crdt = self.tree.get(path.as_str());
+                let binary = crdt.is_some_and(Crdt::is_binary);
+                if file.len() > chunk_size {
+                    // the edits are not sent to the client until it gets the header,
+                    // the header has the edits that are made while the chunks are sent
+                    let heads = crdt.map(Crdt::heads).unwrap_or_default();
+                    let loading = self.loading.entry(path.to_string()).or_default();
+                    loading.insert(username.to_string());
+                    let total = file.len() as u64;
+                    let username = username.to_string();
+                    tokio::spawn(async move {
+                        let file = io::Cursor::new(file);
+                        if send_chunked(&username, &path, file, total, chunk_size)
+                            .await
+                            .is_some()
+                        {
+                            messageing::send_to_api(messageing::Inbox::Loaded {
+                                username,
+                                path,
+                                heads,
+                            });
+                        }
+                    });
+                    return Ok(messageing::RESET_WAITING);
+                }
                 let rpc = RPC::ResSendFile {
                     path,
-                    file: result.0,
-                    heads_history: result.1,
-                    head_idx: result.2,
+                    file,
+                    heads_history,
+                    head_idx,
+                    binary,
+                    view_only: false,
+                    chunked: false,
                 };
-                use crate::server::variables::CLIENTS_SEND;
-                let clients_send = CLIENTS_SEND.lock().await;
-                if let Some(client) = clients_send.get(username) {
-                    client
-                        .lock()
-                        .await
-                        .send(rpc.encode().map_err(Self::err_msg)?)
-                        .await
-                        .map_err(Self::err_msg)?;
-                } // this should be an error
-
-                drop(clients_send);
+                messageing::send_to_client(username, rpc.encode().map_err(Self::err_msg)?)
+                    .await
+                    .map_err(Self::err_msg)?;
                 Ok(messageing::RESET_WAITING) // this mean that reset the message awaiting
             }
+            RPC::ReqListDir {
+                path,
+                depth,
+                cursor,
+                limit,
+            } if permissions.is_some() => {
+                let listing = self
+                    .list_dir(&path, depth, cursor.as_ref(), limit)
+                    .map_err(Self::err_msg)?;
+                let rpc = RPC::ResListDir {
+                    path,
+                    files: listing.files,
+                    emty_dirs: listing.emty_dirs,
+                    dirs: listing.dirs,
+                    links: listing.links,
+                    next_cursor: listing.next_cursor,
+                };
+                messageing::send_to_client(username, rpc.encode().map_err(Self::err_msg)?)
+                    .await
+                    .map_err(Self::err_msg)?;
+                Ok(messageing::RESET_WAITING)
+            }
             RPC::Undo { path } => {
                 let file = self
                     .tree
-                    .get_mut(&path)
+                    .get_mut(path.as_str())
                     .ok_or_else(|| Error::new(io::ErrorKind::NotFound, "file is not found"))
                     .map_err(Self::err_msg)?;
                 file.undo();
@@ -630,49 +853,68 @@ impl PubServerFn for FileTree {
             RPC::Redo { path } => {
                 let file = self
                     .tree
-                    .get_mut(&path)
+                    .get_mut(path.as_str())
                     .ok_or_else(|| Error::new(io::ErrorKind::NotFound, "file is not found"))
                     .map_err(Self::err_msg)?;
                 file.redo();
                 Ok(RPC::Redo { path }.encode().map_err(Self::err_msg)?)
             }
 
-            RPC::ReqBufferTree { .. } => {
+            // the subscriptions are tracked by the api
+            RPC::CloseFile { path } if permissions.is_some() => {
+                if !self.is_file(&path) {
+                    log::error!("{path} is not found");
+                    return Err(format!("{path} is not found"));
+                }
+                Ok(messageing::RESET_WAITING)
+            }
+
+            RPC::ReqBufferTree { .. }
+            | RPC::OpenFile { .. }
+            | RPC::CloseFile { .. }
+            | RPC::ReqListDir { .. } => {
                 // if this mean that this is server sent as the Some(client) is false
                 log::error!("unhandled message {:?}", tx);
                 log::info!("this is should only be send by the client");
-                Err(())
+                Err("this should only be sent by a client".to_string())
             }
 
             RPC::ResConnect { .. } => {
                 log::error!("unhandled message {:?}", tx);
-                Err(())
+                Err("this is a server message".to_string())
             }
-            RPC::ChangePriviledge { .. } => {
+            RPC::ChangePriviledge { .. } | RPC::ReqAccess { .. } | RPC::AccessRequested { .. } => {
                 log::error!("unhandled message {:?}", tx);
                 log::info!("client trying to change priviledge");
-                Err(())
+                Err("the priviledge could only be changed by the host".to_string())
             }
             RPC::ResSendFile { .. }
+            | RPC::ResFileChunk { .. }
+            | RPC::ResListDir { .. }
             | RPC::ResMoveCursor { .. }
             | RPC::ResMark { .. }
-            | RPC::FileSaved { .. } => {
+            | RPC::FileSaved { .. }
+            | RPC::CreateLink { .. }
+            | RPC::UpdateMetadata { .. }
+            | RPC::Rejected { .. }
+            | RPC::UserJoined { .. }
+            | RPC::UserLeft { .. } => {
                 log::error!("unhandled message {:?}", tx);
                 log::info!("this is a server message");
-                Err(())
+                Err("this is a server message".to_string())
             }
             RPC::Error(e) => {
                 log::info!("error occurred: {} ", e);
-                Err(())
+                Err(e)
             }
             RPC::AddUsername { .. } => {
                 log::error!("unhandled message {:?}", tx);
                 log::info!("this should be send in connection request only");
-                Err(())
+                Err("this should be sent in the connection request only".to_string())
             }
         }
     }
-    fn open_file(&mut self, path: String) -> Res<()> {
+    fn open_file(&mut self, path: ProjectPath) -> Res<()> {
         PrivateServerFn::open_file(self, path)
     }
 }
@@ -0,0 +1,195 @@
+use std::collections::BTreeSet;
+
+use super::{handle, FileTree, PrivateServerFn};
+use crate::communication::rpc::RPC;
+
+/// a simple model of the file tree that the tree is checked against
+/// `dirs` has every directory except the root
+#[derive(Default)]
+struct Model {
+    files: BTreeSet<String>,
+    dirs: BTreeSet<String>,
+}
+
+impl Model {
+    fn dir_exists(&self, dir: &str) -> bool {
+        dir == "./" || self.dirs.contains(dir)
+    }
+    fn exists(&self, path: &str) -> bool {
+        let path = path.trim_end_matches('/');
+        self.files.contains(path) || self.dirs.contains(&format!("{path}/"))
+    }
+    /// the parents of a path (without the root) e.g `./a/b/c` -> `./a/`, `./a/b/`
+    fn parents(path: &str) -> Vec<String> {
+        let parts = path.trim_end_matches('/').split('/').collect::<Vec<_>>();
+        (2..parts.len())
+            .map(|i| parts[..i].join("/") + "/")
+            .collect()
+    }
+    fn parent(path: &str) -> String {
+        Self::parents(path).pop().unwrap_or("./".to_string())
+    }
+    fn blocked_by_file(&self, path: &str) -> bool {
+        Self::parents(path)
+            .iter()
+            .chain([path.to_string()].iter())
+            .any(|x| self.files.contains(x.trim_end_matches('/')))
+    }
+
+    fn create_file(&mut self, path: &str) -> bool {
+        if !self.dir_exists(&Self::parent(path)) || self.exists(path) {
+            return false;
+        }
+        self.files.insert(path.to_string())
+    }
+    fn rm_file(&mut self, path: &str) -> bool {
+        self.files.remove(path)
+    }
+    fn move_file(&mut self, old: &str, new: &str) -> bool {
+        if !self.files.contains(old) || !self.dir_exists(&Self::parent(new)) || self.exists(new) {
+            return false;
+        }
+        self.files.remove(old);
+        self.files.insert(new.to_string())
+    }
+    fn make_dir(&mut self, path: &str) -> bool {
+        if self.dir_exists(path) || self.blocked_by_file(path) {
+            return false;
+        }
+        self.dirs.extend(Self::parents(path));
+        self.dirs.insert(path.to_string())
+    }
+    fn rm_dir(&mut self, path: &str) -> bool {
+        if !self.dirs.contains(path) {
+            return false;
+        }
+        self.files.retain(|x| !x.starts_with(path));
+        self.dirs.retain(|x| !x.starts_with(path));
+        true
+    }
+    fn move_dir(&mut self, old: &str, new: &str) -> bool {
+        if new.starts_with(old)
+            || !self.dirs.contains(old)
+            || self.exists(new)
+            || self.blocked_by_file(new)
+        {
+            return false;
+        }
+        let repath = |x: &String| {
+            if x.starts_with(old) {
+                x.replacen(old, new, 1)
+            } else {
+                x.clone()
+            }
+        };
+        self.files = self.files.iter().map(repath).collect();
+        self.dirs = self.dirs.iter().map(repath).collect();
+        self.dirs.extend(Self::parents(new));
+        true
+    }
+
+    fn maps(&self) -> (Vec<String>, Vec<String>) {
+        let files = self.files.iter().cloned().collect::<Vec<_>>();
+        if files.is_empty() && self.dirs.is_empty() {
+            return (files, vec!["./".to_string()]);
+        }
+        let emty_dirs = self
+            .dirs
+            .iter()
+            .filter(|dir| {
+                !self.files.iter().any(|x| x.starts_with(*dir))
+                    && !self.dirs.iter().any(|x| x != *dir && x.starts_with(*dir))
+            })
+            .cloned()
+            .collect();
+        (files, emty_dirs)
+    }
+}
+
+/// xorshift, enough for generating random operations
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+    /// a path with a depth between 1 and 3 from a small set of names
+    /// so that the operations collide often
+    fn path(&mut self, dir: bool) -> String {
+        const NAMES: [&str; 3] = ["a", "b", "c"];
+        let depth = self.next(3) + 1;
+        let parts = (0..depth).map(|_| NAMES[self.next(NAMES.len())]);
+        let path = format!("./{}", parts.collect::<Vec<_>>().join("/"));
+        if dir {
+            path + "/"
+        } else {
+            path
+        }
+    }
+}
+
+fn fuzz(seed: u64, steps: usize) {
+    let mut rng = Rng(seed);
+    let mut ft = FileTree::new(vec![], vec![]);
+    let mut model = Model::default();
+    for step in 0..steps {
+        let (op, res, expected) = match rng.next(6) {
+            0 => {
+                let path = rng.path(false);
+                let res = ft.create_file(super::path(&path), None, None).is_ok();
+                (format!("create_file {path}"), res, model.create_file(&path))
+            }
+            1 => {
+                let path = rng.path(false);
+                let rpc = RPC::DeleteFile {
+                    path: super::path(&path),
+                };
+                let res = handle(&mut ft, rpc).is_ok();
+                (format!("rm_file {path}"), res, model.rm_file(&path))
+            }
+            2 => {
+                let (old, new) = (rng.path(false), rng.path(false));
+                let res = ft.move_file(super::path(&old), super::path(&new)).is_ok();
+                (
+                    format!("move_file {old} {new}"),
+                    res,
+                    model.move_file(&old, &new),
+                )
+            }
+            3 => {
+                let path = rng.path(true);
+                let res = ft.make_dir(super::path(&path)).is_ok();
+                (format!("make_dir {path}"), res, model.make_dir(&path))
+            }
+            4 => {
+                let path = rng.path(true);
+                let rpc = RPC::DeleteDirectory {
+                    path: super::path(&path),
+                };
+                let res = handle(&mut ft, rpc).is_ok();
+                (format!("rm_dir {path}"), res, model.rm_dir(&path))
+            }
+            _ => {
+                let (old, new) = (rng.path(true), rng.path(true));
+                let res = ft.move_dir(super::path(&old), super::path(&new)).is_ok();
+                (
+                    format!("move_dir {old} {new}"),
+                    res,
+                    model.move_dir(&old, &new),
+                )
+            }
+        };
+        assert_eq!(res, expected, "seed {seed} step {step}: {op}");
+        assert_eq!(ft.get_maps(), model.maps(), "seed {seed} step {step}: {op}");
+    }
+}
+
+#[test]
+fn fuzz_random_operations() {
+    for seed in 1..=20u64 {
+        fuzz(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15), 500);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, thread::sleep, time::Duration};
+use std::collections::HashMap;
 
 use super::*;
 use ctor::ctor;
@@ -64,18 +64,26 @@ fn assert_vec(ft: FileTree, files: Option<&[String]>, emty_dirs: Option<&[String
         assert!(is_sorted(ft_data));
         assert!(contains_all(ft_data, array));
     };
-    f(&ft.files, files);
-    f(&ft.emty_dirs, emty_dirs);
+    let (ft_files, ft_emty_dirs) = ft.get_maps();
+    f(&ft_files, files);
+    f(&ft_emty_dirs, emty_dirs);
+}
+fn path(path: &str) -> ProjectPath {
+    ProjectPath::new(path).unwrap()
+}
+/// apply an rpc of the host like the api does (the deleted paths are moved to the trash)
+fn handle(ft: &mut FileTree, rpc: RPC) -> Result<Message, String> {
+    futures::executor::block_on(ft.handle_msg(rpc, None, "host"))
 }
 impl FileTree {
-    pub fn new(mut files: Vec<String>, mut emty_dirs: Vec<String>) -> Self {
-        let tree = HashMap::new();
-        files.sort_unstable();
-        emty_dirs.sort_unstable();
+    pub fn new(files: Vec<String>, emty_dirs: Vec<String>) -> Self {
         Self {
-            tree,
-            files,
-            emty_dirs,
+            tree: HashMap::new(),
+            views: HashMap::new(),
+            pending: HashMap::new(),
+            loading: HashMap::new(),
+            trash: Trash::default(),
+            paths: DirTree::from_paths(files, emty_dirs),
         }
     }
 }
@@ -85,16 +93,16 @@ impl FileTree {
 fn right_naming() {
     fs::create_dir("./empty_dir/").unwrap();
     let res = panic::catch_unwind(|| {
-        let res = FileTree::build_file_tree();
-        for i in res.emty_dirs.iter() {
+        let (files, emty_dirs) = FileTree::build_file_tree().get_maps();
+        for i in emty_dirs.iter() {
             assert!(FileTree::valid_dir_path(i));
         }
-        dbg!(&res.emty_dirs);
-        res.emty_dirs
+        dbg!(&emty_dirs);
+        emty_dirs
             .binary_search(&"./empty_dir/".to_string())
             .unwrap();
-        dbg!(&res.emty_dirs);
-        for i in res.files.iter() {
+        dbg!(&emty_dirs);
+        for i in files.iter() {
             assert!(File::open(i).is_ok());
         }
     });
@@ -102,5 +110,6 @@ fn right_naming() {
     res.unwrap();
 }
 
+mod fuzz_test;
 mod server_dir_test;
 mod server_files_test;
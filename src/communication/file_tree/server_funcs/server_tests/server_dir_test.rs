@@ -1,5 +1,12 @@
-use super::{assert_vec, FILES};
+use super::{assert_vec, handle, path, FILES};
 use super::{FileTree, PrivateServerFn};
+use crate::communication::file_entry::FileEntry;
+use crate::communication::rpc::RPC;
+
+/// the paths of the listed files
+fn paths(files: &[FileEntry]) -> Vec<String> {
+    files.iter().map(|file| file.path.to_string()).collect()
+}
 
 // to do explane the tests
 #[test]
@@ -7,7 +14,10 @@ fn remove_dir() {
     let mut files = FILES.clone();
     let mut ft = FileTree::new(files.clone(), vec![]);
 
-    ft.rm_dir("./dir1/".to_string()).unwrap();
+    let rpc = RPC::DeleteDirectory {
+        path: path("./dir1/"),
+    };
+    handle(&mut ft, rpc).unwrap();
 
     files.retain(|x| !x.starts_with("./dir1/"));
 
@@ -21,7 +31,10 @@ fn remove_emty_dir() {
     let mut emty_dirs = vec!["./empty_dir/".to_string()];
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
 
-    ft.rm_dir("./empty_dir/".to_string()).unwrap();
+    let rpc = RPC::DeleteDirectory {
+        path: path("./empty_dir/"),
+    };
+    handle(&mut ft, rpc).unwrap();
     emty_dirs.clear();
     assert_vec(ft, Some(&files), Some(&emty_dirs));
 }
@@ -31,7 +44,10 @@ fn remove_dir_resulting_in_empty_dir() {
     let mut files = FILES.clone();
     let mut ft = FileTree::new(files.clone(), vec![]);
 
-    ft.rm_dir("./dir_with_one_dir/dir_with_one_file/".to_string()).unwrap();
+    let rpc = RPC::DeleteDirectory {
+        path: path("./dir_with_one_dir/dir_with_one_file/"),
+    };
+    handle(&mut ft, rpc).unwrap();
 
     let emty_dirs = vec!["./dir_with_one_dir/".to_string()];
 
@@ -46,7 +62,10 @@ fn removing_emty_dir_resulting_in_empty_dir() {
     let emty_dirs = vec!["./empty_dir/empty_dir/".to_string()];
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
 
-    ft.rm_dir("./empty_dir/empty_dir/".to_string()).unwrap();
+    let rpc = RPC::DeleteDirectory {
+        path: path("./empty_dir/empty_dir/"),
+    };
+    handle(&mut ft, rpc).unwrap();
     let emty_dirs = vec!["./empty_dir/".to_string()];
     assert_vec(ft, Some(&files), Some(&emty_dirs));
 }
@@ -57,7 +76,7 @@ fn make_dir_expeted_err() {
     let mut emty_dirs = vec!["./empty_dir/".to_string()];
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
 
-    ft.make_dir("./dir1/".to_string()).unwrap_err();
+    ft.make_dir(path("./dir1/")).unwrap_err();
 
     emty_dirs.sort();
     assert_vec(ft, Some(&files), Some(&emty_dirs));
@@ -69,8 +88,7 @@ fn move_dir() {
     let mut files = FILES.clone();
     let emty_dirs = vec!["./empty_dir/".to_string()];
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
-    ft.move_dir("./dir1/".to_string(), "./hi/".to_string())
-        .unwrap();
+    ft.move_dir(path("./dir1/"), path("./hi/")).unwrap();
     files.retain(|x| !x.starts_with("./dir1/"));
     // "./dir1/file1.txt"
     // "./dir1/file2.txt"
@@ -94,7 +112,7 @@ fn make_dir_to_sibling_empty_dir() {
     let mut emty_dirs = vec!["./not_empty_dir/emty_dir/".to_string()];
 
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
-    ft.make_dir("./not_empty_dir/dir1/".to_string()).unwrap();
+    ft.make_dir(path("./not_empty_dir/dir1/")).unwrap();
     emty_dirs.push("./not_empty_dir/dir1/".to_string());
     emty_dirs.sort();
     assert_vec(ft, Some(&files), Some(&emty_dirs));
@@ -107,7 +125,7 @@ fn make_dir_inside_an_emty_dir() {
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
     emty_dirs.clear();
     emty_dirs.push("./empty_dir/empty_dir/".to_string());
-    ft.make_dir("./empty_dir/empty_dir/".to_string()).unwrap();
+    ft.make_dir(path("./empty_dir/empty_dir/")).unwrap();
     assert_vec(ft, Some(&files), Some(&emty_dirs));
 }
 #[test]
@@ -118,7 +136,141 @@ fn rename_emty_dir() {
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
     emty_dirs.clear();
     emty_dirs.push("./hi/".to_string());
-    ft.move_dir("./empty_dir/".to_string(), "./hi/".to_string())
-        .unwrap();
+    ft.move_dir(path("./empty_dir/"), path("./hi/")).unwrap();
     assert_vec(ft, Some(&files), Some(&emty_dirs));
 }
+
+#[test]
+fn list_root_dir() {
+    let files = FILES.clone();
+    let emty_dirs = vec!["./empty_dir/".to_string()];
+    let ft = FileTree::new(files, emty_dirs.clone());
+
+    let listing = ft.list_dir(&path("./"), Some(1), None, None).unwrap();
+
+    assert_eq!(paths(&listing.files), vec!["./file.txt".to_string()]);
+    assert_eq!(listing.emty_dirs, emty_dirs);
+    assert_eq!(
+        listing.dirs,
+        [
+            "./dir0/",
+            "./dir1/",
+            "./dir2/",
+            "./dir3/",
+            "./dir_with_one_dir/",
+            "./dir_with_one_file/"
+        ]
+        .map(String::from)
+    );
+    assert_eq!(listing.next_cursor, None);
+}
+
+#[test]
+fn list_dir_with_depth() {
+    let files = FILES.clone();
+    let emty_dirs = vec!["./dir0/dir1/empty_dir/".to_string()];
+    let ft = FileTree::new(files, emty_dirs.clone());
+
+    let listing = ft.list_dir(&path("./dir0/"), Some(2), None, None).unwrap();
+    assert_eq!(
+        paths(&listing.files),
+        [
+            "./dir0/dir1/file1.txt",
+            "./dir0/dir1/file2.txt",
+            "./dir0/file1.txt"
+        ]
+        .map(String::from)
+    );
+    assert_eq!(listing.emty_dirs, emty_dirs);
+    assert_eq!(listing.dirs, vec!["./dir0/dir1/subdir/".to_string()]);
+
+    let listing = ft.list_dir(&path("./dir0/"), None, None, None).unwrap();
+    assert_eq!(listing.files.len(), 4);
+    assert!(listing.dirs.is_empty());
+}
+
+#[test]
+fn list_dir_pages() {
+    let files = FILES.clone();
+    let ft = FileTree::new(files.clone(), vec![]);
+
+    let mut listed = vec![];
+    let mut cursor = None;
+    loop {
+        let listing = ft
+            .list_dir(&path("./"), None, cursor.as_ref(), Some(5))
+            .unwrap();
+        assert!(listing.files.len() <= 5);
+        listed.extend(paths(&listing.files));
+        cursor = listing.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(listed, files);
+
+    // the next page starts after the cursor even if it is deleted
+    let mut ft = FileTree::new(files.clone(), vec![]);
+    let first = ft.list_dir(&path("./"), None, None, Some(2)).unwrap();
+    let cursor = first.next_cursor.unwrap();
+    ft.paths.remove(&cursor).unwrap();
+    let rest = ft.list_dir(&path("./"), None, Some(&cursor), None).unwrap();
+    let mut listed = paths(&first.files);
+    listed.extend(paths(&rest.files));
+    assert_eq!(listed, files);
+}
+
+#[test]
+fn list_missing_dir() {
+    let ft = FileTree::new(FILES.clone(), vec![]);
+    ft.list_dir(&path("./not_a_dir/"), None, None, None)
+        .unwrap_err();
+}
+
+#[test]
+fn copy_dir() {
+    let mut files = FILES.clone();
+    let mut ft = FileTree::new(files.clone(), vec![]);
+
+    ft.copy_dir(path("./dir0/"), path("./new/dir0/")).unwrap();
+    ft.copy_dir(path("./dir0/"), path("./dir0/dir1/copy/"))
+        .unwrap_err();
+    ft.copy_dir(path("./dir0/"), path("./dir1/")).unwrap_err();
+
+    let copies: Vec<String> = files
+        .iter()
+        .filter(|file| file.starts_with("./dir0/"))
+        .map(|file| file.replacen("./dir0/", "./new/dir0/", 1))
+        .collect();
+    files.extend(copies);
+    files.sort();
+    assert_vec(ft, Some(&files), None);
+}
+
+#[test]
+fn move_nested_and_empty_dirs() {
+    let mut files = FILES.clone();
+    let mut ft = FileTree::new(files.clone(), vec!["./empty_dir/".to_string()]);
+    let content = b"open".to_vec();
+    ft.create_file(
+        path("./dir0/dir1/subdir/open.txt"),
+        None,
+        Some(content.clone()),
+    )
+    .unwrap();
+
+    ft.move_dir(path("./dir0/"), path("./a/b/")).unwrap();
+    // the open file is kept in the new path
+    assert_eq!(ft.read_buf("./a/b/dir1/subdir/open.txt").unwrap(), content);
+    assert!(ft.tree.keys().all(|file| !file.starts_with("./dir0/")));
+
+    ft.move_dir(path("./empty_dir/"), path("./a/empty/"))
+        .unwrap();
+    files.push("./dir0/dir1/subdir/open.txt".to_string());
+    let mut files: Vec<String> = files
+        .into_iter()
+        .map(|file| file.replacen("./dir0/", "./a/b/", 1))
+        .collect();
+    files.sort();
+    assert_vec(ft, Some(&files), Some(&["./a/empty/".to_string()]));
+}
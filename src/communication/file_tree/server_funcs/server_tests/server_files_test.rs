@@ -6,7 +6,8 @@ use std::{
 
 use serial_test::serial;
 
-use super::{assert_vec, FileTree, PrivateServerFn, FILES};
+use super::{assert_vec, handle, path, FileTree, PrivateServerFn, FILES};
+use crate::communication::rpc::RPC;
 // TODO: explain the tests
 #[test]
 fn create_file_change_in_emty_dir() {
@@ -14,7 +15,8 @@ fn create_file_change_in_emty_dir() {
     let mut emty_dirs = vec!["./empty_dir/".to_string()];
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
 
-    ft.create_file("./empty_dir/file1.txt".to_string()).unwrap();
+    ft.create_file(path("./empty_dir/file1.txt"), None, None)
+        .unwrap();
 
     files.push("./empty_dir/file1.txt".to_string());
     files.sort();
@@ -24,19 +26,58 @@ fn create_file_change_in_emty_dir() {
     assert_vec(ft, Some(&files), Some(&emty_dirs));
 }
 
+#[test]
+fn create_file_with_content() {
+    let mut ft = FileTree::new(FILES.clone(), vec![]);
+    let content = b"fn main() {}".to_vec();
+    ft.create_file(path("./dir1/main.rs"), Some(0o755), Some(content.clone()))
+        .unwrap();
+    // the file is loaded so it could be sent without reading the disk
+    assert_eq!(ft.read_buf("./dir1/main.rs").unwrap(), content);
+    let meta = ft.file_entry(&path("./dir1/main.rs")).unwrap().meta;
+    assert_eq!((meta.size, meta.mode, meta.binary), (12, 0o755, false));
+
+    ft.create_file(path("./dir1/image.png"), None, Some(vec![0x89, 0xFF]))
+        .unwrap();
+    assert!(ft.tree["./dir1/image.png"].is_binary());
+    // nothing is added if the file exists
+    ft.create_file(path("./dir1/main.rs"), None, Some(vec![]))
+        .unwrap_err();
+    assert_eq!(ft.read_buf("./dir1/main.rs").unwrap(), content);
+}
+
+#[test]
+fn copy_file() {
+    let mut files = FILES.clone();
+    let mut ft = FileTree::new(files.clone(), vec![]);
+    ft.copy_file(path("./file.txt"), path("./dir2/file.txt"))
+        .unwrap();
+    ft.copy_file(path("./file.txt"), path("./dir2/file.txt"))
+        .unwrap_err();
+    ft.copy_file(path("./missing.txt"), path("./dir2/other.txt"))
+        .unwrap_err();
+    ft.copy_file(path("./file.txt"), path("./missing/file.txt"))
+        .unwrap_err();
+
+    files.push("./dir2/file.txt".to_string());
+    files.sort();
+    assert_vec(ft, Some(&files), None);
+}
+
 #[test]
 fn create_file_without_a_dir() {
     let files = vec![];
     let emty_dirs = vec![];
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
-    ft.create_file("./dir/file1.txt".to_string()).unwrap_err();
+    ft.create_file(path("./dir/file1.txt"), None, None)
+        .unwrap_err();
 }
 #[test]
 fn create_file_in_the_main_dir() {
     let mut files = vec![];
     let emty_dirs = vec![];
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
-    ft.create_file("./file.txt".to_string()).unwrap();
+    ft.create_file(path("./file.txt"), None, None).unwrap();
 
     files.push("./file.txt".to_string());
 
@@ -49,7 +90,8 @@ fn create_file_without_a_dir_2() {
     let emty_dirs = vec!["./empty_dir/".to_string()];
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
 
-    ft.create_file("./dir1/file1.txt".to_string()).unwrap_err();
+    ft.create_file(path("./dir1/file1.txt"), None, None)
+        .unwrap_err();
 
     assert_vec(ft, Some(&files), Some(&emty_dirs));
 }
@@ -59,7 +101,8 @@ fn create_file_without_dir_in_emty_dir() {
     let emty_dirs = vec!["./empty_dir/".to_string()];
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
 
-    ft.create_file("./dir1/new_file.txt".to_string()).unwrap();
+    ft.create_file(path("./dir1/new_file.txt"), None, None)
+        .unwrap();
 
     files.push("./dir1/new_file.txt".to_string());
     files.sort();
@@ -72,7 +115,7 @@ fn create_file_without_a_dir_nested_case() {
     let emty_dirs = vec!["./empty_dir/".to_string()];
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
 
-    ft.create_file("./dir1/not_dir/file.txt".to_string())
+    ft.create_file(path("./dir1/not_dir/file.txt"), None, None)
         .unwrap_err();
 
     assert_vec(ft, Some(&files), Some(&emty_dirs));
@@ -90,7 +133,7 @@ fn open_file() {
 
     let res = panic::catch_unwind(move || {
         let mut fs = FileTree::new(files.clone(), emty_dirs.clone());
-        fs.open_file("./file.txt".to_string()).unwrap();
+        fs.open_file(path("./file.txt")).unwrap();
 
         assert_vec(fs.clone(), Some(&files), Some(&emty_dirs));
 
@@ -109,13 +152,10 @@ fn move_file_1() {
 
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
 
-    ft.move_file(
-        "./dir1/file1.txt".to_string(),
-        "./dir2/file1.txt".to_string(),
-    )
-    .unwrap();
+    ft.move_file(path("./dir1/file1.txt"), path("./dir2/file1.txt"))
+        .unwrap();
 
-    files.retain(|x| *x != "./dir1/file1.txt".to_string());
+    files.retain(|x| *x != "./dir1/file1.txt");
     files.push("./dir2/file1.txt".to_string());
     files.sort();
 
@@ -128,13 +168,10 @@ fn move_file_to_emty_dir() {
 
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
 
-    ft.move_file(
-        "./dir1/file1.txt".to_string(),
-        "./empty_dir/file1.txt".to_string(),
-    )
-    .unwrap();
+    ft.move_file(path("./dir1/file1.txt"), path("./empty_dir/file1.txt"))
+        .unwrap();
 
-    files.retain(|x| *x != "./dir1/file1.txt".to_string());
+    files.retain(|x| *x != "./dir1/file1.txt");
     files.push("./empty_dir/file1.txt".to_string());
     files.sort();
     emty_dirs.clear();
@@ -150,11 +187,11 @@ fn move_file_2() {
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
 
     ft.move_file(
-        "./dir_with_one_file/file.txt".to_string(),
-        "./dir1/file3.txt".to_string(),
+        path("./dir_with_one_file/file.txt"),
+        path("./dir1/file3.txt"),
     )
     .unwrap();
-    files.retain(|x| *x != "./dir_with_one_file/file.txt".to_string());
+    files.retain(|x| *x != "./dir_with_one_file/file.txt");
     files.push("./dir1/file3.txt".to_string());
     emty_dirs.push("./dir_with_one_file/".to_string());
 
@@ -170,11 +207,8 @@ fn move_file_failer() {
 
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
 
-    ft.move_file(
-        "./dir0/file1.txt".to_string(),
-        "./dir1/file1.txt".to_string(),
-    )
-    .unwrap_err();
+    ft.move_file(path("./dir0/file1.txt"), path("./dir1/file1.txt"))
+        .unwrap_err();
 
     assert_vec(ft, Some(&files), Some(&emty_dirs));
 }
@@ -186,9 +220,12 @@ fn remove_file() {
 
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
 
-    ft.rm_file("./dir1/file1.txt".to_string()).unwrap();
+    let rpc = RPC::DeleteFile {
+        path: path("./dir1/file1.txt"),
+    };
+    handle(&mut ft, rpc).unwrap();
 
-    files.retain(|x| *x != "./dir1/file1.txt".to_string());
+    files.retain(|x| *x != "./dir1/file1.txt");
     files.sort();
 
     assert_vec(ft, Some(&files), Some(&emty_dirs));
@@ -201,10 +238,12 @@ fn remove_file_add_emty_dir() {
 
     let mut ft = FileTree::new(files.clone(), emty_dirs.clone());
 
-    ft.rm_file("./dir_with_one_file/file.txt".to_string())
-        .unwrap();
+    let rpc = RPC::DeleteFile {
+        path: path("./dir_with_one_file/file.txt"),
+    };
+    handle(&mut ft, rpc).unwrap();
 
-    files.retain(|x| *x != "./dir_with_one_file/file.txt".to_string());
+    files.retain(|x| *x != "./dir_with_one_file/file.txt");
     files.sort();
 
     emty_dirs.push("./dir_with_one_file/".to_string());
@@ -225,14 +264,14 @@ fn undo_redo_operation() {
 
     let res = panic::catch_unwind(move || {
         let mut fs = FileTree::new(files.clone(), emty_dirs.clone());
-        fs.open_file("./file.txt".to_string()).unwrap();
+        fs.open_file(path("./file.txt")).unwrap();
 
         assert_vec(fs.clone(), Some(&files), Some(&emty_dirs));
 
         let auto = fs.tree.get_mut("./file.txt").unwrap();
         assert!(auto.read().unwrap() == b"hello world");
 
-        auto.edit(None, None, "hello world 2");
+        auto.edit(None, None, "hello world 2").unwrap();
         auto.undo();
 
         assert!(auto.read().unwrap() == b"hello world");
@@ -243,3 +282,57 @@ fn undo_redo_operation() {
     fs::remove_file("./file.txt").unwrap();
     res.unwrap();
 }
+
+#[test]
+#[serial]
+fn open_large_file() {
+    use crate::server::config::config;
+    use std::io::ErrorKind;
+    let files = vec!["./large_file.log".to_string()];
+    let file = File::create("./large_file.log").unwrap();
+
+    let res = panic::catch_unwind(move || {
+        let mut ft = FileTree::new(files, vec![]);
+        // the file is sparse so nothing is written to the disk
+        file.set_len(config().view_only_size + 1).unwrap();
+        let e = ft.open_file(path("./large_file.log")).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Unsupported);
+
+        file.set_len(config().max_file_size + 1).unwrap();
+        let e = ft.open_file(path("./large_file.log")).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::FileTooLarge);
+        assert!(ft.tree.is_empty());
+    });
+    fs::remove_file("./large_file.log").unwrap();
+    res.unwrap();
+}
+
+#[test]
+#[serial]
+fn binary_files_over_the_replace_limit_are_view_only() {
+    use crate::communication::file_tree::crdt::MAX_BIN_SIZE;
+    use std::io::ErrorKind;
+    let files = vec!["./large.bin".to_string()];
+    let mut file = File::create("./large.bin").unwrap();
+
+    let res = panic::catch_unwind(move || {
+        let mut ft = FileTree::new(files, vec![]);
+        // it can't be replaced with `replace_bin` so no crdt is made for it
+        file.write_all(&[0xFF]).unwrap();
+        file.set_len(MAX_BIN_SIZE as u64 + 1).unwrap();
+        let e = ft.open_file(path("./large.bin")).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Unsupported);
+        // only the start is sniffed, the rest is checked when it is read
+        let mut content = vec![b'a'; MAX_BIN_SIZE];
+        content.push(0xFF);
+        fs::write("./large.bin", content).unwrap();
+        let e = ft.open_file(path("./large.bin")).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Unsupported);
+
+        let big = vec![0xFF; MAX_BIN_SIZE + 1];
+        ft.create_file(path("./new.bin"), None, Some(big)).unwrap();
+        assert!(!ft.tree.contains_key("./new.bin"));
+    });
+    fs::remove_file("./large.bin").unwrap();
+    res.unwrap();
+}
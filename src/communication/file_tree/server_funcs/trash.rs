@@ -0,0 +1,172 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+/// a deleted file or directory in the session trash (see `ServerApi::trash`)
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct TrashEntry {
+    pub id: u64,
+    /// where it was deleted from (the directories end with `/`)
+    pub path: ProjectPath,
+    pub deleted_by: String,
+    /// in milliseconds since the unix epoch
+    pub deleted_at: u64,
+}
+
+/// a deleted path with what is needed to put it back
+#[derive(Debug, Clone)]
+struct Trashed {
+    entry: TrashEntry,
+    node: Node,
+    /// the open files with their history (by their old paths)
+    loaded: HashMap<String, Crdt>,
+}
+
+/// the deleted paths of the session, their content is in `ServerConfig::trash_dir`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Trash {
+    items: Vec<Trashed>,
+    next_id: u64,
+}
+
+impl FileTree {
+    /// move a file, a link or a directory (with its content) to the trash
+    /// the open files are kept so they are the same if they are restored
+    pub(crate) fn trash(&mut self, path: ProjectPath, username: &str) -> Res<TrashEntry> {
+        if path.is_dir() {
+            if path.is_root() {
+                return Err(Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "The root directory can't be removed",
+                ));
+            }
+            if !self.in_dir(&path) {
+                return Err(Error::new(
+                    io::ErrorKind::NotFound,
+                    "The directory does not exist",
+                ));
+            }
+        } else if !self.is_file(&path) && !self.paths.is_link(&path) {
+            return Err(Error::new(
+                io::ErrorKind::NotFound,
+                "The file does not exist",
+            ));
+        }
+        let id = self.trash.next_id;
+        // the link itself is moved (not what it points to)
+        #[cfg(not(test))]
+        move_on_disk(
+            &on_disk(&path.parent())?.join(path.file_name()),
+            &config().trash_dir.join(id.to_string()),
+        )?;
+        self.trash.next_id += 1;
+
+        let node = self.paths.remove(&path)?;
+        let open: Vec<String> = self
+            .tree
+            .keys()
+            .filter(|file| *file == path.as_str() || path.is_dir() && file.starts_with(&*path))
+            .cloned()
+            .collect();
+        let loaded = open
+            .into_iter()
+            .filter_map(|file| Some((file.clone(), self.tree.remove(&file)?)))
+            .collect();
+        let deleted_at = SystemTime::now().duration_since(UNIX_EPOCH);
+        let entry = TrashEntry {
+            id,
+            path,
+            deleted_by: username.to_string(),
+            deleted_at: deleted_at.map_or(0, |time| time.as_millis() as u64),
+        };
+        self.trash.items.push(Trashed {
+            entry: entry.clone(),
+            node,
+            loaded,
+        });
+        Ok(entry)
+    }
+
+    /// put a path of the trash back where it was, its parent should exist
+    pub(crate) fn restore(&mut self, id: u64) -> Res<ProjectPath> {
+        let i = self.trash.items.iter().position(|item| item.entry.id == id);
+        let i = i.ok_or_else(|| Error::new(io::ErrorKind::NotFound, "it is not in the trash"))?;
+        let path = self.trash.items[i].entry.path.clone();
+        if self.paths.get(&path).is_some() {
+            return Err(Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{path} exists"),
+            ));
+        }
+        if !self.paths.is_dir(&path.parent()) {
+            return Err(Error::new(
+                io::ErrorKind::NotFound,
+                "The directory does not exist",
+            ));
+        }
+        #[cfg(not(test))]
+        move_on_disk(
+            &config().trash_dir.join(id.to_string()),
+            &on_disk(&path.parent())?.join(path.file_name()),
+        )?;
+        let item = self.trash.items.remove(i);
+        self.paths.insert(&path, item.node, false)?;
+        self.tree.extend(item.loaded);
+        Ok(path)
+    }
+
+    /// the last deleted path that was in `path` (with or without the `/` of a directory)
+    pub(crate) fn trashed_at(&self, path: &str) -> Option<u64> {
+        let path = path.trim_end_matches('/');
+        let items = self.trash.items.iter().rev();
+        items
+            .map(|item| &item.entry)
+            .find(|entry| entry.path.trim_end_matches('/') == path)
+            .map(|entry| entry.id)
+    }
+
+    /// the oldest first
+    pub(crate) fn trash_entries(&self) -> Vec<TrashEntry> {
+        let items = self.trash.items.iter();
+        items.map(|item| item.entry.clone()).collect()
+    }
+
+    /// delete everything in the trash for good
+    pub(crate) fn purge_trash(&mut self) -> Res<()> {
+        #[cfg(not(test))]
+        if config().trash_dir.exists() {
+            fs::remove_dir_all(&config().trash_dir)?;
+        }
+        self.trash.items.clear();
+        Ok(())
+    }
+}
+
+/// rename a path, it is copied if the trash is on another file system
+fn move_on_disk(from: &Path, to: &Path) -> Res<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {}
+        res => return res,
+    }
+    let file_type = fs::symlink_metadata(from)?.file_type();
+    if file_type.is_dir() {
+        copy_dir_on_disk(from, to)?;
+        fs::remove_dir_all(from)
+    } else if file_type.is_symlink() {
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(fs::read_link(from)?, to)?;
+        fs::remove_file(from)
+    } else {
+        fs::copy(from, to)?;
+        fs::remove_file(from)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,83 @@
+use super::*;
+
+fn path(path: &str) -> ProjectPath {
+    ProjectPath::new(path).unwrap()
+}
+
+fn tree() -> FileTree {
+    let files = ["./a.txt", "./dir/b.txt", "./dir/sub/c.txt"];
+    FileTree {
+        tree: HashMap::new(),
+        views: HashMap::new(),
+        pending: HashMap::new(),
+        loading: HashMap::new(),
+        paths: DirTree::from_paths(files.map(String::from).to_vec(), vec![]),
+        trash: Trash::default(),
+    }
+}
+
+#[test]
+fn restore_keeps_the_open_files() {
+    let mut ft = tree();
+    let content = b"not saved".to_vec();
+    ft.tree
+        .insert("./dir/sub/c.txt".to_string(), crdt_of(content.clone()));
+
+    let entry = ft.trash(path("./dir/"), "a").unwrap();
+    assert_eq!(
+        (entry.path.as_str(), entry.deleted_by.as_str()),
+        ("./dir/", "a")
+    );
+    assert!(ft.paths.get("./dir/").is_none());
+    assert!(ft.tree.is_empty());
+
+    ft.trash(path("./a.txt"), "b").unwrap();
+    assert_eq!(ft.trash_entries().len(), 2);
+    // the last one in a path is restored (with or without the `/`)
+    assert_eq!(ft.trashed_at("./dir"), Some(entry.id));
+    assert_eq!(ft.restore(entry.id).unwrap(), path("./dir/"));
+    assert!(ft.is_file("./dir/b.txt"));
+    assert_eq!(ft.read_buf("./dir/sub/c.txt").unwrap(), content);
+    assert_eq!(ft.trash_entries().len(), 1);
+}
+
+#[test]
+fn restore_needs_the_path() {
+    let mut ft = tree();
+    let file = ft.trash(path("./dir/b.txt"), "a").unwrap();
+    let dir = ft.trash(path("./dir/"), "a").unwrap();
+    assert!(ft.trash(path("./dir/"), "a").is_err());
+
+    // the parent is gone
+    let e = ft.restore(file.id).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::NotFound);
+    // the path is taken
+    ft.paths.make_dir("./dir/", true).unwrap();
+    let e = ft.restore(dir.id).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+    ft.restore(file.id).unwrap();
+
+    ft.purge_trash().unwrap();
+    assert!(ft.trash_entries().is_empty());
+    assert!(ft.restore(dir.id).is_err());
+}
+
+#[test]
+fn only_a_move_to_another_device_is_copied() {
+    let root = std::env::temp_dir().join(format!("pairwriter_move_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let (from, to) = (root.join("from"), root.join("to"));
+    fs::create_dir_all(&from).unwrap();
+    fs::write(from.join("a.txt"), "a").unwrap();
+    fs::create_dir_all(&to).unwrap();
+    fs::write(to.join("b.txt"), "b").unwrap();
+
+    // a directory can't be renamed to a directory that is not empty
+    let e = move_on_disk(&from, &to).unwrap_err();
+    let kept = from.join("a.txt").exists() && !to.join("a.txt").exists();
+    let missing = move_on_disk(&root.join("missing"), &root.join("new")).unwrap_err();
+    fs::remove_dir_all(&root).unwrap();
+    assert_eq!(e.kind(), io::ErrorKind::DirectoryNotEmpty);
+    assert!(kept, "nothing is copied");
+    assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+}
@@ -1,4 +1,13 @@
 pub(crate) mod file_tree;
 
+pub(crate) mod event;
+
+pub(crate) mod file_entry;
+
 pub(crate) mod rpc;
 
+pub(crate) mod participant;
+
+pub(crate) mod permissions;
+
+pub(crate) mod project_path;
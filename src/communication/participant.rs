@@ -0,0 +1,299 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+
+use crate::communication::{
+    event::{DirChange, Event, Splice},
+    file_tree::{crdt::EditError, move_keys},
+};
+
+/// what the host and the guests could do in a session besides editing,
+/// it is implemented by `ServerApi` and `ClientApi` so an editor plugin could use either
+#[allow(async_fn_in_trait)] // the apis are used directly (not as trait objects)
+pub trait Participant {
+    /// the username that the others see
+    fn username(&self) -> String;
+
+    /// send the cursor to the participants that have the file open
+    async fn move_cursor(&mut self, path: String, position: usize) -> io::Result<()>;
+
+    /// send the selection to the participants that have the file open
+    async fn set_selection(&mut self, path: String, start: usize, end: usize) -> io::Result<()>;
+
+    /// the participants in the session (with this one)
+    async fn participants(&self) -> Vec<String>;
+
+    /// the last cursors and selections of the others in a file
+    async fn cursors(&self, path: String) -> Vec<Cursor>;
+
+    /// get `Event::Following` when the participant moves its cursor (`None` stops following)
+    /// the last known cursor of the participant is sent right away
+    async fn follow(&mut self, username: Option<String>) -> io::Result<()>;
+
+    /// undo the last edit of this participant in the file (the edits of the others are kept)
+    async fn undo(&mut self, path: String) -> Result<(), EditError>;
+
+    /// redo the last edit that was undone with `undo`
+    async fn redo(&mut self, path: String) -> Result<(), EditError>;
+}
+
+/// the cursor of a participant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub username: String,
+    pub path: String,
+    pub position: usize,
+    /// the start and the end of the selection
+    pub selection: Option<(usize, usize)>,
+}
+
+/// an own edit that could be undone: `inserted` replaced `deleted` at `pos`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Change {
+    pos: usize,
+    deleted: String,
+    inserted: String,
+}
+
+impl Change {
+    fn inverse(self) -> Self {
+        Self {
+            pos: self.pos,
+            deleted: self.inserted,
+            inserted: self.deleted,
+        }
+    }
+
+    /// move the change after an edit of someone else
+    /// `None` if the edit changed the same text (it can't be undone anymore)
+    fn transform(mut self, other: &Splice) -> Option<Self> {
+        let end = self.pos + self.inserted.chars().count();
+        if other.pos + other.del <= self.pos {
+            self.pos = self.pos + other.text.chars().count() - other.del;
+            Some(self)
+        } else if other.pos >= end {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
+/// the own edits of a file, an edit has the changes of its splices in the order they are applied
+#[derive(Debug, Clone, Default)]
+struct History {
+    undo: Vec<Vec<Change>>,
+    redo: Vec<Vec<Change>>,
+}
+
+/// what a participant knows about the others, the same for the host and the guests
+/// it is updated with every event that is sent to the editor
+#[derive(Debug, Default)]
+pub(crate) struct Presence {
+    /// the others in the session
+    users: BTreeSet<String>,
+    cursors: HashMap<String, Cursor>,
+    following: Option<String>,
+    /// the own edits of every file
+    history: HashMap<String, History>,
+}
+
+impl Presence {
+    pub(crate) fn new(users: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            users: users.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    /// update the state with an event, returns the event of following if there is one
+    pub(crate) fn observe(&mut self, event: &Event) -> Option<Event> {
+        match event {
+            Event::UserJoined { username } => {
+                self.users.insert(username.clone());
+            }
+            Event::UserLeft { username } => {
+                self.users.remove(username);
+                self.cursors.remove(username);
+                if self.following.as_ref() == Some(username) {
+                    self.following = None;
+                }
+            }
+            Event::CursorMoved {
+                username,
+                path,
+                position,
+            } => {
+                let cursor = Cursor {
+                    username: username.clone(),
+                    path: path.clone(),
+                    position: *position,
+                    selection: None,
+                };
+                self.cursors.insert(username.clone(), cursor);
+                return self.following(username);
+            }
+            Event::SelectionChanged {
+                username,
+                path,
+                start,
+                end,
+            } => {
+                let cursor = Cursor {
+                    username: username.clone(),
+                    path: path.clone(),
+                    position: *end,
+                    selection: Some((*start, *end)),
+                };
+                self.cursors.insert(username.clone(), cursor);
+                return self.following(username);
+            }
+            Event::TextChanged { path, splices } => {
+                if let Some(history) = self.history.get_mut(path) {
+                    for splice in splices {
+                        // an edit is dropped if one of its changes can't be undone
+                        let transform = |edits: &mut Vec<Vec<Change>>| {
+                            *edits = edits
+                                .drain(..)
+                                .filter_map(|edit| {
+                                    edit.into_iter()
+                                        .map(|change| change.transform(splice))
+                                        .collect()
+                                })
+                                .collect();
+                        };
+                        transform(&mut history.undo);
+                        transform(&mut history.redo);
+                    }
+                }
+            }
+            Event::BinaryReplaced { path } | Event::FileDeleted { path } => {
+                self.history.remove(path);
+            }
+            Event::FileMoved { path, new_path } => {
+                if let Some(history) = self.history.remove(path) {
+                    self.history.insert(new_path.clone(), history);
+                }
+                for cursor in self.cursors.values_mut() {
+                    if cursor.path == *path {
+                        cursor.path = new_path.clone();
+                    }
+                }
+            }
+            Event::DirChanged {
+                path,
+                change: DirChange::Moved { new_path },
+            } => {
+                move_keys(&mut self.history, path, new_path);
+                for cursor in self.cursors.values_mut() {
+                    if let Some(rest) = cursor.path.strip_prefix(path.as_str()) {
+                        cursor.path = format!("{new_path}{rest}");
+                    }
+                }
+            }
+            Event::DirChanged {
+                path,
+                change: DirChange::Deleted,
+            } => {
+                self.history
+                    .retain(|file, _| !file.starts_with(path.as_str()));
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn following(&self, username: &String) -> Option<Event> {
+        if self.following.as_ref() != Some(username) {
+            return None;
+        }
+        let cursor = self.cursors.get(username)?;
+        Some(Event::Following {
+            username: username.clone(),
+            path: cursor.path.clone(),
+            position: cursor.position,
+        })
+    }
+
+    /// the others in the session
+    pub(crate) fn users(&self) -> impl Iterator<Item = &String> {
+        self.users.iter()
+    }
+
+    pub(crate) fn cursors(&self, path: &str) -> Vec<Cursor> {
+        let cursors = self.cursors.values().filter(|cursor| cursor.path == path);
+        cursors.cloned().collect()
+    }
+
+    /// start following a participant, returns its last cursor
+    pub(crate) fn follow(&mut self, username: Option<String>) -> io::Result<Option<Event>> {
+        match username {
+            Some(username) if !self.users.contains(&username) => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{username} is not in the session"),
+            )),
+            username => {
+                self.following = username;
+                Ok(self
+                    .following
+                    .as_ref()
+                    .and_then(|user| self.following(user)))
+            }
+        }
+    }
+
+    /// remember an own edit, `before` is the text of the file and `splices` are what the edit did
+    pub(crate) fn edited(&mut self, path: &str, before: &str, splices: &[Splice]) {
+        let history = self.history.entry(path.to_string()).or_default();
+        history.redo.clear();
+        let mut text: Vec<char> = before.chars().collect();
+        let mut changes = vec![];
+        for splice in splices {
+            let end = (splice.pos + splice.del).min(text.len());
+            let pos = splice.pos.min(end);
+            let deleted = text.splice(pos..end, splice.text.chars()).collect();
+            changes.push(Change {
+                pos,
+                deleted,
+                inserted: splice.text.clone(),
+            });
+        }
+        if !changes.is_empty() {
+            history.undo.push(changes);
+        }
+    }
+
+    /// the splices that undo the last own edit of the file, in the order they are applied
+    pub(crate) fn undo(&mut self, path: &str) -> Option<Vec<Splice>> {
+        let history = self.history.get_mut(path)?;
+        let edit = inverse(history.undo.pop()?);
+        let splices = splices(&edit);
+        history.redo.push(edit);
+        Some(splices)
+    }
+
+    /// the splices that do the last undone edit of the file again
+    pub(crate) fn redo(&mut self, path: &str) -> Option<Vec<Splice>> {
+        let history = self.history.get_mut(path)?;
+        let edit = inverse(history.redo.pop()?);
+        let splices = splices(&edit);
+        history.undo.push(edit);
+        Some(splices)
+    }
+}
+
+/// the changes that revert an edit (the last change is reverted first)
+fn inverse(edit: Vec<Change>) -> Vec<Change> {
+    edit.into_iter().rev().map(Change::inverse).collect()
+}
+
+fn splices(edit: &[Change]) -> Vec<Splice> {
+    let splice = |change: &Change| Splice {
+        pos: change.pos,
+        del: change.deleted.chars().count(),
+        text: change.inserted.clone(),
+    };
+    edit.iter().map(splice).collect()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,108 @@
+use super::*;
+
+fn splice(pos: usize, del: usize, text: &str) -> Splice {
+    Splice {
+        pos,
+        del,
+        text: text.to_string(),
+    }
+}
+
+fn text_changed(pos: usize, del: usize, text: &str) -> Event {
+    Event::TextChanged {
+        path: "./a.txt".to_string(),
+        splices: vec![splice(pos, del, text)],
+    }
+}
+
+#[test]
+fn undo_own_edits_only() {
+    let mut presence = Presence::default();
+    presence.edited("./a.txt", "hello", &[splice(5, 0, " world")]);
+    // someone else added text before the own edit
+    presence.observe(&text_changed(0, 0, ">> "));
+    assert_eq!(presence.undo("./a.txt"), Some(vec![splice(8, 6, "")]));
+    assert_eq!(presence.redo("./a.txt"), Some(vec![splice(8, 0, " world")]));
+    // an edit of the same text drops the change
+    presence.observe(&text_changed(9, 2, ""));
+    assert_eq!(presence.undo("./a.txt"), None);
+}
+
+#[test]
+fn new_edit_clears_redo() {
+    let mut presence = Presence::default();
+    presence.edited("./a.txt", "ab", &[splice(2, 0, "c")]);
+    assert_eq!(presence.undo("./a.txt"), Some(vec![splice(2, 1, "")]));
+    presence.edited("./a.txt", "ab", &[splice(0, 0, "x")]);
+    assert_eq!(presence.redo("./a.txt"), None);
+    assert_eq!(presence.undo("./a.txt"), Some(vec![splice(0, 1, "")]));
+}
+
+#[test]
+fn undo_the_splices_of_an_edit() {
+    let mut presence = Presence::default();
+    // "a b c" -> "A b C", the text between the splices is not in the change
+    presence.edited("./a.txt", "a b c", &[splice(0, 1, "A"), splice(4, 1, "C")]);
+    // someone else edited the text between them
+    presence.observe(&text_changed(2, 1, "bb"));
+    let undo = vec![splice(5, 1, "c"), splice(0, 1, "a")];
+    assert_eq!(presence.undo("./a.txt"), Some(undo));
+    let redo = vec![splice(0, 1, "A"), splice(5, 1, "C")];
+    assert_eq!(presence.redo("./a.txt"), Some(redo));
+}
+
+#[test]
+fn follow_a_participant() {
+    let mut presence = Presence::new(["a".to_string()]);
+    assert!(presence.follow(Some("b".to_string())).is_err());
+    assert_eq!(presence.follow(Some("a".to_string())).unwrap(), None);
+    let moved = Event::CursorMoved {
+        username: "a".to_string(),
+        path: "./a.txt".to_string(),
+        position: 4,
+    };
+    let following = Event::Following {
+        username: "a".to_string(),
+        path: "./a.txt".to_string(),
+        position: 4,
+    };
+    assert_eq!(presence.observe(&moved), Some(following));
+    assert_eq!(presence.cursors("./a.txt").len(), 1);
+
+    let left = Event::UserLeft {
+        username: "a".to_string(),
+    };
+    presence.observe(&left);
+    assert!(presence.cursors("./a.txt").is_empty());
+    assert_eq!(presence.users().count(), 0);
+    assert_eq!(presence.observe(&moved), None);
+}
+
+#[test]
+fn cursors_and_history_follow_moved_dirs() {
+    let mut presence = Presence::new(["a".to_string()]);
+    presence.edited("./src/a.txt", "", &[splice(0, 0, "a")]);
+    presence.observe(&Event::CursorMoved {
+        username: "a".to_string(),
+        path: "./src/a.txt".to_string(),
+        position: 1,
+    });
+    presence.observe(&Event::DirChanged {
+        path: "./src/".to_string(),
+        change: DirChange::Moved {
+            new_path: "./lib/src/".to_string(),
+        },
+    });
+    assert_eq!(presence.cursors("./lib/src/a.txt").len(), 1);
+    assert_eq!(presence.undo("./src/a.txt"), None);
+    assert_eq!(
+        presence.undo("./lib/src/a.txt"),
+        Some(vec![splice(0, 1, "")])
+    );
+
+    presence.observe(&Event::DirChanged {
+        path: "./lib/".to_string(),
+        change: DirChange::Deleted,
+    });
+    assert_eq!(presence.redo("./lib/src/a.txt"), None);
+}
@@ -0,0 +1,204 @@
+use std::io::{self, Error};
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::communication::{project_path::ProjectPath, rpc::RPC};
+
+/// the role of a user, every role could do what the roles before it could do
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Encode, Decode, Serialize, Deserialize,
+)]
+pub enum Priviledge {
+    /// read the files and move the cursor
+    Viewer,
+    /// add marks (comments) to the files
+    Commenter,
+    /// edit the text of the files (but not the file tree)
+    Editor,
+    /// change the file tree and save the files
+    Maintainer,
+    /// everything (this is the role of the server)
+    Host,
+}
+
+/// a role for the paths that match `glob`
+/// `*` matches a part of a name, `?` one character and `**` any number of directories
+/// (`docs/**` is `docs` and everything in it)
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct Scope {
+    pub glob: String,
+    pub priviledge: Priviledge,
+}
+
+/// the role of a user and the roles of some paths
+/// the first scope that matches a path is used, the role is used if none matches
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct Permissions {
+    pub priviledge: Priviledge,
+    pub scopes: Vec<Scope>,
+}
+
+impl From<Priviledge> for Permissions {
+    fn from(priviledge: Priviledge) -> Self {
+        Self {
+            priviledge,
+            scopes: vec![],
+        }
+    }
+}
+
+/// what is changed by an rpc
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    /// on a file or the content of a directory
+    File,
+    /// on a directory and everything in it
+    Dir,
+}
+
+impl Permissions {
+    /// the host (it is used for the server)
+    pub fn host() -> Self {
+        Priviledge::Host.into()
+    }
+
+    /// add a role for the paths that match `glob` (the scopes are checked in order)
+    pub fn with_scope(mut self, glob: &str, priviledge: Priviledge) -> Self {
+        self.scopes.push(Scope {
+            glob: glob.to_string(),
+            priviledge,
+        });
+        self
+    }
+
+    /// the role of the user for a path
+    pub fn priviledge_for(&self, path: &ProjectPath) -> Priviledge {
+        let path = relative(path.as_str());
+        self.scopes
+            .iter()
+            .find(|scope| glob_match(relative(&scope.glob), path))
+            .map_or(self.priviledge, |scope| scope.priviledge)
+    }
+
+    /// `true` if the user could do what needs `needed` on `path`
+    pub fn allows(&self, path: &ProjectPath, needed: Priviledge) -> bool {
+        self.check(path, needed, Action::File)
+    }
+
+    fn check(&self, path: &ProjectPath, needed: Priviledge, action: Action) -> bool {
+        if self.priviledge_for(path) < needed {
+            return false;
+        }
+        if action == Action::File {
+            return true;
+        }
+        // a change of a directory changes everything in it,
+        // so a scope with a lower role that could match inside the directory denies it
+        let dir = match relative(path.as_str()) {
+            "" => String::new(),
+            dir => format!("{dir}/"),
+        };
+        !self.scopes.iter().any(|scope| {
+            let glob = relative(&scope.glob);
+            let fixed = &glob[..glob.find(['*', '?']).unwrap_or(glob.len())];
+            scope.priviledge < needed && (fixed.starts_with(&dir) || dir.starts_with(fixed))
+        })
+    }
+
+    /// check if the user could send `rpc`
+    /// this is used for every rpc that is sent by a client
+    pub fn authorize(&self, rpc: &RPC) -> io::Result<()> {
+        for (path, needed, action) in checks(rpc) {
+            if !self.check(path, needed, action) {
+                return Err(Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("{:?} is needed for {path}", needed),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the paths of `rpc` with the role that is needed for each of them
+fn checks(rpc: &RPC) -> Vec<(&ProjectPath, Priviledge, Action)> {
+    match rpc {
+        RPC::ReqListDir { path, .. }
+        | RPC::ReqBufferTree { path }
+        | RPC::OpenFile { path }
+        | RPC::CloseFile { path }
+        | RPC::ReqMoveCursor { path, .. } => vec![(path, Priviledge::Viewer, Action::File)],
+        RPC::RequestMark { path, .. } => vec![(path, Priviledge::Commenter, Action::File)],
+        RPC::EditBuffer { path, .. } => vec![(path, Priviledge::Editor, Action::File)],
+        // it undoes the last edit of anyone, the clients undo their own edits (see `Participant::undo`)
+        RPC::Undo { path } | RPC::Redo { path } => vec![(path, Priviledge::Host, Action::File)],
+        RPC::ReqSaveFile { path } | RPC::CreateFile { path, .. } | RPC::DeleteFile { path } => {
+            vec![(path, Priviledge::Maintainer, Action::File)]
+        }
+        RPC::MoveFile { path, new_path } => vec![
+            (path, Priviledge::Maintainer, Action::File),
+            (new_path, Priviledge::Maintainer, Action::File),
+        ],
+        // the copied file is only read
+        RPC::CopyFile { path, new_path } => vec![
+            (path, Priviledge::Viewer, Action::File),
+            (new_path, Priviledge::Maintainer, Action::File),
+        ],
+        // the path could be a directory (with the content that was in it)
+        RPC::CreateDirectory { path } | RPC::DeleteDirectory { path } | RPC::Restore { path } => {
+            vec![(path, Priviledge::Maintainer, Action::Dir)]
+        }
+        RPC::MoveDirectory { path, new_path } => vec![
+            (path, Priviledge::Maintainer, Action::Dir),
+            (new_path, Priviledge::Maintainer, Action::Dir),
+        ],
+        RPC::CopyDirectory { path, new_path } => vec![
+            (path, Priviledge::Viewer, Action::Dir),
+            (new_path, Priviledge::Maintainer, Action::Dir),
+        ],
+        // the messages of the server are rejected by the api
+        _ => vec![],
+    }
+}
+
+/// the paths that `rpc` reads or changes (the messages of the server have none)
+pub(crate) fn paths_of(rpc: &RPC) -> Vec<&ProjectPath> {
+    checks(rpc).into_iter().map(|(path, _, _)| path).collect()
+}
+
+/// `./docs/a.md` -> `docs/a.md`, `./docs/` -> `docs` and `./` -> ``
+fn relative(path: &str) -> &str {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    let path = path.strip_prefix('/').unwrap_or(path);
+    path.strip_suffix('/').unwrap_or(path)
+}
+
+pub(crate) fn glob_match(glob: &str, path: &str) -> bool {
+    fn split(path: &str) -> Vec<&str> {
+        path.split('/').filter(|part| !part.is_empty()).collect()
+    }
+    fn parts(glob: &[&str], path: &[&str]) -> bool {
+        match glob.split_first() {
+            None => path.is_empty(),
+            Some((&"**", rest)) => (0..=path.len()).any(|i| parts(rest, &path[i..])),
+            Some((part, rest)) => path.split_first().is_some_and(|(name, path)| {
+                let part: Vec<char> = part.chars().collect();
+                let name: Vec<char> = name.chars().collect();
+                name_match(&part, &name) && parts(rest, path)
+            }),
+        }
+    }
+    fn name_match(glob: &[char], name: &[char]) -> bool {
+        match glob.split_first() {
+            None => name.is_empty(),
+            Some(('*', rest)) => (0..=name.len()).any(|i| name_match(rest, &name[i..])),
+            Some(('?', rest)) => !name.is_empty() && name_match(rest, &name[1..]),
+            Some((c, rest)) => name.first() == Some(c) && name_match(rest, &name[1..]),
+        }
+    }
+    parts(&split(glob), &split(path))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,82 @@
+use super::*;
+
+fn path(path: &str) -> ProjectPath {
+    ProjectPath::new(path).unwrap()
+}
+
+#[test]
+fn globs() {
+    assert!(glob_match("docs/**", "docs"));
+    assert!(glob_match("docs/**", "docs/a/b.md"));
+    assert!(!glob_match("docs/**", "src/docs/a.md"));
+    assert!(glob_match("**/*.rs", "src/lib.rs"));
+    assert!(glob_match("**/*.rs", "lib.rs"));
+    assert!(glob_match("src/?.rs", "src/a.rs"));
+    assert!(!glob_match("src/?.rs", "src/ab.rs"));
+    assert!(!glob_match("src/*", "src/a/b.rs"));
+}
+
+#[test]
+fn roles_of_paths() {
+    let permissions = Permissions::from(Priviledge::Viewer)
+        .with_scope("docs/**", Priviledge::Editor)
+        .with_scope("./src/", Priviledge::Commenter);
+    assert_eq!(
+        permissions.priviledge_for(&path("./docs/a.md")),
+        Priviledge::Editor
+    );
+    assert_eq!(
+        permissions.priviledge_for(&path("./src/")),
+        Priviledge::Commenter
+    );
+    assert_eq!(
+        permissions.priviledge_for(&path("./src/a.rs")),
+        Priviledge::Viewer
+    );
+
+    let edit = |file: &str| RPC::EditBuffer {
+        path: path(file),
+        changes: vec![],
+        old_head_idx: 0,
+        new_heads: vec![],
+    };
+    assert!(permissions.authorize(&edit("./docs/a.md")).is_ok());
+    let denied = permissions.authorize(&edit("./a.md")).unwrap_err();
+    assert_eq!(denied.kind(), io::ErrorKind::PermissionDenied);
+    // an editor can't change the file tree
+    let create = RPC::CreateFile {
+        path: path("./docs/b.md"),
+        mode: None,
+        content: None,
+    };
+    assert!(permissions.authorize(&create).is_err());
+    let open = RPC::OpenFile {
+        path: path("./a.md"),
+    };
+    assert!(permissions.authorize(&open).is_ok());
+    // only the own edits are undone (see `Participant::undo`)
+    let undo = RPC::Undo {
+        path: path("./docs/a.md"),
+    };
+    assert!(permissions.authorize(&undo).is_err());
+    assert!(Permissions::from(Priviledge::Maintainer)
+        .authorize(&undo)
+        .is_err());
+}
+
+#[test]
+fn directory_with_a_lower_scope() {
+    let permissions = Permissions::from(Priviledge::Maintainer)
+        .with_scope("src/generated/**", Priviledge::Viewer);
+    let delete = |dir: &str| RPC::DeleteDirectory { path: path(dir) };
+    assert!(permissions.authorize(&delete("./docs/")).is_ok());
+    // the directory has read only files in it
+    assert!(permissions.authorize(&delete("./src/")).is_err());
+    assert!(permissions.authorize(&delete("./")).is_err());
+    let moved = RPC::MoveFile {
+        path: path("./a.rs"),
+        new_path: path("./src/generated/a.rs"),
+    };
+    assert!(permissions.authorize(&moved).is_err());
+    assert!(Permissions::host().authorize(&delete("./")).is_ok());
+}
@@ -0,0 +1,264 @@
+use std::fmt;
+use std::io::{self, Error};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use bincode::{
+    de::Decoder,
+    enc::Encoder,
+    error::{DecodeError, EncodeError},
+    Decode, Encode,
+};
+use serde::{Deserialize, Serialize};
+
+type Res<T> = io::Result<T>;
+
+/// a path inside the shared directory
+///
+/// it is always in the form of `./dir/file` for files and `./dir/` for directories (`./` is the root)
+/// the path is normalized when it is created: `.` and repeated `/` are removed
+/// (and `\` is changed to `/` on windows)
+/// paths with `..`, absolute paths and windows prefixes are rejected,
+/// so a client can't point outside of the shared directory
+/// (symlinks are checked when the path is resolved on the disk with `resolve`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ProjectPath(String);
+
+impl ProjectPath {
+    /// normalize and validate a path
+    pub fn new(path: &str) -> Res<Self> {
+        let invalid = |msg: &str| {
+            Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{msg}: {path}"),
+            ))
+        };
+        // `\` is a separator on windows but it could be in a name on unix
+        let first = path.split(['/', '\\']).next().unwrap_or("");
+        let unix = if cfg!(windows) || is_drive(first) {
+            path.replace('\\', "/")
+        } else {
+            path.to_string()
+        };
+        if unix.starts_with('/') {
+            return invalid("absolute paths are not allowed");
+        }
+        if unix.contains('\0') {
+            return invalid("the path has a null character");
+        }
+        let mut parts = vec![];
+        for part in unix.split('/') {
+            match part {
+                "" | "." => {}
+                ".." => return invalid("`..` is not allowed in paths"),
+                part if parts.is_empty() && is_drive(part) => {
+                    return invalid("absolute paths are not allowed"); // windows prefix like `C:`
+                }
+                part => parts.push(part),
+            }
+        }
+        let is_dir = unix.ends_with('/') || parts.is_empty();
+        let mut normal = String::with_capacity(unix.len() + 2);
+        normal.push_str("./");
+        normal.push_str(&parts.join("/"));
+        if is_dir && !parts.is_empty() {
+            normal.push('/');
+        }
+        Ok(Self(normal))
+    }
+
+    /// the shared directory itself
+    pub fn root() -> Self {
+        Self("./".to_string())
+    }
+
+    /// make a path from a path on the disk that is inside `root`
+    pub fn from_disk(path: &Path, root: &Path, is_dir: bool) -> Res<Self> {
+        let relative = path.strip_prefix(root).map_err(|_| {
+            Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is outside of the shared directory", path.display()),
+            )
+        })?;
+        let relative = relative.to_str().ok_or_else(|| {
+            Error::new(io::ErrorKind::InvalidData, "the path is not valid unicode")
+        })?;
+        let path = Self::new(relative)?;
+        Ok(if is_dir { path.into_dir() } else { path })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// paths of directories end with `/`
+    pub fn is_dir(&self) -> bool {
+        self.0.ends_with('/')
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0 == "./"
+    }
+
+    /// the same path as a directory (ending with `/`)
+    pub fn into_dir(self) -> Self {
+        if self.is_dir() {
+            self
+        } else {
+            Self(self.0 + "/")
+        }
+    }
+
+    /// the same path as a file, directories are rejected
+    pub fn into_file(self) -> Res<Self> {
+        if self.is_dir() {
+            Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("expected a path of a file found a directory: {}", self.0),
+            ))
+        } else {
+            Ok(self)
+        }
+    }
+
+    /// the directory that has this path (the root is the parent of itself)
+    pub fn parent(&self) -> Self {
+        let path = self.0.trim_end_matches('/');
+        match path.rfind('/') {
+            Some(i) if path != "." => Self(path[..=i].to_string()),
+            _ => Self::root(),
+        }
+    }
+
+    /// the directories that have this path from the top (without the root)
+    pub fn ancestors(&self) -> impl Iterator<Item = String> + '_ {
+        let path = self.0.trim_end_matches('/');
+        path.match_indices('/')
+            .skip(1)
+            .map(move |(i, _)| path[..=i].to_string())
+    }
+
+    /// the last part of the path
+    pub fn file_name(&self) -> &str {
+        let path = self.0.trim_end_matches('/');
+        path.rsplit('/').next().filter(|x| *x != ".").unwrap_or("")
+    }
+
+    /// the path on the disk under `root`
+    /// this fails if the path (or one of its parents) is a symlink that points outside of `root`
+    pub fn resolve(&self, root: &Path) -> Res<PathBuf> {
+        let root = root.canonicalize()?;
+        let full = root.join(&self.0[2..]);
+        // canonicalize the deepest part of the path that exists
+        let mut existing = full.as_path();
+        while !existing.exists() && existing.symlink_metadata().is_err() {
+            existing = match existing.parent() {
+                Some(parent) => parent,
+                None => break,
+            };
+        }
+        let canonical = existing.canonicalize().map_err(|_| {
+            // a broken symlink
+            Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} can't be resolved", self.0),
+            )
+        })?;
+        if !canonical.starts_with(&root) {
+            return Err(Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} points outside of the shared directory", self.0),
+            ));
+        }
+        Ok(full)
+    }
+}
+
+/// a windows drive like `C:` (the other names with `:` are valid on unix)
+#[cfg(not(windows))]
+fn is_drive(part: &str) -> bool {
+    let bytes = part.as_bytes();
+    bytes.len() == 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+/// `C:` or `C:dir` (relative to the current directory of the drive)
+#[cfg(windows)]
+fn is_drive(part: &str) -> bool {
+    part.contains(':')
+}
+
+impl Deref for ProjectPath {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for ProjectPath {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for ProjectPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl TryFrom<String> for ProjectPath {
+    type Error = Error;
+    fn try_from(path: String) -> Res<Self> {
+        Self::new(&path)
+    }
+}
+
+impl TryFrom<&str> for ProjectPath {
+    type Error = Error;
+    fn try_from(path: &str) -> Res<Self> {
+        Self::new(path)
+    }
+}
+
+impl From<ProjectPath> for String {
+    fn from(path: ProjectPath) -> Self {
+        path.0
+    }
+}
+
+impl PartialEq<str> for ProjectPath {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<String> for ProjectPath {
+    fn eq(&self, other: &String) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<&str> for ProjectPath {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl Encode for ProjectPath {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.0.encode(encoder)
+    }
+}
+
+// the path is validated again when it is decoded, as it could be from a bad client
+impl<Context> Decode<Context> for ProjectPath {
+    fn decode<D: Decoder<Context = Context>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let path = String::decode(decoder)?;
+        Self::new(&path).map_err(|e| DecodeError::OtherString(e.to_string()))
+    }
+}
+bincode::impl_borrow_decode!(ProjectPath);
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,116 @@
+use super::*;
+
+#[test]
+fn normalize() {
+    for (path, normal) in [
+        ("", "./"),
+        (".", "./"),
+        ("./", "./"),
+        ("file.txt", "./file.txt"),
+        ("./dir//file.txt", "./dir/file.txt"),
+        ("././dir/./file.txt", "./dir/file.txt"),
+        ("dir///", "./dir/"),
+    ] {
+        assert_eq!(ProjectPath::new(path).unwrap(), normal, "{path}");
+    }
+}
+
+#[cfg(windows)]
+#[test]
+fn windows_separators() {
+    assert_eq!(ProjectPath::new(".\\dir\\sub\\").unwrap(), "./dir/sub/");
+    ProjectPath::new("\\\\server\\x").unwrap_err();
+}
+
+// `\` is a valid character of a name on unix
+#[cfg(unix)]
+#[test]
+fn names_with_backslashes() {
+    let path = ProjectPath::new("./dir/a\\b.txt").unwrap();
+    assert_eq!(path, "./dir/a\\b.txt");
+    assert_eq!(path.file_name(), "a\\b.txt");
+    // a windows path with a drive is still rejected
+    ProjectPath::new("C:\\x").unwrap_err();
+}
+
+// only a drive like `C:` is a prefix on unix
+#[cfg(unix)]
+#[test]
+fn names_with_colons() {
+    for path in ["./foo:bar.txt", "dir/a:b", "ab:/c"] {
+        ProjectPath::new(path).unwrap();
+    }
+    assert_eq!(ProjectPath::new("./foo:bar.txt").unwrap(), "./foo:bar.txt");
+}
+
+#[test]
+fn reject_escapes() {
+    for path in [
+        "../x",
+        "./a/../../x",
+        "/etc/passwd",
+        "C:\\x",
+        "d:/x",
+        "a/..",
+        "a\0b",
+    ] {
+        ProjectPath::new(path).unwrap_err();
+    }
+}
+
+#[test]
+fn parent_and_name() {
+    let path = ProjectPath::new("./a/b/c.txt").unwrap();
+    assert_eq!(path.parent(), "./a/b/");
+    assert_eq!(path.file_name(), "c.txt");
+    let dir = ProjectPath::new("./a/b/").unwrap();
+    assert_eq!(dir.parent(), "./a/");
+    assert_eq!(dir.file_name(), "b");
+    assert_eq!(ProjectPath::new("./a").unwrap().parent(), "./");
+    assert!(ProjectPath::root().parent().is_root());
+    assert_eq!(path.ancestors().collect::<Vec<_>>(), ["./a/", "./a/b/"]);
+    assert_eq!(dir.ancestors().collect::<Vec<_>>(), ["./a/"]);
+    assert_eq!(ProjectPath::root().ancestors().count(), 0);
+}
+
+#[test]
+fn decode_rejects_bad_paths() {
+    let config = bincode::config::standard();
+    let encoded = bincode::encode_to_vec("./../x".to_string(), config).unwrap();
+    bincode::decode_from_slice::<ProjectPath, _>(&encoded, config).unwrap_err();
+    let encoded = bincode::encode_to_vec("a//b".to_string(), config).unwrap();
+    let (path, _): (ProjectPath, _) = bincode::decode_from_slice(&encoded, config).unwrap();
+    assert_eq!(path, "./a/b");
+}
+
+#[cfg(unix)]
+#[test]
+fn resolve_rejects_symlink_escape() {
+    let root = std::env::temp_dir().join(format!("pairwriter_resolve_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("inside")).unwrap();
+    std::os::unix::fs::symlink("/", root.join("escape")).unwrap();
+    std::os::unix::fs::symlink(root.join("inside"), root.join("link")).unwrap();
+
+    let res = std::panic::catch_unwind(|| {
+        let path = ProjectPath::new("./inside/new.txt").unwrap();
+        assert_eq!(
+            path.resolve(&root).unwrap(),
+            root.canonicalize().unwrap().join("inside/new.txt")
+        );
+        ProjectPath::new("./link/x")
+            .unwrap()
+            .resolve(&root)
+            .unwrap();
+        ProjectPath::new("./escape/etc/passwd")
+            .unwrap()
+            .resolve(&root)
+            .unwrap_err();
+        ProjectPath::new("./escape")
+            .unwrap()
+            .resolve(&root)
+            .unwrap_err();
+    });
+    std::fs::remove_dir_all(&root).unwrap();
+    res.unwrap();
+}
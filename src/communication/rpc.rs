@@ -5,106 +5,233 @@ use bincode::{
 use serde::{Deserialize, Serialize};
 use tokio_tungstenite::tungstenite::Message;
 
-use crate::server::connection::Priviledge;
+use crate::communication::file_entry::FileEntry;
+use crate::communication::permissions::{Priviledge, Scope};
+use crate::communication::project_path::ProjectPath;
 
 #[derive(Debug, Clone, Encode, Decode, PartialEq, Serialize, Deserialize)]
 #[allow(clippy::upper_case_acronyms)]
 pub enum RPC {
     /// Response to a connection request
+    /// only the root level of the tree is sent, the rest should be requested with `ReqListDir`
     ResConnect {
         /// server username
         username: String,
-        files: Vec<String>,
-        emty_dirs: Vec<String>,
+        files: Vec<FileEntry>,
+        emty_dirs: Vec<ProjectPath>,
+        /// directories that are not empty (their content is not sent)
+        dirs: Vec<ProjectPath>,
+        links: Vec<Link>,
         priviledge: Priviledge,
+        /// the roles of some paths (see `Permissions`)
+        scopes: Vec<Scope>,
+        /// the participants in the session (with the host)
+        users: Vec<String>,
+        /// the shared directories, they are the directories of the root if there are more than one
+        roots: Vec<WorkspaceRoot>,
     },
     /// inform the client that the priviledge that (his/her) privilege has been changed
     ChangePriviledge {
         priviledge: Priviledge,
+        scopes: Vec<Scope>,
+    },
+    /// ask the host for a higher role, the answer is a `ChangePriviledge`
+    /// (with the same role if the request is denied or timed out)
+    ReqAccess {
+        priviledge: Priviledge,
+    },
+    /// a client asked for a higher role (this is sent to the receiver of the `ServerApi`)
+    /// the host answers with `ServerApi::approve_access` or `ServerApi::deny_access`
+    AccessRequested {
+        username: String,
+        priviledge: Priviledge,
+    },
+    /// request the content of a directory
+    /// `depth` of `None` means the whole subtree
+    /// `limit` of `None` means all the entries after `cursor`
+    /// (`None` is from the start, or the `next_cursor` of the page before)
+    ReqListDir {
+        path: ProjectPath,
+        depth: Option<usize>,
+        cursor: Option<ProjectPath>,
+        limit: Option<usize>,
+    },
+    /// a page of the content of a directory (sent only to the client that requested it)
+    ResListDir {
+        path: ProjectPath,
+        files: Vec<FileEntry>,
+        emty_dirs: Vec<ProjectPath>,
+        /// directories that are deeper than the requested depth (their content is not sent)
+        dirs: Vec<ProjectPath>,
+        links: Vec<Link>,
+        /// the cursor to request the next page with, `None` if this is the last page
+        next_cursor: Option<ProjectPath>,
     },
     /// buffer operations these are all read and write operaition
+    /// the same as `OpenFile`
     ReqBufferTree {
-        path: String,
+        path: ProjectPath,
+    },
+    /// open a file, the server sends it with `ResSendFile`
+    /// and then sends its edits and cursors until it is closed
+    OpenFile {
+        path: ProjectPath,
+    },
+    /// the client stops looking at the file
+    /// the server unloads the file when no one has it open and it is saved
+    CloseFile {
+        path: ProjectPath,
     },
     /// `.` should refer to the Current Working Directory
+    /// every path is validated and normalized when it is decoded (see `ProjectPath`)
     /// send file to the client  
     ResSendFile {
-        path: String,
+        path: ProjectPath,
         file: Vec<u8>, // this could be a automerge tree
         heads_history: Vec<Vec<[u8; 32]>>,
         head_idx: usize,
+        /// binary files can only be replaced as a whole
+        binary: bool,
+        /// the file is too large to be edited, the content is the file as it is (not a crdt)
+        view_only: bool,
+        /// the content was sent before in `ResFileChunk`s, `file` is empty
+        /// or has the changes that are made after the chunks (they are added to them)
+        chunked: bool,
+    },
+    /// a part of a large file, it is sent before the `ResSendFile` of the file
+    /// it could be used to show the progress of the transfer
+    ResFileChunk {
+        path: ProjectPath,
+        offset: u64,
+        total: u64,
+        data: Vec<u8>,
     },
     EditBuffer {
-        path: String,
+        path: ProjectPath,
         changes: Vec<u8>,
         old_head_idx: usize,
         /// hash of the new heads
         new_heads: Vec<Vec<[u8; 32]>>,
     },
     ReqMoveCursor {
-        path: String,
+        path: ProjectPath,
         position: usize,
     },
     ResMoveCursor {
         username: String,
-        path: String,
+        path: ProjectPath,
         position: usize,
     },
     RequestMark {
-        path: String,
+        path: ProjectPath,
         s_position: usize,
         e_position: usize,
     },
     ResMark {
-        path: String,
+        path: ProjectPath,
         s_position: usize,
         e_position: usize,
         username: String,
     },
     // Directory system operations
     CreateDirectory {
-        path: String,
+        path: ProjectPath,
     },
     DeleteDirectory {
-        path: String,
+        path: ProjectPath,
     },
     MoveDirectory {
-        path: String,
-        new_path: String,
+        path: ProjectPath,
+        new_path: ProjectPath,
+    },
+    CopyDirectory {
+        path: ProjectPath,
+        new_path: ProjectPath,
     },
     // file system operations
     CreateFile {
-        path: String,
+        path: ProjectPath,
+        /// the unix permissions of the new file (`None` is `0o644`)
+        mode: Option<u32>,
+        /// the first content of the file (the text is utf-8), it is only sent to the server
+        content: Option<Vec<u8>>,
     },
     DeleteFile {
-        path: String,
+        path: ProjectPath,
     },
     MoveFile {
-        path: String,
-        new_path: String,
+        path: ProjectPath,
+        new_path: ProjectPath,
+    },
+    /// the server copies the file (with the changes that are not saved yet)
+    CopyFile {
+        path: ProjectPath,
+        new_path: ProjectPath,
+    },
+    /// put the last deleted file or directory in `path` back from the session trash
+    Restore {
+        path: ProjectPath,
     },
     ReqSaveFile {
-        path: String,
+        path: ProjectPath,
     },
     /// this mean that the server saved the file
     FileSaved {
-        path: String,
+        path: ProjectPath,
+    },
+    /// the metadata of a file changed on the disk (only sent by the server)
+    UpdateMetadata {
+        entry: FileEntry,
+    },
+    /// a symlink is found on the disk (only sent by the server, see `SymlinkPolicy::Link`)
+    /// it is deleted with `DeleteFile`
+    CreateLink {
+        path: ProjectPath,
+        target: String,
     },
 
     Undo {
-        path: String,
+        path: ProjectPath,
     },
 
     Redo {
-        path: String,
+        path: ProjectPath,
     },
 
     AddUsername(String),
+    /// a client joined the session (it is sent to the others)
+    UserJoined {
+        username: String,
+    },
+    /// a client left or is kicked
+    UserLeft {
+        username: String,
+    },
     Error(String),
+    /// the rpc of the client is not applied (it is sent only to that client)
+    Rejected {
+        rpc: Box<RPC>,
+        reason: String,
+    },
     // this is a simple selection of a file
     // also it doesn't support multiple selection as not all editors support it
 }
 
+/// a symlink in the shared directory, it can't be opened
+/// the target is as it is on the disk (it could point outside of the shared directory)
+#[derive(Debug, Clone, Encode, Decode, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Link {
+    pub path: ProjectPath,
+    pub target: String,
+}
+
+/// a shared directory of the host, its content is in `path` (`./` if it is the only one)
+#[derive(Debug, Clone, Encode, Decode, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkspaceRoot {
+    pub name: String,
+    pub path: ProjectPath,
+}
+
 impl RPC {
     const CONFIG: bincode::config::Configuration = bincode::config::standard();
 
@@ -119,4 +246,15 @@ impl RPC {
     pub fn decode(encoded: &[u8]) -> Result<Self, DecodeError> {
         Ok(bincode::decode_from_slice(encoded, Self::CONFIG)?.0)
     }
+    /// the rpc as the server sends it back to everyone (the content of a new file is dropped)
+    pub(crate) fn echo(&self) -> Self {
+        match self {
+            RPC::CreateFile { path, mode, .. } => RPC::CreateFile {
+                path: path.clone(),
+                mode: *mode,
+                content: None,
+            },
+            rpc => rpc.clone(),
+        }
+    }
 }
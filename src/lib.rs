@@ -11,19 +11,34 @@ pub mod prelude {
     pub use super::client::api_client::ClientApi;
     pub use super::client::connect_as_client;
     pub use super::client::API as client_api;
-    pub use super::communication::rpc::RPC;
+    pub use super::communication::event::{ConnectionState, DirChange, Event, Splice};
+    pub use super::communication::file_entry::{Encoding, FileEntry, FileMeta};
+    pub use super::communication::file_tree::crdt::{EditError, MAX_BIN_SIZE};
+    pub use super::communication::file_tree::server_funcs::trash::TrashEntry;
+    pub use super::communication::participant::{Cursor, Participant};
+    pub use super::communication::permissions::{Permissions, Priviledge, Scope};
+    pub use super::communication::project_path::ProjectPath;
+    pub use super::communication::rpc::{Link, WorkspaceRoot, RPC};
     pub use super::server::api_server::ServerApi;
-    pub use super::server::connection::Priviledge;
-    pub use super::server::start_server;
+    pub use super::server::config::{ServerConfig, ShareMode, SharedRoot, SymlinkPolicy};
+    pub use super::server::outbox::{OverflowPolicy, QueueStats};
     pub use super::server::variables::API as server_api;
+    pub use super::server::{start_server, start_server_with_config};
 }
 
 pub mod server_import {
-    pub use super::prelude::{server_api, start_server, Priviledge, ServerApi};
+    pub use super::prelude::{
+        server_api, start_server, start_server_with_config, Event, OverflowPolicy, Participant,
+        Permissions, Priviledge, QueueStats, Scope, ServerApi, ServerConfig, ShareMode, SharedRoot,
+        SymlinkPolicy, TrashEntry, WorkspaceRoot,
+    };
 }
 
 pub mod client_import {
-    pub use super::prelude::{client_api, connect_as_client, ClientApi, Priviledge};
+    pub use super::prelude::{
+        client_api, connect_as_client, ClientApi, Event, FileEntry, Participant, Permissions,
+        Priviledge, Scope, WorkspaceRoot,
+    };
 }
 
-// env you need to define `LOGFILE`, `SERVER_USERNAME` 
+// env you need to define `LOGFILE`, `SERVER_USERNAME` (the default `ServerConfig::host_username`)
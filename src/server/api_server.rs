@@ -1,142 +1,545 @@
-use std::{env, io};
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    time::Instant,
+};
 
-use super::{connection::Priviledge, CLIENTS_RES, CLIENTS_SEND};
+use super::{outbox::QueueStats, CLIENTS};
 use crate::{
     communication::{
-        file_tree::{server_funcs::PubServerFn as _, FileTree},
-        rpc::RPC,
+        event::{ConnectionState, Event},
+        file_entry::FileEntry,
+        file_tree::{
+            crdt::{Crdt, Edit, EditError},
+            move_keys,
+            server_funcs::PubServerFn as _,
+            DirListing, FileTree,
+        },
+        participant::Presence,
+        permissions::Permissions,
+        project_path::ProjectPath,
+        rpc::{Link, WorkspaceRoot, RPC},
+    },
+    server::{
+        config::config,
+        messageing::{self, send_routed, send_to_client, Route},
+        roots,
     },
-    server::messageing::server_send_message,
 };
 
-use futures::SinkExt;
+use automerge::ChangeHash;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tokio_tungstenite::tungstenite::Message;
 
 #[derive(Debug)]
 pub struct ServerApi {
     file_tree: FileTree,
-    sender: UnboundedSender<RPC>,
-    pub receiver: Option<UnboundedReceiver<RPC>>,
+    /// the clients that have a file open (they get its edits and cursors)
+    subscribers: HashMap<String, HashSet<String>>,
+    /// the access requests that are not answered yet
+    access_requests: HashMap<String, access::AccessRequest>,
+    /// when the clients asked for access the last time (for the rate limit)
+    last_access_request: HashMap<String, Instant>,
+    /// the banned usernames and the reasons
+    banned: HashMap<String, String>,
+    /// new clients can't join
+    locked: bool,
+    max_participants: Option<usize>,
+    /// the clients, their cursors and the own edits of the host
+    presence: Presence,
+    /// what the clients see (see `ServerConfig::share`)
+    shared: share::Shared,
+    sender: UnboundedSender<Event>,
+    pub receiver: Option<UnboundedReceiver<Event>>,
 }
 
 impl ServerApi {
     pub(crate) fn new_server() -> Self {
         let (sender, receiver) = unbounded_channel();
+        let _ = sender.send(Event::ConnectionState(ConnectionState::Connected));
         Self {
             file_tree: FileTree::build_file_tree(),
+            subscribers: HashMap::new(),
+            access_requests: HashMap::new(),
+            last_access_request: HashMap::new(),
+            banned: HashMap::new(),
+            locked: false,
+            max_participants: config().max_participants,
+            presence: Presence::default(),
+            shared: share::Shared::new(config().share),
             sender,
             receiver: Some(receiver),
         }
     }
 
     pub async fn read_file_server(&mut self, path: String) -> io::Result<Vec<u8>> {
-        let file = &mut self.file_tree;
-        let res_buf = file.read_buf(&path);
-        match res_buf {
-            Ok(buf) => Ok(buf),
-            Err(e) if e.kind() == std::io::ErrorKind::NotConnected => {
-                file.open_file(path.clone())?;
-                Ok(file.read_buf(&path)?)
+        let path = ProjectPath::new(&path)?;
+        self.load(&path)?;
+        self.file_tree.read_buf(&path)
+    }
+
+    /// open the file from the disk if it is not loaded
+    fn load(&mut self, path: &ProjectPath) -> io::Result<()> {
+        match self.file_tree.read_buf(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotConnected => {
+                self.file_tree.open_file(path.clone())?;
+                let file = self.file_tree.tree.get(path.as_str());
+                self.emit(Event::FileOpened {
+                    path: path.to_string(),
+                    binary: file.is_some_and(Crdt::is_binary),
+                    view_only: false,
+                });
+                Ok(())
             }
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(e),
-            e => e,
+            result => result.map(|_| ()),
+        }
+    }
+
+    /// send an event to the receiver (and the event of following if there is one)
+    fn emit(&mut self, event: Event) {
+        let following = self.presence.observe(&event);
+        for event in [Some(event), following].into_iter().flatten() {
+            let _ = self.sender.send(event);
         }
     }
 
+    /// the receiver of the events is not dropped (the events that are costly are skipped if it is)
+    fn is_listened(&self) -> bool {
+        !self.sender.is_closed()
+    }
+
     /// update the buffer in the  path
     /// if del and text is None, then it is an total update operation
-    /// if one of them is None, then it does nothing
+    /// if one of them is None, then it returns an error
     /// else it is a splice operation
+    /// binary files can't be edited, they should be replaced with `replace_bin`
+    /// the file is opened if it is not loaded
     pub async fn edit_buf(
         &mut self,
         path: String,
         pos: Option<usize>,
         del: Option<isize>,
         text: &str,
-    ) {
+    ) -> Result<(), EditError> {
+        let path = ProjectPath::new(&path).map_err(|e| EditError::Invalid(e.to_string()))?;
+        self.edit_text(path, pos, del, text, true)
+    }
+
+    /// `record` is `false` for the edits of `undo` and `redo`
+    fn edit_text(
+        &mut self,
+        path: ProjectPath,
+        pos: Option<usize>,
+        del: Option<isize>,
+        text: &str,
+        record: bool,
+    ) -> Result<(), EditError> {
+        self.load(&path)
+            .map_err(|e| EditError::Invalid(e.to_string()))?;
         let map = &mut self.file_tree.tree;
-        let file = map.get_mut(&path).unwrap();
-        let result = file.edit(pos, del, text);
+        let file = map.get_mut(path.as_str()).ok_or(EditError::NotLoaded)?;
+        let before = if record {
+            let text = file.read().map_err(|e| EditError::Invalid(e.to_string()))?;
+            Some((file.current_heads(), text))
+        } else {
+            None
+        };
+        let result = file.edit(pos, del, text)?;
+        if let Some((heads, text)) = before {
+            // only the splices of the edit are undone, not everything between them
+            let splices = file.splices_since(&heads);
+            if let (Some(splices), Ok(text)) = (splices, String::from_utf8(text)) {
+                self.presence.edited(path.as_str(), &text, &splices);
+            }
+        }
+        self.send_edit(path, result);
+        Ok(())
+    }
+
+    /// replace the whole content of a binary file
+    /// the content should not be bigger than `MAX_BIN_SIZE`
+    /// if two users replace the file at the same time the last one wins
+    pub async fn replace_bin(&mut self, path: String, content: &[u8]) -> Result<(), EditError> {
+        let path = ProjectPath::new(&path).map_err(|e| EditError::Invalid(e.to_string()))?;
+        self.load(&path)
+            .map_err(|e| EditError::Invalid(e.to_string()))?;
+        let map = &mut self.file_tree.tree;
+        let file = map.get_mut(path.as_str()).ok_or(EditError::NotLoaded)?;
+        let result = file.replace_bin(content)?;
+        self.send_edit(path, result);
+        Ok(())
+    }
+
+    fn send_edit(&self, path: ProjectPath, (changes, old_head_idx, new_heads): Edit) {
+        let route = self.subscribers_route(&path, None);
         let rpc = RPC::EditBuffer {
             path,
-            changes: result.0,
-            old_head_idx: result.1,
-            new_heads: result.2,
+            changes,
+            old_head_idx,
+            new_heads,
         };
-        server_send_message(rpc.encode().unwrap());
+        send_routed(rpc.encode().unwrap(), route);
+    }
+
+    /// `Some(true)` if the file is loaded and binary
+    pub async fn is_binary(&self, path: String) -> Option<bool> {
+        let path = ProjectPath::new(&path).ok()?;
+        self.file_tree
+            .tree
+            .get(path.as_str())
+            .map(|file| file.is_binary())
     }
 
     pub(super) async fn read_rpc(
         &mut self,
         rpc: RPC,
-        client: Priviledge,
+        permissions: &Permissions,
         username: &str,
-    ) -> Result<Message, ()> {
+    ) -> Result<(Message, Route), ()> {
+        let sender = Route::Users(vec![username.to_string()]);
+        if let RPC::ReqAccess { priviledge } = rpc {
+            let reply = match self.request_access(username, permissions, priviledge) {
+                Ok(()) => messageing::RESET_WAITING,
+                Err(e) => {
+                    log::error!("the access request of {username} is rejected: {e}");
+                    RPC::Error(e).encode().map_err(|_| ())?
+                }
+            };
+            return Ok((reply, sender));
+        }
+        let before = self
+            .is_listened()
+            .then(|| Event::heads_before(&self.file_tree, &rpc))
+            .flatten();
         let file = &mut self.file_tree;
-        let result = file.handle_msg(rpc.clone(), Some(client), username).await?; //todo
-        let _ = self.sender.send(rpc);
-        Ok(result)
+        let result = match self.shared.authorize(&rpc).map_err(|e| e.to_string()) {
+            Ok(()) => {
+                file.handle_msg(rpc.clone(), Some(permissions), username)
+                    .await
+            }
+            Err(reason) => Err(reason),
+        };
+        let result = match result {
+            Ok(result) => result,
+            Err(reason) => {
+                // the client could undo what it did optimistically
+                let rpc = RPC::Rejected {
+                    rpc: Box::new(rpc),
+                    reason,
+                };
+                return Ok((rpc.encode().map_err(|_| ())?, sender));
+            }
+        };
+        self.track(&rpc, username);
+        let route = self.route(&rpc, username);
+        let event = match before {
+            Some((path, before)) => Event::changed(&self.file_tree, path, &before),
+            None => Self::event_of(&result),
+        };
+        if let Some(event) = event {
+            self.emit(event);
+        }
+        Ok((result, route))
     }
 
-    pub fn take_receiver(&mut self) -> UnboundedReceiver<RPC> {
-        self.receiver.take().unwrap()
+    /// the event of a message that is sent to the clients
+    fn event_of(message: &Message) -> Option<Event> {
+        match message {
+            // the files are sent directly to the clients so the messages here are small
+            Message::Binary(bytes) if !bytes.is_empty() => {
+                Event::from_rpc(&RPC::decode(bytes).ok()?)
+            }
+            _ => None,
+        }
     }
 
-    pub async fn close_connection(&self, username: &str) -> Result<(), String> {
-        let mut clients_send = CLIENTS_RES.lock().await;
-        match clients_send.remove(username) { 
-            // removing the client from CLIENTS_RES will remove it from CLIENTS_SEND in the next iteration
-            Some(_c) => {
-                Ok(())
+    /// who gets the result of `rpc` that is sent by `username`
+    fn route(&self, rpc: &RPC, username: &str) -> Route {
+        match rpc {
+            // the sender gets its edits back so the history is in the same order as the server
+            RPC::EditBuffer { path, .. } | RPC::Undo { path } | RPC::Redo { path } => {
+                self.subscribers_route(path, None)
+            }
+            RPC::ReqMoveCursor { path, .. } | RPC::RequestMark { path, .. } => {
+                self.subscribers_route(path, Some(username))
             }
-            None => Err("Client not found".to_string()),
+            // acks
+            RPC::ReqSaveFile { .. } => Route::Users(vec![username.to_string()]),
+            // the changes of the file tree
+            _ => Route::All,
         }
     }
 
+    /// the clients that are getting the file in chunks are skipped (see `loaded`)
+    fn subscribers_route(&self, path: &ProjectPath, except: Option<&str>) -> Route {
+        let users = self.subscribers.get(path.as_str()).into_iter().flatten();
+        let loading = self.file_tree.loading.get(path.as_str());
+        Route::Users(
+            users
+                .filter(|user| Some(user.as_str()) != except)
+                .filter(|user| !loading.is_some_and(|loading| loading.contains(*user)))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// the header of a file that is sent in chunks to `username` (`heads` are the heads of the chunks)
+    /// it has the edits that are made after the chunks and the client gets the next edits,
+    /// `None` if the client closed the file or the file is moved (it should be requested again)
+    pub(super) fn loaded(
+        &mut self,
+        username: &str,
+        path: ProjectPath,
+        heads: &[ChangeHash],
+    ) -> Option<Message> {
+        let loading = self.file_tree.loading.get_mut(path.as_str())?;
+        if !loading.remove(username) {
+            return None;
+        }
+        if loading.is_empty() {
+            self.file_tree.loading.remove(path.as_str());
+        }
+        let crdt = self.file_tree.tree.get(path.as_str())?;
+        let binary = crdt.is_binary();
+        let (file, heads_history, head_idx) = crdt.save_after(heads);
+        let rpc = RPC::ResSendFile {
+            path,
+            file,
+            heads_history,
+            head_idx,
+            binary,
+            view_only: false,
+            chunked: true,
+        };
+        rpc.encode().ok()
+    }
+
+    /// keep the subscriptions in sync with the file tree
+    /// a client subscribes to a file when it requests it
+    fn track(&mut self, rpc: &RPC, username: &str) {
+        match rpc {
+            RPC::ReqBufferTree { path } | RPC::OpenFile { path } => {
+                let users = self.subscribers.entry(path.to_string()).or_default();
+                users.insert(username.to_string());
+            }
+            RPC::CloseFile { path } => {
+                if let Some(users) = self.file_tree.loading.get_mut(path.as_str()) {
+                    users.remove(username);
+                }
+                if let Some(users) = self.subscribers.get_mut(path.as_str()) {
+                    users.remove(username);
+                    if users.is_empty() {
+                        self.subscribers.remove(path.as_str());
+                    }
+                }
+                self.release(path.as_str());
+            }
+            RPC::ReqSaveFile { path } => self.release(path.as_str()),
+            RPC::DeleteFile { path } => {
+                self.subscribers.remove(path.as_str());
+                self.file_tree.loading.remove(path.as_str());
+            }
+            RPC::MoveFile { path, new_path } => {
+                if let Some(users) = self.subscribers.remove(path.as_str()) {
+                    self.subscribers.insert(new_path.to_string(), users);
+                }
+                // a file that is moved while it is sent in chunks is requested again
+                self.file_tree.loading.remove(path.as_str());
+            }
+            RPC::DeleteDirectory { path } => {
+                let dir = path.clone().into_dir();
+                self.subscribers
+                    .retain(|file, _| !file.starts_with(dir.as_str()));
+                self.file_tree
+                    .loading
+                    .retain(|file, _| !file.starts_with(dir.as_str()));
+            }
+            RPC::MoveDirectory { path, new_path } => {
+                let (dir, new_dir) = (path.clone().into_dir(), new_path.clone().into_dir());
+                move_keys(&mut self.subscribers, &dir, &new_dir);
+                self.file_tree
+                    .loading
+                    .retain(|file, _| !file.starts_with(dir.as_str()));
+            }
+            _ => {}
+        }
+    }
+
+    /// forget the open files and the access request of a client that left
+    pub(crate) fn user_left(&mut self, username: &str) {
+        self.unsubscribe_user(username);
+        for users in self.file_tree.loading.values_mut() {
+            users.remove(username);
+        }
+        self.access_requests.remove(username);
+    }
+
+    /// remove the subscriptions of a client that left
+    fn unsubscribe_user(&mut self, username: &str) {
+        let mut closed = vec![];
+        self.subscribers.retain(|path, users| {
+            users.remove(username);
+            if users.is_empty() {
+                closed.push(path.clone());
+            }
+            !users.is_empty()
+        });
+        for path in closed {
+            self.release(&path);
+        }
+    }
+
+    /// unload the file from the memory if no client has it open and it is saved
+    fn release(&mut self, path: &str) {
+        if self.subscribers.contains_key(path) {
+            return;
+        }
+        if self.file_tree.tree.get(path).is_some_and(Crdt::is_clean) {
+            self.file_tree.tree.remove(path);
+        }
+    }
+
+    /// the clients that have the file open
+    pub async fn who_has_open(&self, path: String) -> io::Result<Vec<String>> {
+        let path = ProjectPath::new(&path)?.into_file()?;
+        let users = self.subscribers.get(path.as_str()).into_iter().flatten();
+        Ok(users.cloned().collect())
+    }
+
+    /// the events of the session (see `Event`)
+    pub fn take_receiver(&mut self) -> UnboundedReceiver<Event> {
+        self.receiver.take().unwrap()
+    }
+
+    /// close the connection of a client (see `kick`)
+    pub async fn close_connection(&mut self, username: &str) -> Result<(), String> {
+        self.kick(username, "the connection is closed by the host")
+            .await
+    }
+
     pub async fn list_users(&self) -> Vec<String> {
-        let clients = CLIENTS_RES.lock().await;
+        let clients = CLIENTS.lock().await;
         clients.keys().cloned().collect()
     }
 
+    /// change the role of a client and the roles of some paths (see `Permissions`)
+    /// a `Priviledge` could be used for a role without scopes
     pub async fn change_priviledge(
-        &self,
+        &mut self,
         username: &String,
-        priviledge: Priviledge,
+        permissions: impl Into<Permissions>,
     ) -> Result<(), String> {
-        let mut queue = CLIENTS_SEND.lock().await;
-        if let Some(user) = queue.get_mut(username) {
-            CLIENTS_RES
-                .lock()
-                .await
-                .get(username)
-                .unwrap()
-                .lock()
-                .await
-                .priviledge = priviledge;
-            let rpc = RPC::ChangePriviledge { priviledge };
-            let _ = user.lock().await.send(rpc.encode().unwrap()).await;
-            Ok(())
-        } else {
-            Err("Client not found".to_string())
-        }
+        let permissions = permissions.into();
+        let mut clients = CLIENTS.lock().await;
+        let client = clients
+            .get_mut(username)
+            .ok_or_else(|| "Client not found".to_string())?;
+        client.permissions = permissions.clone();
+        drop(clients);
+        self.emit(Event::PrivilegeChanged {
+            username: username.clone(),
+            permissions: permissions.clone(),
+        });
+        let rpc = RPC::ChangePriviledge {
+            priviledge: permissions.priviledge,
+            scopes: permissions.scopes,
+        };
+        send_to_client(username, rpc.encode().unwrap()).await
+    }
+
+    /// the stats of the outbox of every client (how many messages are waiting to be sent...)
+    pub async fn queue_stats(&self) -> HashMap<String, QueueStats> {
+        let clients = CLIENTS.lock().await;
+        clients
+            .iter()
+            .map(|(username, client)| (username.clone(), client.outbox.stats()))
+            .collect()
     }
 
     pub async fn send_rpc(&mut self, rpc: RPC) {
-        if let Ok(x) = self
+        let username = config().host_username.clone();
+        match self
             .file_tree
-            .handle_msg(
-                rpc.clone(),
-                None,
-                &env::var("SERVER_USERNAME").unwrap_or("SERVER".to_string()),
-            )
+            .handle_msg(rpc.clone(), None, &username)
             .await
         {
-            server_send_message(x);
+            Ok(x) => {
+                // the clients don't get their own cursors back
+                if !matches!(rpc, RPC::ReqMoveCursor { .. } | RPC::RequestMark { .. }) {
+                    if let Some(event) = Self::event_of(&x) {
+                        self.emit(event);
+                    }
+                }
+                self.track(&rpc, &username);
+                send_routed(x, self.route(&rpc, &username));
+            }
+            Err(reason) => self.emit(Event::Error(format!("{rpc:?} is rejected: {reason}"))),
         }
     }
 
-    pub async fn get_file_maps(&self) -> (&Vec<String>, &Vec<String>) {
+    /// send an rpc to only one client (in order with the other messages)
+    pub async fn send_to(&self, username: &str, rpc: RPC) -> Result<(), String> {
+        if !CLIENTS.lock().await.contains_key(username) {
+            return Err("Client not found".to_string());
+        }
+        let message = rpc.encode().map_err(|e| e.to_string())?;
+        send_routed(message, Route::Users(vec![username.to_string()]));
+        Ok(())
+    }
+
+    /// send an rpc to the clients that have the file open
+    pub async fn send_to_file_subscribers(&self, path: String, rpc: RPC) -> io::Result<()> {
+        let path = ProjectPath::new(&path)?.into_file()?;
+        let message = rpc.encode().map_err(io::Error::other)?;
+        send_routed(message, self.subscribers_route(&path, None));
+        Ok(())
+    }
+
+    pub async fn get_file_maps(&self) -> (Vec<String>, Vec<String>) {
         self.file_tree.get_maps()
     }
+
+    /// the symlinks in the tree (see `SymlinkPolicy::Link`)
+    pub async fn get_links(&self) -> Vec<Link> {
+        self.file_tree.links()
+    }
+
+    /// the shared directories (see `ServerConfig::roots`)
+    pub async fn roots(&self) -> Vec<WorkspaceRoot> {
+        roots::workspace_roots()
+    }
+
+    /// the size, the mode and the encoding of a file that is in the tree
+    pub async fn file_entry(&self, path: String) -> Option<FileEntry> {
+        self.file_tree.file_entry(&ProjectPath::new(&path).ok()?)
+    }
+
+    /// the root level of the tree, it is sent to the clients when they connect
+    pub(crate) fn root_listing(&self) -> DirListing {
+        let root = ProjectPath::root();
+        let listing = self.file_tree.list_dir(&root, Some(1), None, None);
+        listing.unwrap_or_default() // "./" always exists
+    }
+
+    /// list the content of a directory up to `depth` levels (`None` is the whole subtree)
+    /// returns the files, the empty directories and the directories deeper than `depth`
+    pub async fn list_dir(
+        &self,
+        path: String,
+        depth: Option<usize>,
+    ) -> io::Result<(Vec<FileEntry>, Vec<ProjectPath>, Vec<ProjectPath>)> {
+        let listing = self
+            .file_tree
+            .list_dir(&ProjectPath::new(&path)?, depth, None, None)?;
+        Ok((listing.files, listing.emty_dirs, listing.dirs))
+    }
 }
+
+mod access;
+mod participant;
+pub(crate) mod session;
+mod share;
+#[cfg(test)]
+mod tests;
+mod trash;
+pub(crate) mod watch;
@@ -0,0 +1,107 @@
+use std::time::Instant;
+
+use super::*;
+use crate::communication::permissions::Priviledge;
+use crate::server::API;
+
+/// a request of a client for a higher role
+#[derive(Debug, Clone, Copy)]
+pub(super) struct AccessRequest {
+    priviledge: Priviledge,
+    at: Instant,
+}
+
+impl ServerApi {
+    /// a client asked for a higher role, the host gets `Event::AccessRequested` in the receiver
+    /// the request is denied if it is not answered in `access_request_timeout`
+    /// the error is sent back to the client
+    pub(super) fn request_access(
+        &mut self,
+        username: &str,
+        permissions: &Permissions,
+        priviledge: Priviledge,
+    ) -> Result<(), String> {
+        if priviledge <= permissions.priviledge {
+            return Err(format!(
+                "{username} is already {:?}",
+                permissions.priviledge
+            ));
+        }
+        if self.access_requests.contains_key(username) {
+            return Err("the last access request is not answered yet".to_string());
+        }
+        let now = Instant::now();
+        if let Some(last) = self.last_access_request.get(username) {
+            if now.duration_since(*last) < config().access_request_interval {
+                return Err("wait before asking for access again".to_string());
+            }
+        }
+        self.last_access_request.insert(username.to_string(), now);
+        let request = AccessRequest {
+            priviledge,
+            at: now,
+        };
+        self.access_requests.insert(username.to_string(), request);
+        self.emit(Event::AccessRequested {
+            username: username.to_string(),
+            priviledge,
+        });
+
+        let username = username.to_string();
+        tokio::spawn(async move {
+            tokio::time::sleep(config().access_request_timeout).await;
+            let mut api = API.lock().await;
+            // it could be answered and asked again
+            if api
+                .access_requests
+                .get(&username)
+                .is_some_and(|r| r.at == now)
+            {
+                log::warn!("the access request of {username} timed out");
+                let _ = api.deny_access(&username).await;
+            }
+        });
+        Ok(())
+    }
+
+    /// the access requests that are not answered yet (the username and the requested role)
+    pub async fn access_requests(&self) -> Vec<(String, Priviledge)> {
+        self.access_requests
+            .iter()
+            .map(|(username, request)| (username.clone(), request.priviledge))
+            .collect()
+    }
+
+    /// give the requested role to the client (its scopes are kept)
+    pub async fn approve_access(&mut self, username: &str) -> Result<(), String> {
+        let permissions = Self::permissions_of(username).await?;
+        let request = self
+            .access_requests
+            .remove(username)
+            .ok_or_else(|| format!("{username} didn't ask for access"))?;
+        let permissions = Permissions {
+            priviledge: request.priviledge,
+            ..permissions
+        };
+        self.change_priviledge(&username.to_string(), permissions)
+            .await
+    }
+
+    /// the client keeps its role, it gets its current role with `ChangePriviledge`
+    pub async fn deny_access(&mut self, username: &str) -> Result<(), String> {
+        let permissions = Self::permissions_of(username).await?;
+        self.access_requests
+            .remove(username)
+            .ok_or_else(|| format!("{username} didn't ask for access"))?;
+        self.change_priviledge(&username.to_string(), permissions)
+            .await
+    }
+
+    async fn permissions_of(username: &str) -> Result<Permissions, String> {
+        let clients = CLIENTS.lock().await;
+        let client = clients
+            .get(username)
+            .ok_or_else(|| "Client not found".to_string())?;
+        Ok(client.permissions.clone())
+    }
+}
@@ -0,0 +1,110 @@
+use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+
+use super::*;
+use crate::server::messageing::{disconnect, server_send_message};
+
+/// the close frame that is sent to a client that is kicked or can't join
+pub(crate) fn close_frame(reason: &str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code: CloseCode::Policy,
+        reason: reason.to_string().into(),
+    }))
+}
+
+impl ServerApi {
+    /// check if a client could join the session, the error is the reason it can't
+    /// `connected` is the number of connected clients
+    /// and `used` is `true` if a client with the same username is still connected
+    /// (it could join again after it is removed)
+    pub(crate) fn can_join(
+        &self,
+        username: &str,
+        connected: usize,
+        used: bool,
+    ) -> Result<(), String> {
+        if username == config().host_username {
+            return Err("the username is used by the host".to_string());
+        }
+        if used {
+            return Err("the username is used".to_string());
+        }
+        if let Some(reason) = self.banned.get(username) {
+            return Err(format!("you are banned: {reason}"));
+        }
+        if self.locked {
+            return Err("the session is locked".to_string());
+        }
+        match self.max_participants {
+            Some(max) if connected >= max => Err("the session is full".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// tell the host and the other clients that a client joined
+    pub(crate) fn user_joined(&mut self, username: &str) {
+        let username = username.to_string();
+        self.emit(Event::UserJoined {
+            username: username.clone(),
+        });
+        server_send_message(RPC::UserJoined { username }.encode().unwrap());
+    }
+
+    /// forget the client and tell the host and the others that it left
+    pub(crate) fn user_disconnected(&mut self, username: &str) {
+        self.user_left(username);
+        let username = username.to_string();
+        self.emit(Event::UserLeft {
+            username: username.clone(),
+        });
+        server_send_message(RPC::UserLeft { username }.encode().unwrap());
+    }
+
+    /// close the connection of a client with a close frame that has the reason
+    /// the client could join again (see `ban`)
+    pub async fn kick(&mut self, username: &str, reason: &str) -> Result<(), String> {
+        let mut clients = CLIENTS.lock().await;
+        let client = disconnect(self, &mut clients, username)
+            .ok_or_else(|| "Client not found".to_string())?;
+        // the waiting messages are dropped and the writer stops after the close frame
+        client.outbox.close_with(close_frame(reason));
+        Ok(())
+    }
+
+    /// kick the client (if it is connected) and don't let it join again in this session
+    pub async fn ban(&mut self, username: &str, reason: &str) {
+        self.banned.insert(username.to_string(), reason.to_string());
+        let _ = self.kick(username, reason).await;
+    }
+
+    /// `false` if the client is not banned
+    pub async fn unban(&mut self, username: &str) -> bool {
+        self.banned.remove(username).is_some()
+    }
+
+    /// the banned clients and the reasons
+    pub async fn banned(&self) -> Vec<(String, String)> {
+        let banned = self.banned.iter();
+        banned
+            .map(|(user, reason)| (user.clone(), reason.clone()))
+            .collect()
+    }
+
+    /// new clients can't join (the connected ones stay)
+    pub async fn lock_session(&mut self) {
+        self.locked = true;
+    }
+
+    pub async fn unlock_session(&mut self) {
+        self.locked = false;
+    }
+
+    pub async fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// the number of clients that could be connected at the same time (`None` is no limit)
+    /// the connected clients are not kicked if there are more of them
+    pub async fn set_max_participants(&mut self, max: Option<usize>) {
+        self.max_participants = max;
+    }
+}
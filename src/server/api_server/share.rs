@@ -0,0 +1,167 @@
+use super::*;
+use crate::communication::file_tree::Entry;
+use crate::communication::permissions::paths_of;
+use crate::server::config::ShareMode;
+use crate::server::disk::{Disk, RealDisk};
+
+/// the paths that the host shares in `ShareMode::Selected` (`None` is everything)
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Shared(Option<Vec<ProjectPath>>);
+
+impl Shared {
+    pub(crate) fn new(mode: ShareMode) -> Self {
+        match mode {
+            ShareMode::Everything => Self(None),
+            ShareMode::Selected => Self(Some(vec![])),
+        }
+    }
+
+    /// the path is shared or it is in a shared directory
+    pub(crate) fn contains(&self, path: &str) -> bool {
+        let Some(shared) = &self.0 else {
+            return true;
+        };
+        let file = path.trim_end_matches('/');
+        shared.iter().any(|shared| {
+            shared.trim_end_matches('/') == file || shared.is_dir() && path.starts_with(&**shared)
+        })
+    }
+
+    /// the clients see the shared paths and the directories that have them
+    pub(crate) fn visible(&self, path: &str) -> bool {
+        let dir = format!("{}/", path.trim_end_matches('/'));
+        let has_shared = |shared: &Vec<ProjectPath>| {
+            dir == "./" || shared.iter().any(|shared| shared.starts_with(&dir))
+        };
+        self.contains(path) || self.0.as_ref().is_some_and(has_shared)
+    }
+
+    /// the paths of a client rpc should be shared (the parents could only be listed)
+    pub(crate) fn authorize(&self, rpc: &RPC) -> io::Result<()> {
+        let listed = match rpc {
+            RPC::ReqListDir { path, .. } | RPC::ReqBufferTree { path } => self.visible(path),
+            _ => paths_of(rpc).iter().all(|path| self.contains(path)),
+        };
+        if !listed {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "the path is not shared",
+            ));
+        }
+        Ok(())
+    }
+
+    /// the disk without what is not shared
+    pub(crate) fn disk<'a, D: Disk>(&'a self, disk: &'a D) -> SharedDisk<'a, D> {
+        SharedDisk { disk, shared: self }
+    }
+}
+
+/// the disk as the clients see it (see `Shared::visible`)
+pub(crate) struct SharedDisk<'a, D: Disk> {
+    disk: &'a D,
+    shared: &'a Shared,
+}
+
+impl<D: Disk> Disk for SharedDisk<'_, D> {
+    fn entry(&self, path: &str) -> Option<Entry> {
+        self.disk.entry(path).filter(|_| self.shared.visible(path))
+    }
+
+    fn walk(&self, dir: &str) -> Vec<(String, Entry)> {
+        let entries = self.disk.walk(dir).into_iter();
+        entries
+            .filter(|(path, _)| self.shared.visible(path))
+            .collect()
+    }
+
+    fn read(&self, path: &str) -> Option<Vec<u8>> {
+        self.disk.read(path).filter(|_| self.shared.contains(path))
+    }
+
+    fn hash(&self, path: &str) -> Option<u64> {
+        self.disk.hash(path).filter(|_| self.shared.contains(path))
+    }
+
+    fn current(&self, path: &str) -> Option<Entry> {
+        self.disk
+            .current(path)
+            .filter(|_| self.shared.visible(path))
+    }
+}
+
+impl ServerApi {
+    /// share a file or a directory (with its content) with the clients (see `ShareMode::Selected`)
+    /// the clients get it with its parents like new paths
+    pub async fn share(&mut self, path: String) -> io::Result<()> {
+        self.share_disk(ProjectPath::new(&path)?, &RealDisk)
+    }
+
+    /// stop sharing a path that was shared with `share`
+    /// the clients get it as deleted (and the parents that have nothing else shared),
+    /// nothing is deleted on the disk
+    pub async fn unshare(&mut self, path: String) -> io::Result<()> {
+        self.unshare_disk(ProjectPath::new(&path)?, &RealDisk)
+    }
+
+    /// the paths that are shared with `share` (`None` if everything is shared)
+    pub async fn shared(&self) -> Option<Vec<ProjectPath>> {
+        self.shared.0.clone()
+    }
+
+    pub(crate) fn share_disk(&mut self, path: ProjectPath, disk: &impl Disk) -> io::Result<()> {
+        if self.shared.0.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "everything is shared (see `ServerConfig::share`)",
+            ));
+        }
+        let path = match disk.entry(&path) {
+            Some(Entry::Dir) => path.into_dir(),
+            Some(_) => path.into_file()?,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{path} is not on the disk"),
+                ))
+            }
+        };
+        if self.shared.contains(&path) {
+            return Ok(());
+        }
+        if let Some(shared) = &mut self.shared.0 {
+            // the paths in a directory are shared with it
+            shared.retain(|shared| !shared.starts_with(&*path));
+            shared.push(path.clone());
+        }
+        let shared = self.shared.clone();
+        self.sync_disk(&path, &shared.disk(disk));
+        Ok(())
+    }
+
+    pub(crate) fn unshare_disk(&mut self, path: ProjectPath, disk: &impl Disk) -> io::Result<()> {
+        let Some(shared) = &mut self.shared.0 else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "everything is shared (see `ServerConfig::share`)",
+            ));
+        };
+        let file = path.trim_end_matches('/');
+        let Some(i) = shared
+            .iter()
+            .position(|shared| shared.trim_end_matches('/') == file)
+        else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{path} is not shared"),
+            ));
+        };
+        let path = shared.remove(i);
+        let shared = self.shared.clone();
+        self.sync_disk(&path, &shared.disk(disk));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+
+use super::*;
+use crate::communication::file_entry::FileMeta;
+use crate::server::config::ShareMode;
+
+/// the paths on the disk, the directories end with `/`
+struct SimDisk(BTreeMap<String, Entry>);
+
+impl SimDisk {
+    fn new(paths: &[&str]) -> Self {
+        let entry = |path: &str| match path.ends_with('/') {
+            true => Entry::Dir,
+            false => Entry::File(FileMeta::default()),
+        };
+        Self(paths.iter().map(|p| (p.to_string(), entry(p))).collect())
+    }
+}
+
+impl Disk for SimDisk {
+    fn entry(&self, path: &str) -> Option<Entry> {
+        let file = path.trim_end_matches('/');
+        if file == "." || self.0.contains_key(&format!("{file}/")) {
+            Some(Entry::Dir)
+        } else {
+            self.0.get(file).cloned()
+        }
+    }
+
+    fn walk(&self, dir: &str) -> Vec<(String, Entry)> {
+        let paths = self.0.iter().filter(|(path, _)| path.starts_with(dir));
+        paths
+            .map(|(path, entry)| (path.clone(), entry.clone()))
+            .collect()
+    }
+
+    fn read(&self, _: &str) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+fn path(path: &str) -> ProjectPath {
+    ProjectPath::new(path).unwrap()
+}
+
+fn paths(api: &ServerApi) -> Vec<String> {
+    let paths = api.file_tree.subtree(&ProjectPath::root()).into_keys();
+    paths.collect()
+}
+
+fn selected() -> (ServerApi, SimDisk) {
+    let disk = SimDisk::new(&[
+        "./.env",
+        "./src/",
+        "./src/lib.rs",
+        "./src/main.rs",
+        "./docs/",
+        "./docs/a.md",
+    ]);
+    let mut api = ServerApi::new_server();
+    api.shared = Shared::new(ShareMode::Selected);
+    api.rescan_disk(&disk).unwrap();
+    (api, disk)
+}
+
+#[test]
+fn only_the_shared_paths_are_seen() {
+    let (mut api, disk) = selected();
+    assert!(paths(&api).is_empty());
+    api.share_disk(path("./src/lib.rs"), &disk).unwrap();
+    api.share_disk(path("./docs"), &disk).unwrap();
+    assert_eq!(
+        paths(&api),
+        ["./docs/", "./docs/a.md", "./src/", "./src/lib.rs"]
+    );
+    // the watcher doesn't add what is not shared
+    api.rescan_disk(&disk).unwrap();
+    assert_eq!(paths(&api).len(), 4);
+    assert!(api.share_disk(path("./missing.rs"), &disk).is_err());
+
+    api.unshare_disk(path("./src/lib.rs"), &disk).unwrap();
+    assert_eq!(paths(&api), ["./docs/", "./docs/a.md"]);
+    // only the shared paths can be unshared (not what is in them)
+    let e = api.unshare_disk(path("./docs/a.md"), &disk).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::NotFound);
+    assert!(disk.entry("./src/lib.rs").is_some());
+}
+
+#[test]
+fn rpcs_outside_of_the_shared_paths_are_rejected() {
+    let (mut api, disk) = selected();
+    api.share_disk(path("./src/lib.rs"), &disk).unwrap();
+    let allowed = |rpc: RPC| api.shared.authorize(&rpc).is_ok();
+
+    assert!(allowed(RPC::OpenFile {
+        path: path("./src/lib.rs")
+    }));
+    assert!(!allowed(RPC::OpenFile {
+        path: path("./src/main.rs")
+    }));
+    assert!(!allowed(RPC::OpenFile {
+        path: path("./.env")
+    }));
+    // the parents are listed but nothing can be made in them
+    assert!(allowed(RPC::ReqListDir {
+        path: path("./src/"),
+        depth: None,
+        cursor: None,
+        limit: None
+    }));
+    assert!(!allowed(RPC::CreateFile {
+        path: path("./src/new.rs"),
+        mode: None,
+        content: None
+    }));
+    assert!(!allowed(RPC::MoveFile {
+        path: path("./src/lib.rs"),
+        new_path: path("./lib.rs")
+    }));
+
+    // everything is shared by default
+    let api = ServerApi::new_server();
+    assert!(api
+        .shared
+        .authorize(&RPC::DeleteFile {
+            path: path("./.env")
+        })
+        .is_ok());
+    let mut api = api;
+    let e = api.share_disk(path("./src/"), &disk).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+}
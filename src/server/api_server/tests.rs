@@ -0,0 +1,264 @@
+use super::*;
+use crate::communication::permissions::Priviledge;
+
+fn path(path: &str) -> ProjectPath {
+    ProjectPath::new(path).unwrap()
+}
+
+fn users(route: Route) -> Vec<String> {
+    match route {
+        Route::Users(mut users) => {
+            users.sort();
+            users
+        }
+        Route::All => panic!("expected users"),
+    }
+}
+
+#[test]
+fn route_to_subscribers() {
+    let mut api = ServerApi::new_server();
+    let file = path("./a.txt");
+    for user in ["a", "b"] {
+        api.track(&RPC::ReqBufferTree { path: file.clone() }, user);
+    }
+    api.track(
+        &RPC::ReqBufferTree {
+            path: path("./b.txt"),
+        },
+        "c",
+    );
+
+    let edit = RPC::EditBuffer {
+        path: file.clone(),
+        changes: vec![],
+        old_head_idx: 0,
+        new_heads: vec![],
+    };
+    assert_eq!(users(api.route(&edit, "a")), ["a", "b"]);
+    let cursor = RPC::ReqMoveCursor {
+        path: file.clone(),
+        position: 0,
+    };
+    assert_eq!(users(api.route(&cursor, "a")), ["b"]);
+    let save = RPC::ReqSaveFile { path: file.clone() };
+    assert_eq!(users(api.route(&save, "c")), ["c"]);
+    let create = RPC::CreateFile {
+        path: path("./c.txt"),
+        mode: None,
+        content: None,
+    };
+    assert_eq!(api.route(&create, "c"), Route::All);
+
+    // the subscriptions follow the file
+    let moved = RPC::MoveFile {
+        path: file.clone(),
+        new_path: path("./d/a.txt"),
+    };
+    api.track(&moved, "a");
+    let cursor = RPC::ReqMoveCursor {
+        path: path("./d/a.txt"),
+        position: 0,
+    };
+    assert_eq!(users(api.route(&cursor, "c")), ["a", "b"]);
+    api.unsubscribe_user("a");
+    assert_eq!(users(api.route(&cursor, "c")), ["b"]);
+
+    // and the directories that they are in
+    let moved = RPC::MoveDirectory {
+        path: path("./d/"),
+        new_path: path("./e/d/"),
+    };
+    api.track(&moved, "a");
+    assert!(users(api.route(&cursor, "c")).is_empty());
+    let cursor = RPC::ReqMoveCursor {
+        path: path("./e/d/a.txt"),
+        position: 0,
+    };
+    assert_eq!(users(api.route(&cursor, "c")), ["b"]);
+    api.track(&RPC::DeleteDirectory { path: path("./e/") }, "c");
+    assert!(users(api.route(&cursor, "c")).is_empty());
+}
+
+#[tokio::test]
+async fn move_dir_keeps_open_files() {
+    use crate::communication::participant::Participant as _;
+    let mut api = ServerApi::new_server();
+    let file = "./src/server/config.rs";
+    api.edit_buf(file.to_string(), Some(0), Some(0), "// moved\n")
+        .await
+        .unwrap();
+    api.track(&RPC::OpenFile { path: path(file) }, "a");
+    let before = api.file_tree.read_buf(file).unwrap();
+
+    // a nested directory to a parent that doesn't exist yet
+    let moved = RPC::MoveDirectory {
+        path: path("./src/server/"),
+        new_path: path("./old/server/"),
+    };
+    api.send_rpc(moved).await;
+    let file = "./old/server/config.rs";
+    // the edit is not saved so the document is the same one
+    assert_eq!(api.file_tree.read_buf(file).unwrap(), before);
+    assert_eq!(api.who_has_open(file.to_string()).await.unwrap(), ["a"]);
+    api.undo(file.to_string()).await.unwrap();
+    assert!(!api
+        .file_tree
+        .read_buf(file)
+        .unwrap()
+        .starts_with(b"// moved"));
+}
+
+#[tokio::test]
+async fn unload_closed_files() {
+    let mut api = ServerApi::new_server();
+    let file = path("./a.txt");
+    let loaded = Crdt::open(automerge::Automerge::new());
+    api.file_tree.tree.insert(file.to_string(), loaded);
+    for user in ["a", "b"] {
+        api.track(&RPC::OpenFile { path: file.clone() }, user);
+    }
+    let mut open = api.who_has_open(file.to_string()).await.unwrap();
+    open.sort();
+    assert_eq!(open, ["a", "b"]);
+
+    api.track(&RPC::CloseFile { path: file.clone() }, "a");
+    assert_eq!(api.who_has_open(file.to_string()).await.unwrap(), ["b"]);
+    assert!(api.file_tree.tree.contains_key(file.as_str()));
+    // the last client left and the file is saved
+    api.unsubscribe_user("b");
+    assert!(api.who_has_open(file.to_string()).await.unwrap().is_empty());
+    assert!(!api.file_tree.tree.contains_key(file.as_str()));
+}
+
+#[tokio::test]
+async fn access_requests() {
+    let mut api = ServerApi::new_server();
+    let mut receiver = api.take_receiver();
+    assert_eq!(
+        receiver.recv().await,
+        Some(Event::ConnectionState(ConnectionState::Connected))
+    );
+    let viewer = Permissions::from(Priviledge::Viewer);
+    assert!(api
+        .request_access("a", &viewer, Priviledge::Viewer)
+        .is_err());
+    api.request_access("a", &viewer, Priviledge::Editor)
+        .unwrap();
+    assert_eq!(
+        receiver.recv().await,
+        Some(Event::AccessRequested {
+            username: "a".to_string(),
+            priviledge: Priviledge::Editor
+        })
+    );
+    assert_eq!(
+        api.access_requests().await,
+        [("a".to_string(), Priviledge::Editor)]
+    );
+    // only one request at a time
+    assert!(api
+        .request_access("a", &viewer, Priviledge::Maintainer)
+        .is_err());
+    // the client is not connected
+    assert!(api.approve_access("a").await.is_err());
+    api.user_left("a");
+    assert!(api.access_requests().await.is_empty());
+    // the rate limit is kept after leaving
+    assert!(api
+        .request_access("a", &viewer, Priviledge::Editor)
+        .is_err());
+}
+
+#[tokio::test]
+async fn max_participants() {
+    let mut api = ServerApi::new_server();
+    api.set_max_participants(Some(2)).await;
+    assert!(api.can_join("a", 1, false).is_ok());
+    assert!(api.can_join("a", 2, false).is_err());
+    // a username can't be used by two clients
+    assert!(api.can_join("a", 1, true).is_err());
+}
+
+#[tokio::test]
+async fn host_edits_and_undo() {
+    use crate::communication::participant::Participant as _;
+
+    let mut api = ServerApi::new_server();
+    // the file is opened by the edit
+    api.edit_buf("./Cargo.toml".to_string(), Some(0), Some(0), "# ")
+        .await
+        .unwrap();
+    let text = api
+        .read_file_server("./Cargo.toml".to_string())
+        .await
+        .unwrap();
+    assert!(text.starts_with(b"# "));
+    api.undo("./Cargo.toml".to_string()).await.unwrap();
+    let undone = api
+        .read_file_server("./Cargo.toml".to_string())
+        .await
+        .unwrap();
+    assert_eq!(undone, &text[2..]);
+    api.redo("./Cargo.toml".to_string()).await.unwrap();
+    let redone = api
+        .read_file_server("./Cargo.toml".to_string())
+        .await
+        .unwrap();
+    assert_eq!(redone, text);
+    assert_eq!(api.participants().await, [api.username()]);
+}
+
+#[tokio::test]
+async fn edits_while_a_file_is_sent_in_chunks() {
+    use crate::communication::file_tree::client_funcs::PubClientFn;
+
+    let mut api = ServerApi::new_server();
+    let file = "./Cargo.toml";
+    api.edit_buf(file.to_string(), Some(0), Some(0), "# ")
+        .await
+        .unwrap();
+    api.track(&RPC::OpenFile { path: path(file) }, "a");
+    // "b" gets the file in chunks like in `FileTree::handle_msg`
+    api.track(&RPC::OpenFile { path: path(file) }, "b");
+    api.file_tree
+        .loading
+        .entry(file.to_string())
+        .or_default()
+        .insert("b".to_string());
+    let crdt = &api.file_tree.tree[file];
+    let (chunks, heads) = (crdt.save().0, crdt.heads());
+
+    // the edits are not routed to "b" before the header
+    api.edit_buf(file.to_string(), Some(2), Some(0), "edited ")
+        .await
+        .unwrap();
+    let cursor = RPC::ReqMoveCursor {
+        path: path(file),
+        position: 0,
+    };
+    assert_eq!(users(api.route(&cursor, "host")), ["a"]);
+    let header = api.loaded("b", path(file), &heads).unwrap();
+    assert_eq!(users(api.route(&cursor, "host")), ["a", "b"]);
+    assert!(api.loaded("b", path(file), &heads).is_none());
+
+    // the header has the edit
+    let mut client = <FileTree as PubClientFn>::build_tree(vec![], vec![], vec![], vec![]);
+    let total = chunks.len() as u64;
+    for (i, data) in chunks.chunks(1024).enumerate() {
+        let chunk = RPC::ResFileChunk {
+            path: path(file),
+            offset: i as u64 * 1024,
+            total,
+            data: data.to_vec(),
+        };
+        PubClientFn::handle_msg(&mut client, chunk);
+    }
+    let Message::Binary(header) = header else {
+        panic!("the header is not binary");
+    };
+    PubClientFn::handle_msg(&mut client, RPC::decode(&header).unwrap());
+    let text = client.tree[file].read().unwrap();
+    assert!(text.starts_with(b"# edited "));
+    assert_eq!(text, api.file_tree.tree[file].read().unwrap());
+}
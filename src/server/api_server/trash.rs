@@ -0,0 +1,43 @@
+use super::*;
+use crate::communication::file_tree::server_funcs::trash::TrashEntry;
+
+impl ServerApi {
+    /// the deleted files and directories that could be restored, the oldest first
+    pub async fn trash(&self) -> Vec<TrashEntry> {
+        self.file_tree.trash_entries()
+    }
+
+    /// put a path of the trash back (the clients get it like a new path)
+    /// a client could do the same with `RPC::Restore` for the last deleted path
+    pub async fn restore(&mut self, id: u64) -> io::Result<()> {
+        let path = self.file_tree.restore(id)?;
+        let rpc = RPC::Restore { path };
+        if let Some(event) = Event::from_rpc(&rpc) {
+            self.emit(event);
+        }
+        match rpc.encode() {
+            Ok(message) => send_routed(message, Route::All),
+            Err(e) => log::error!("{e}"),
+        }
+        Ok(())
+    }
+
+    /// delete everything in the trash for good
+    pub async fn purge_trash(&mut self) -> io::Result<()> {
+        self.file_tree.purge_trash()
+    }
+
+    /// the server is stopped, the trash is deleted if `ServerConfig::purge_trash_on_shutdown`
+    pub async fn shutdown(&mut self) {
+        if !config().purge_trash_on_shutdown {
+            let trash = self.file_tree.trash_entries();
+            if !trash.is_empty() {
+                log::warn!("the trash is kept in {:?}", config().trash_dir);
+            }
+            return;
+        }
+        if let Err(e) = self.file_tree.purge_trash() {
+            log::error!("the trash can't be deleted: {e}");
+        }
+    }
+}
@@ -0,0 +1,507 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use notify::DebouncedEvent;
+
+use super::*;
+use crate::communication::file_entry::FileMeta;
+use crate::communication::file_tree::Entry;
+use crate::server::{
+    disk::{hash, Disk, RealDisk},
+    roots,
+};
+
+/// the entries of a part of the tree or the disk by their paths (the directories end with `/`)
+type Entries = BTreeMap<String, Entry>;
+
+impl ServerApi {
+    /// compare the whole tree with the disk and send the differences as normal changes
+    /// (the watcher could miss events, like when its queue overflows or on network file systems)
+    /// the moved files are found by their content if it is known (see `moves`)
+    pub async fn rescan(&mut self) -> io::Result<()> {
+        self.rescan_disk(&RealDisk)
+    }
+
+    pub(crate) fn rescan_disk(&mut self, disk: &impl Disk) -> io::Result<()> {
+        let shared = self.shared.clone();
+        let disk = &shared.disk(disk);
+        if disk.entry("./") != Some(Entry::Dir) {
+            // everything would be deleted
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "the shared directory can't be read",
+            ));
+        }
+        self.sync_disk(&ProjectPath::root(), disk);
+        Ok(())
+    }
+
+    /// apply an event of the watcher, the tree is compared with the disk where the event happened
+    /// so the changes that the server made (and are already in the tree) are not sent again
+    pub(crate) fn disk_event(&mut self, event: DebouncedEvent, disk: &impl Disk) {
+        // the paths that are not shared are not on the disk for the clients
+        let shared = self.shared.clone();
+        let disk = &shared.disk(disk);
+        match event {
+            DebouncedEvent::Create(path) | DebouncedEvent::Remove(path) => {
+                if let Some(path) = watched(&path) {
+                    self.sync_disk(&path, disk);
+                }
+            }
+            DebouncedEvent::Rename(old_path, new_path) => {
+                if let (Some(old_path), Some(new_path)) = (watched(&old_path), watched(&new_path)) {
+                    self.disk_rename(old_path, new_path, disk);
+                }
+            }
+            // some events are lost, the whole tree is compared with the disk
+            DebouncedEvent::Rescan => {
+                if let Err(e) = self.rescan_disk(disk) {
+                    log::error!("{e}");
+                }
+            }
+            DebouncedEvent::Error(e, path) => {
+                log::error!("watch error: {e} {path:?}");
+                if let Err(e) = self.rescan_disk(disk) {
+                    log::error!("{e}");
+                }
+            }
+            // the content of the files is changed by the editors and saved with `save`,
+            // only their metadata is updated
+            DebouncedEvent::Write(path) | DebouncedEvent::Chmod(path) => {
+                if let Some(path) = watched(&path) {
+                    self.sync_disk(&path, disk);
+                }
+            }
+            DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_) => {}
+        }
+    }
+
+    /// keep the loaded file of a moved path, anything else is synced like the other events
+    fn disk_rename(&mut self, old_path: ProjectPath, new_path: ProjectPath, disk: &impl Disk) {
+        let entry = self.file_tree.entry(&old_path);
+        // the links are deleted and made again
+        let rpc = RPC::MoveFile {
+            path: old_path.clone(),
+            new_path: new_path.clone(),
+        };
+        let moved = matches!(entry, Some(Entry::File(_) | Entry::Dir))
+            && !self.is_stale(&rpc, disk)
+            && disk.entry(&old_path).is_none()
+            && self.file_tree.entry(&new_path).is_none()
+            && disk
+                .entry(&new_path)
+                .zip(entry.as_ref())
+                .is_some_and(|(new, old)| new.same_kind(old));
+        if moved {
+            let (path, new_path) = match entry {
+                Some(Entry::Dir) => (old_path.clone().into_dir(), new_path.clone().into_dir()),
+                _ => (old_path.clone(), new_path.clone()),
+            };
+            self.disk_move(path, new_path);
+        }
+        // the changes after the move (or everything if it is not a move that the tree could follow)
+        self.sync_disk(&old_path, disk);
+        self.sync_disk(&new_path, disk);
+    }
+
+    /// make `path` (with its parents and its content) the same in the tree as on the disk
+    /// the moves are found first so the loaded files and the subscriptions are kept
+    pub(super) fn sync_disk(&mut self, path: &ProjectPath, disk: &impl Disk) {
+        let on_disk = disk_subtree(disk, path);
+        for (old_path, new_path) in self.moves(&self.file_tree.subtree(path), &on_disk, disk) {
+            let rpc = RPC::MoveFile {
+                path: old_path.clone(),
+                new_path: new_path.clone(),
+            };
+            if !self.is_stale(&rpc, disk) {
+                self.disk_move(old_path, new_path);
+            }
+        }
+        for rpc in diff(&self.file_tree.subtree(path), &on_disk) {
+            if self.is_stale(&rpc, disk) {
+                log::warn!("{rpc:?} is skipped, the path is changed on the disk since the scan");
+                continue;
+            }
+            self.apply_disk_change(rpc);
+        }
+    }
+
+    /// the change is not the same as the disk now (see `Disk::current`)
+    /// the path is changed by a client after the disk was read, like a new file that is not
+    /// in the snapshot, the tree is kept as the watcher will send the change of the client
+    fn is_stale(&self, rpc: &RPC, disk: &impl Disk) -> bool {
+        match rpc {
+            RPC::DeleteFile { path } | RPC::DeleteDirectory { path } => {
+                let tree = self.file_tree.entry(path);
+                let current = disk.current(path);
+                current
+                    .zip(tree)
+                    .is_some_and(|(current, tree)| current.same_kind(&tree))
+            }
+            RPC::CreateFile { path, .. }
+            | RPC::CreateDirectory { path }
+            | RPC::CreateLink { path, .. }
+            | RPC::UpdateMetadata {
+                entry: FileEntry { path, .. },
+            } => disk.current(path).is_none(),
+            RPC::MoveFile { path, new_path } | RPC::MoveDirectory { path, new_path } => {
+                disk.current(path).is_some() || disk.current(new_path).is_none()
+            }
+            _ => false,
+        }
+    }
+
+    /// the moves in the difference of `tree` and `disk`
+    /// a directory is moved if its content is the same and a file if its hash is the same,
+    /// the content of a deleted file is only known if it is loaded (the saved content is hashed)
+    fn moves(
+        &self,
+        tree: &Entries,
+        disk: &Entries,
+        on_disk: &impl Disk,
+    ) -> Vec<(ProjectPath, ProjectPath)> {
+        let deleted = top_paths(difference(tree, disk));
+        let mut created = top_paths(difference(disk, tree));
+        let mut moves = vec![];
+        for dir in deleted.iter().filter(|path| path.ends_with('/')) {
+            let inside = content(tree, dir);
+            let same = created
+                .iter()
+                .position(|new| new.ends_with('/') && same_content(&inside, &content(disk, new)));
+            if let Some(i) = same.filter(|_| !inside.is_empty()) {
+                moves.push((dir.clone(), created.remove(i)));
+            }
+        }
+
+        let moved_dirs: Vec<String> = moves.iter().map(|(dir, _)| dir.clone()).collect();
+        // by the size, the time and the hash (a moved file has the same size and time)
+        let mut hashes: HashMap<(u64, u64, u64), String> = HashMap::new();
+        for (file, entry) in difference(tree, disk) {
+            let Entry::File(meta) = entry else {
+                continue;
+            };
+            if meta.size > config().view_only_size
+                || moved_dirs.iter().any(|dir| file.starts_with(dir))
+            {
+                continue;
+            }
+            // the moved copy on the disk doesn't have the changes that are not saved
+            let loaded = self
+                .file_tree
+                .tree
+                .get(file)
+                .and_then(|crdt| crdt.read_saved().ok());
+            let content = loaded.or_else(|| self.file_tree.views.get(file).cloned());
+            if let Some(content) = content.filter(|content| !content.is_empty()) {
+                let key = (meta.size, meta.mtime, hash(&content));
+                hashes.entry(key).or_insert(file.clone());
+            }
+        }
+        if !hashes.is_empty() {
+            let new_files = difference(disk, tree).filter_map(|(path, entry)| match entry {
+                Entry::File(meta) => Some((path, meta)),
+                _ => None,
+            });
+            let is_new = |file: &String| {
+                let in_top =
+                    |top: &String| file == top || top.ends_with('/') && file.starts_with(top);
+                created.iter().any(in_top)
+            };
+            for (file, meta) in new_files.filter(|(file, _)| is_new(file)) {
+                // only the files with the same size and time are hashed
+                let same =
+                    |(size, mtime, _): &(u64, u64, u64)| (*size, *mtime) == (meta.size, meta.mtime);
+                if !hashes.keys().any(same) {
+                    continue;
+                }
+                let old = on_disk
+                    .hash(file)
+                    .and_then(|hash| hashes.remove(&(meta.size, meta.mtime, hash)));
+                if let Some(old) = old {
+                    moves.push((old, file.clone()));
+                }
+            }
+        }
+        let path = |path: String| ProjectPath::new(&path).ok();
+        let moves = moves.into_iter();
+        moves
+            .filter_map(|(old, new)| Some((path(old)?, path(new)?)))
+            .collect()
+    }
+
+    /// the size and the time of the loaded files by their paths (only their moves are found)
+    /// the new files that have the same are hashed by `Snapshot::scan`
+    pub(crate) fn loaded_files(&self) -> HashMap<String, (u64, u64)> {
+        let loaded = self
+            .file_tree
+            .tree
+            .keys()
+            .chain(self.file_tree.views.keys());
+        loaded
+            .filter_map(
+                |file| match self.file_tree.entry(&ProjectPath::new(file).ok()?)? {
+                    Entry::File(meta) if meta.size <= config().view_only_size => {
+                        Some((file.clone(), (meta.size, meta.mtime)))
+                    }
+                    _ => None,
+                },
+            )
+            .collect()
+    }
+
+    /// move a path in the tree and make the parents of the new path first (so the clients know them)
+    fn disk_move(&mut self, path: ProjectPath, new_path: ProjectPath) {
+        if self.file_tree.entry(&new_path).is_some() {
+            return;
+        }
+        for dir in new_path.ancestors() {
+            let Ok(dir) = ProjectPath::new(&dir) else {
+                continue;
+            };
+            if self.file_tree.entry(&dir).is_none() {
+                self.apply_disk_change(RPC::CreateDirectory { path: dir });
+            }
+        }
+        let rpc = if path.is_dir() {
+            RPC::MoveDirectory { path, new_path }
+        } else {
+            RPC::MoveFile { path, new_path }
+        };
+        self.apply_disk_change(rpc);
+    }
+
+    /// apply a change of the disk to the tree and send it to everyone
+    fn apply_disk_change(&mut self, rpc: RPC) {
+        if let Err(e) = self.file_tree.sync(&rpc) {
+            log::error!("{rpc:?} can't be synced: {e}");
+            return;
+        }
+        self.track(&rpc, &config().host_username);
+        if let Some(event) = Event::from_rpc(&rpc) {
+            self.emit(event);
+        }
+        match rpc.encode() {
+            Ok(message) => send_routed(message, Route::All),
+            Err(e) => log::error!("{e}"),
+        }
+    }
+}
+
+/// the paths of the project where the watcher saw a change (`./` if everything should be compared)
+pub(crate) fn event_paths(event: &DebouncedEvent) -> Vec<ProjectPath> {
+    match event {
+        DebouncedEvent::Create(path)
+        | DebouncedEvent::Remove(path)
+        | DebouncedEvent::Write(path)
+        | DebouncedEvent::Chmod(path) => watched(path).into_iter().collect(),
+        DebouncedEvent::Rename(old_path, new_path) => [old_path, new_path]
+            .into_iter()
+            .filter_map(|path| watched(path))
+            .collect(),
+        DebouncedEvent::Rescan | DebouncedEvent::Error(..) => vec![ProjectPath::root()],
+        DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_) => vec![],
+    }
+}
+
+/// the paths of notify are absolute (and could be like `/root/./dir`) so they are normalized
+/// (they are in the directory of their root in the tree)
+fn watched(path: &Path) -> Option<ProjectPath> {
+    roots::project_path(path, false)
+        .map_err(|e| log::error!("{e}"))
+        .ok()
+}
+
+/// the entries of some paths of the disk (with their parents and their content)
+/// they are read without the lock of the api so a large walk doesn't block the editors
+/// (see `watch_events`), the content of the files is read from the disk
+pub(crate) struct Snapshot<D: Disk> {
+    disk: D,
+    entries: Entries,
+    /// the new files that could be the `loaded` files after a move
+    hashes: HashMap<String, u64>,
+}
+
+impl<D: Disk> Snapshot<D> {
+    /// `loaded` is from `ServerApi::loaded_files`
+    pub(crate) fn scan(
+        disk: D,
+        paths: &[ProjectPath],
+        loaded: &HashMap<String, (u64, u64)>,
+    ) -> Self {
+        let mut entries = Entries::new();
+        if disk.entry("./") == Some(Entry::Dir) {
+            entries.insert("./".to_string(), Entry::Dir);
+        }
+        for path in paths {
+            entries.extend(disk_subtree(&disk, path));
+        }
+        let same: HashSet<&(u64, u64)> = loaded.values().collect();
+        let mut hashes = HashMap::new();
+        for (path, entry) in &entries {
+            let Entry::File(meta) = entry else {
+                continue;
+            };
+            if !loaded.contains_key(path) && same.contains(&(meta.size, meta.mtime)) {
+                if let Some(hash) = disk.hash(path) {
+                    hashes.insert(path.clone(), hash);
+                }
+            }
+        }
+        Self {
+            disk,
+            entries,
+            hashes,
+        }
+    }
+}
+
+impl<D: Disk> Disk for Snapshot<D> {
+    fn entry(&self, path: &str) -> Option<Entry> {
+        let file = path.trim_end_matches('/');
+        let dir = self.entries.get(&format!("{file}/"));
+        dir.or_else(|| self.entries.get(file)).cloned()
+    }
+
+    fn walk(&self, dir: &str) -> Vec<(String, Entry)> {
+        let inside = self.entries.range(dir.to_string()..);
+        inside
+            .take_while(|(path, _)| path.starts_with(dir))
+            .filter(|(path, _)| *path != dir)
+            .map(|(path, entry)| (path.clone(), entry.clone()))
+            .collect()
+    }
+
+    fn read(&self, path: &str) -> Option<Vec<u8>> {
+        self.disk.read(path)
+    }
+
+    /// only the files that are hashed in `scan` (the api is locked when it is used)
+    fn hash(&self, path: &str) -> Option<u64> {
+        self.hashes.get(path).copied()
+    }
+
+    /// the disk is read again as a client could change the path after the scan
+    fn current(&self, path: &str) -> Option<Entry> {
+        self.disk.entry(path)
+    }
+}
+
+/// the same as `FileTree::subtree` for the disk
+fn disk_subtree(disk: &impl Disk, path: &ProjectPath) -> Entries {
+    let mut paths: Entries = path
+        .ancestors()
+        .filter(|dir| disk.entry(dir) == Some(Entry::Dir))
+        .map(|dir| (dir, Entry::Dir))
+        .collect();
+    match disk.entry(path) {
+        Some(Entry::Dir) => {
+            let dir = path.clone().into_dir();
+            paths.extend(disk.walk(&dir));
+            if !dir.is_root() {
+                paths.insert(dir.into(), Entry::Dir);
+            }
+        }
+        Some(entry) => {
+            paths.insert(path.as_str().trim_end_matches('/').to_string(), entry);
+        }
+        None => {}
+    }
+    paths
+}
+
+/// the entries of `a` that are not the same kind in `b` (the metadata is not compared)
+fn difference<'a>(a: &'a Entries, b: &'a Entries) -> impl Iterator<Item = (&'a String, &'a Entry)> {
+    a.iter()
+        .filter(|(path, entry)| !b.get(*path).is_some_and(|other| other.same_kind(entry)))
+}
+
+/// the paths that are not in one of the other directories
+fn top_paths<'a>(paths: impl Iterator<Item = (&'a String, &'a Entry)>) -> Vec<String> {
+    let mut top: Vec<String> = vec![];
+    for (path, _) in paths {
+        // the content of a directory comes right after it
+        if !top
+            .last()
+            .is_some_and(|dir| dir.ends_with('/') && path.starts_with(dir))
+        {
+            top.push(path.clone());
+        }
+    }
+    top
+}
+
+/// the entries in `dir` without `dir` (the paths are relative to it)
+fn content<'a>(entries: &'a Entries, dir: &str) -> Vec<(&'a str, &'a Entry)> {
+    let inside = entries.range(dir.to_string()..).skip(1);
+    inside
+        .take_while(|(path, _)| path.starts_with(dir))
+        .map(|(path, entry)| (&path[dir.len()..], entry))
+        .collect()
+}
+
+/// the same names and kinds (a copied file could have a new time)
+fn same_content(a: &[(&str, &Entry)], b: &[(&str, &Entry)]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .all(|((a, a_entry), (b, b_entry))| a == b && a_entry.same_kind(b_entry))
+}
+
+/// the changes that make `tree` the same as `disk`
+/// a deleted directory is deleted with its content and the parents are made before their content,
+/// the metadata of the files is updated last
+pub(super) fn diff(tree: &Entries, disk: &Entries) -> Vec<RPC> {
+    let mut changes = vec![];
+    let mut deleted: Option<&str> = None;
+    for (path, entry) in difference(tree, disk) {
+        // the content of a directory comes right after it
+        if deleted.is_some_and(|dir| path.starts_with(dir)) {
+            continue;
+        }
+        let Ok(project_path) = ProjectPath::new(path) else {
+            continue;
+        };
+        if *entry == Entry::Dir {
+            deleted = Some(path);
+            changes.push(RPC::DeleteDirectory { path: project_path });
+        } else {
+            changes.push(RPC::DeleteFile { path: project_path });
+        }
+    }
+    for (path, entry) in difference(disk, tree) {
+        let Ok(path) = ProjectPath::new(path) else {
+            continue;
+        };
+        changes.push(match entry.clone() {
+            Entry::Dir => RPC::CreateDirectory { path },
+            Entry::File(meta) => RPC::CreateFile {
+                path,
+                mode: Some(meta.mode),
+                content: None,
+            },
+            Entry::Link(target) => RPC::CreateLink { path, target },
+        });
+    }
+    for (path, entry) in disk {
+        let Entry::File(meta) = entry else {
+            continue;
+        };
+        // a new file has the default metadata with its mode
+        let known = match tree.get(path) {
+            Some(Entry::File(known)) => known.clone(),
+            _ => FileMeta::with_mode(Some(meta.mode)),
+        };
+        if known != *meta {
+            if let Ok(path) = ProjectPath::new(path) {
+                let meta = meta.clone();
+                changes.push(RPC::UpdateMetadata {
+                    entry: FileEntry { path, meta },
+                });
+            }
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests;
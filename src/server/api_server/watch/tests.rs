@@ -0,0 +1,423 @@
+use std::path::PathBuf;
+
+use super::*;
+use crate::communication::event::DirChange;
+use crate::communication::file_entry::FileEntry;
+use crate::server::CURRENT_DIR;
+
+/// the disk as the entries and the content of some files
+#[derive(Clone)]
+struct SimDisk(Entries, HashMap<String, Vec<u8>>);
+
+/// the entries of the paths, the directories end with `/`
+fn entries(paths: &[&str]) -> Entries {
+    let entry = |path: &str| match path.ends_with('/') {
+        true => Entry::Dir,
+        false => Entry::File(FileMeta::default()),
+    };
+    paths.iter().map(|p| (p.to_string(), entry(p))).collect()
+}
+
+impl SimDisk {
+    /// the same as the tree of the api
+    fn of(api: &ServerApi) -> Self {
+        Self(api.file_tree.subtree(&ProjectPath::root()), HashMap::new())
+    }
+
+    fn add(&mut self, paths: &[&str]) {
+        self.0.extend(entries(paths));
+    }
+
+    /// move the entries with their metadata
+    fn rename(&mut self, path: &str, new_path: &str) {
+        let moved: Entries = self
+            .0
+            .iter()
+            .filter(|(p, _)| p.starts_with(path))
+            .map(|(p, entry)| (p.replacen(path, new_path, 1), entry.clone()))
+            .collect();
+        self.remove(path);
+        self.0.extend(moved);
+    }
+
+    fn remove(&mut self, path: &str) {
+        self.0.retain(|p, _| !p.starts_with(path));
+    }
+}
+
+impl Disk for SimDisk {
+    fn entry(&self, path: &str) -> Option<Entry> {
+        let file = path.trim_end_matches('/');
+        if self.0.contains_key(&format!("{file}/")) || file == "." && !self.0.is_empty() {
+            Some(Entry::Dir)
+        } else {
+            self.0.get(file).cloned()
+        }
+    }
+
+    fn walk(&self, dir: &str) -> Vec<(String, Entry)> {
+        let paths = self.0.iter().filter(|(path, _)| path.starts_with(dir));
+        let paths = paths.filter(|(path, _)| *path != dir);
+        paths
+            .map(|(path, entry)| (path.clone(), entry.clone()))
+            .collect()
+    }
+
+    fn read(&self, path: &str) -> Option<Vec<u8>> {
+        self.1.get(path).cloned()
+    }
+}
+
+fn full(path: &str) -> PathBuf {
+    Path::new(&*CURRENT_DIR).join(path)
+}
+
+/// apply an event like the watcher does (with the entries that are read before)
+fn watch_event(api: &mut ServerApi, event: DebouncedEvent, disk: &SimDisk) {
+    let loaded = api.loaded_files();
+    let snapshot = Snapshot::scan(disk.clone(), &event_paths(&event), &loaded);
+    api.disk_event(event, &snapshot);
+}
+
+fn events(api: &mut ServerApi) -> Vec<Event> {
+    let receiver = api.receiver.as_mut().unwrap();
+    let mut events = vec![];
+    while let Ok(event) = receiver.try_recv() {
+        events.push(event);
+    }
+    events
+}
+
+fn dir_event(path: &str, change: DirChange) -> Event {
+    Event::DirChanged {
+        path: path.to_string(),
+        change,
+    }
+}
+
+#[test]
+fn diff_deletes_top_directories() {
+    let tree = entries(&["./a/", "./a/b/", "./a/b/c", "./a-b", "./d"]);
+    let disk = entries(&["./a-b", "./d/", "./d/e"]);
+    let path = |p: &str| ProjectPath::new(p).unwrap();
+    assert_eq!(
+        diff(&tree, &disk),
+        [
+            RPC::DeleteDirectory { path: path("./a/") },
+            RPC::DeleteFile { path: path("./d") },
+            RPC::CreateDirectory { path: path("./d/") },
+            RPC::CreateFile {
+                path: path("./d/e"),
+                mode: Some(0o644),
+                content: None,
+            },
+        ]
+    );
+}
+
+#[test]
+fn external_changes() {
+    let mut api = ServerApi::new_server();
+    let mut disk = SimDisk::of(&api);
+    events(&mut api);
+
+    // a directory is copied with its content (there is one event for it)
+    disk.add(&["./sim/", "./sim/a.txt", "./sim/d/", "./sim/d/b.txt"]);
+    watch_event(&mut api, DebouncedEvent::Create(full("sim")), &disk);
+    assert_eq!(
+        events(&mut api),
+        [
+            dir_event("./sim/", DirChange::Created),
+            Event::FileCreated {
+                path: "./sim/a.txt".to_string()
+            },
+            dir_event("./sim/d/", DirChange::Created),
+            Event::FileCreated {
+                path: "./sim/d/b.txt".to_string()
+            },
+        ]
+    );
+
+    // the events of the changes that are in the tree are dropped
+    watch_event(&mut api, DebouncedEvent::Create(full("sim/d/b.txt")), &disk);
+    assert_eq!(events(&mut api), []);
+
+    // a removed path is a directory if it is a directory in the tree
+    disk.remove("./sim/d/");
+    watch_event(&mut api, DebouncedEvent::Remove(full("sim/d")), &disk);
+    assert_eq!(
+        events(&mut api),
+        [dir_event("./sim/d/", DirChange::Deleted)]
+    );
+
+    disk.remove("./sim/");
+    disk.add(&["./moved/", "./moved/a.txt"]);
+    watch_event(
+        &mut api,
+        DebouncedEvent::Rename(full("sim"), full("moved")),
+        &disk,
+    );
+    assert_eq!(
+        events(&mut api),
+        [dir_event(
+            "./sim/",
+            DirChange::Moved {
+                new_path: "./moved/".to_string()
+            }
+        )]
+    );
+    assert_eq!(
+        api.file_tree
+            .entry(&ProjectPath::new("./moved/a.txt").unwrap()),
+        Some(Entry::File(FileMeta::default()))
+    );
+}
+
+#[test]
+fn rescan_compares_everything() {
+    let mut api = ServerApi::new_server();
+    let mut disk = SimDisk::of(&api);
+    events(&mut api);
+
+    disk.remove("./Cargo.toml");
+    disk.add(&["./new/", "./new/file"]);
+    watch_event(&mut api, DebouncedEvent::Rescan, &disk);
+    assert_eq!(
+        events(&mut api),
+        [
+            Event::FileDeleted {
+                path: "./Cargo.toml".to_string()
+            },
+            dir_event("./new/", DirChange::Created),
+            Event::FileCreated {
+                path: "./new/file".to_string()
+            },
+        ]
+    );
+    assert_eq!(api.file_tree.subtree(&ProjectPath::root()), disk.0);
+}
+
+#[test]
+fn rescan_finds_moves() {
+    let mut api = ServerApi::new_server();
+    let mut disk = SimDisk::of(&api);
+    let cargo = ProjectPath::new("./Cargo.toml").unwrap();
+    api.load(&cargo).unwrap();
+    events(&mut api);
+
+    // the content of a loaded file is known
+    disk.add(&["./moved/"]);
+    disk.rename("./Cargo.toml", "./moved/Cargo.toml");
+    let content = std::fs::read("Cargo.toml").unwrap();
+    disk.1.insert("./moved/Cargo.toml".to_string(), content);
+    // a directory with the same content
+    disk.rename("./src/communication/event/", "./event/");
+    api.rescan_disk(&disk).unwrap();
+    assert_eq!(
+        events(&mut api),
+        [
+            dir_event(
+                "./src/communication/event/",
+                DirChange::Moved {
+                    new_path: "./event/".to_string()
+                }
+            ),
+            dir_event("./moved/", DirChange::Created),
+            Event::FileMoved {
+                path: "./Cargo.toml".to_string(),
+                new_path: "./moved/Cargo.toml".to_string()
+            },
+        ]
+    );
+    assert!(api.file_tree.tree.contains_key("./moved/Cargo.toml"));
+    assert_eq!(api.file_tree.subtree(&ProjectPath::root()), disk.0);
+
+    // nothing is deleted if the shared directory is gone
+    let gone = SimDisk(Entries::new(), HashMap::new());
+    assert!(api.rescan_disk(&gone).is_err());
+}
+
+#[test]
+fn moves_are_matched_by_size_and_time() {
+    let mut api = ServerApi::new_server();
+    let mut disk = SimDisk::of(&api);
+    let cargo = ProjectPath::new("./Cargo.toml").unwrap();
+    api.load(&cargo).unwrap();
+    events(&mut api);
+
+    // a copy has the same content but a new time
+    let content = std::fs::read("Cargo.toml").unwrap();
+    let Some(Entry::File(mut meta)) = disk.0.get("./Cargo.toml").cloned() else {
+        panic!("Cargo.toml is not a file");
+    };
+    meta.mtime += 1;
+    disk.0.insert("./copy.toml".to_string(), Entry::File(meta));
+    disk.rename("./Cargo.toml", "./moved.toml");
+    for file in ["./copy.toml", "./moved.toml"] {
+        disk.1.insert(file.to_string(), content.clone());
+    }
+
+    let root = [ProjectPath::root()];
+    let snapshot = Snapshot::scan(disk.clone(), &root, &api.loaded_files());
+    assert_eq!(snapshot.hashes.keys().collect::<Vec<_>>(), ["./moved.toml"]);
+    api.rescan_disk(&snapshot).unwrap();
+    assert!(events(&mut api).contains(&Event::FileMoved {
+        path: "./Cargo.toml".to_string(),
+        new_path: "./moved.toml".to_string()
+    }));
+    assert!(api.file_tree.tree.contains_key("./moved.toml"));
+}
+
+#[test]
+fn stale_snapshots_keep_the_changes_of_clients() {
+    let mut api = ServerApi::new_server();
+    let mut disk = SimDisk::of(&api);
+    let root = [ProjectPath::root()];
+    let mut snapshot = Snapshot::scan(disk.clone(), &root, &api.loaded_files());
+
+    // a client makes a file and moves one after the scan
+    let new_file = RPC::CreateFile {
+        path: ProjectPath::new("./new.txt").unwrap(),
+        mode: None,
+        content: None,
+    };
+    let moved = RPC::MoveFile {
+        path: ProjectPath::new("./Cargo.toml").unwrap(),
+        new_path: ProjectPath::new("./moved.toml").unwrap(),
+    };
+    for rpc in [&new_file, &moved] {
+        api.file_tree.sync(rpc).unwrap();
+    }
+    disk.add(&["./new.txt"]);
+    disk.rename("./Cargo.toml", "./moved.toml");
+    snapshot.disk = disk;
+    events(&mut api);
+
+    api.rescan_disk(&snapshot).unwrap();
+    assert_eq!(events(&mut api), []);
+    for file in ["./new.txt", "./moved.toml"] {
+        let path = ProjectPath::new(file).unwrap();
+        assert!(api.file_tree.entry(&path).is_some());
+    }
+    let cargo = ProjectPath::new("./Cargo.toml").unwrap();
+    assert!(api.file_tree.entry(&cargo).is_none());
+}
+
+#[tokio::test]
+async fn dirty_files_are_moved() {
+    let mut api = ServerApi::new_server();
+    let mut disk = SimDisk::of(&api);
+    let file = "./Cargo.toml".to_string();
+    api.edit_buf(file.clone(), Some(0), Some(0), "# not saved\n")
+        .await
+        .unwrap();
+    events(&mut api);
+
+    // the disk has the saved content
+    disk.rename("./Cargo.toml", "./moved.toml");
+    let content = std::fs::read("Cargo.toml").unwrap();
+    disk.1.insert("./moved.toml".to_string(), content);
+    let root = [ProjectPath::root()];
+    let snapshot = Snapshot::scan(disk.clone(), &root, &api.loaded_files());
+    api.rescan_disk(&snapshot).unwrap();
+    assert!(events(&mut api).contains(&Event::FileMoved {
+        path: file,
+        new_path: "./moved.toml".to_string()
+    }));
+    let moved = api.file_tree.tree.get("./moved.toml").unwrap();
+    assert!(moved.read().unwrap().starts_with(b"# not saved\n"));
+}
+
+#[test]
+fn metadata_follows_the_disk() {
+    let mut api = ServerApi::new_server();
+    let mut disk = SimDisk::of(&api);
+    events(&mut api);
+
+    // a new file with more than the default metadata
+    let meta = FileMeta {
+        size: 4,
+        mtime: 1,
+        mode: 0o755,
+        ..Default::default()
+    };
+    disk.0
+        .insert("./run.sh".to_string(), Entry::File(meta.clone()));
+    api.disk_event(DebouncedEvent::Create(full("run.sh")), &disk);
+    let path = ProjectPath::new("./run.sh").unwrap();
+    let entry = FileEntry {
+        path: path.clone(),
+        meta: meta.clone(),
+    };
+    assert_eq!(
+        events(&mut api),
+        [
+            Event::FileCreated {
+                path: "./run.sh".to_string()
+            },
+            Event::MetadataChanged {
+                entry: entry.clone()
+            },
+        ]
+    );
+
+    // the content is saved by someone else
+    let mut changed = meta.clone();
+    changed.size = 0;
+    changed.sniff(&[0, 0xFF], true);
+    disk.0
+        .insert("./run.sh".to_string(), Entry::File(changed.clone()));
+    api.disk_event(DebouncedEvent::Write(full("run.sh")), &disk);
+    let entry = FileEntry {
+        path,
+        meta: changed,
+    };
+    assert_eq!(
+        events(&mut api),
+        [Event::MetadataChanged {
+            entry: entry.clone()
+        }]
+    );
+    assert_eq!(api.file_tree.file_entry(&entry.path), Some(entry));
+
+    // nothing is sent if the metadata is the same
+    api.disk_event(DebouncedEvent::Chmod(full("run.sh")), &disk);
+    assert_eq!(events(&mut api), []);
+}
+
+#[tokio::test]
+async fn links_follow_the_disk() {
+    let mut api = ServerApi::new_server();
+    let mut disk = SimDisk::of(&api);
+    events(&mut api);
+
+    let link = |target: &str| Entry::Link(target.to_string());
+    disk.0.insert("./link".to_string(), link("/etc"));
+    api.disk_event(DebouncedEvent::Create(full("link")), &disk);
+    assert_eq!(
+        events(&mut api),
+        [Event::LinkCreated {
+            path: "./link".to_string(),
+            target: "/etc".to_string()
+        }]
+    );
+    assert_eq!(api.file_tree.links()[0].target, "/etc");
+
+    // a link that points somewhere else is made again
+    disk.0.insert("./link".to_string(), link("./src"));
+    api.disk_event(DebouncedEvent::Rescan, &disk);
+    let deleted = Event::FileDeleted {
+        path: "./link".to_string(),
+    };
+    let created = Event::LinkCreated {
+        path: "./link".to_string(),
+        target: "./src".to_string(),
+    };
+    assert_eq!(events(&mut api), [deleted.clone(), created]);
+
+    // the links can't be opened
+    assert!(api.read_file_server("./link".to_string()).await.is_err());
+    disk.remove("./link");
+    api.disk_event(DebouncedEvent::Remove(full("link")), &disk);
+    assert_eq!(events(&mut api), [deleted]);
+}
@@ -0,0 +1,128 @@
+use std::{env, path::PathBuf, process, time::Duration};
+
+use super::outbox::OverflowPolicy;
+use super::variables::CONFIG;
+use crate::communication::permissions::{Permissions, Priviledge};
+
+/// the settings of the server
+/// it is set once when the server starts with `start_server_with_config`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerConfig {
+    /// files bigger than this (in bytes) can't be opened
+    pub max_file_size: u64,
+    /// files bigger than this (in bytes) are sent as they are and can't be edited
+    /// (no crdt is made for them)
+    pub view_only_size: u64,
+    /// files are sent to the clients in chunks of this size (in bytes)
+    pub chunk_size: usize,
+    /// the number of messages that could wait to be sent to a client
+    pub outbox_size: usize,
+    /// what to do when the outbox of a client is full
+    pub overflow_policy: OverflowPolicy,
+    /// the permissions of the clients when they connect
+    pub default_permissions: Permissions,
+    /// an access request is denied if the host doesn't answer it in this time
+    pub access_request_timeout: Duration,
+    /// the time a client should wait before asking for access again
+    pub access_request_interval: Duration,
+    /// the number of clients that could be connected at the same time (`None` is no limit)
+    pub max_participants: Option<usize>,
+    /// the username of the host (the clients can't use it),
+    /// the default is the `SERVER_USERNAME` env variable or `SERVER`
+    pub host_username: String,
+    /// compare the whole tree with the disk this often (`None` is only when the watcher asks for it)
+    /// it finds the changes that the watcher missed
+    pub rescan_interval: Option<Duration>,
+    /// how the symlinks in the shared directory are shared
+    pub symlinks: SymlinkPolicy,
+    /// the deleted files and directories are moved here so they could be restored
+    /// (the default is a directory in the temp directory of the system)
+    pub trash_dir: PathBuf,
+    /// delete the trash when the server is stopped (see `ServerApi::shutdown`)
+    pub purge_trash_on_shutdown: bool,
+    /// the directories that are shared, each one is a directory of the tree (`./<name>/`)
+    /// the current directory is shared as `./` if there are none
+    pub roots: Vec<SharedRoot>,
+    /// what the clients see, everything or only the paths that the host shares with `ServerApi::share`
+    pub share: ShareMode,
+}
+
+/// what is shared with the clients
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ShareMode {
+    /// everything in the shared directories (without the ignored paths)
+    #[default]
+    Everything,
+    /// only the files and directories that are shared with `ServerApi::share` (nothing at first),
+    /// the clients see their parents but not the rest of what is in them
+    Selected,
+}
+
+/// a shared directory with its name (like a folder of a multi-root workspace)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedRoot {
+    /// the directory of the tree, it should be a unique name without `/`
+    pub name: String,
+    pub path: PathBuf,
+    /// the paths that are not shared and not watched, like `target` or `*.log` (any part of a path)
+    /// or `docs/build/**` (from the root if there is a `/`)
+    pub ignore: Vec<String>,
+}
+
+impl SharedRoot {
+    pub fn new(name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            name: name.into(),
+            path: path.into(),
+            ignore: vec![],
+        }
+    }
+
+    /// don't share the paths that match `glob`
+    pub fn with_ignore(mut self, glob: impl Into<String>) -> Self {
+        self.ignore.push(glob.into());
+        self
+    }
+}
+
+/// how the symlinks in the shared directory are shared
+/// sockets, fifos and devices are never shared
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// share the files and directories that the symlinks point to
+    /// (only the ones inside the shared directory, the cycles are skipped)
+    Follow,
+    /// share the symlinks as links that can't be opened (editors could show them)
+    #[default]
+    Link,
+    /// the symlinks are not shared
+    Ignore,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            max_file_size: 512 * 1024 * 1024,
+            view_only_size: 16 * 1024 * 1024,
+            chunk_size: 256 * 1024,
+            outbox_size: 1024,
+            overflow_policy: OverflowPolicy::default(),
+            default_permissions: Priviledge::Maintainer.into(),
+            access_request_timeout: Duration::from_secs(120),
+            access_request_interval: Duration::from_secs(30),
+            max_participants: None,
+            host_username: env::var("SERVER_USERNAME").unwrap_or("SERVER".to_string()),
+            rescan_interval: None,
+            symlinks: SymlinkPolicy::default(),
+            trash_dir: env::temp_dir().join(format!("pairwriter-trash-{}", process::id())),
+            purge_trash_on_shutdown: true,
+            roots: vec![],
+            share: ShareMode::default(),
+        }
+    }
+}
+
+/// the config of the server (the default one if the server is not started yet)
+pub(crate) fn config() -> &'static ServerConfig {
+    CONFIG.get_or_init(ServerConfig::default)
+}
@@ -1,10 +1,14 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
-use crate::communication::rpc::RPC;
-use bincode::Decode;
+use crate::communication::{participant::Participant as _, permissions::Permissions, rpc::RPC};
+use api_server::{session::close_frame, ServerApi};
+use config::config;
 use futures::stream::StreamExt;
-use messageing::server_send_message;
+use messageing::Inbox;
+use outbox::Outbox;
 use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
 use tokio_tungstenite::{accept_async, tungstenite::Message};
 
 use super::*;
@@ -20,84 +24,132 @@ async fn handle_connection(raw_stream: TcpStream) -> Result<WebSocketStream<TcpS
 }
 pub(super) async fn connect_to_server(raw_stream: TcpStream) -> Result<(), String> {
     let mut ws_stream = handle_connection(raw_stream).await?;
-    if let Some(Ok(Message::Binary(rpc))) = ws_stream.next().await {
-        if let RPC::AddUsername(username) = RPC::decode(rpc.as_slice()).unwrap() {
-            let api = API.lock().await;
-            let (files, emty_dirs) = api.get_file_maps().await;
-            let rpc = RPC::ResConnect {
-                username: "Server".to_string(),
-                files:files.clone(),
-                emty_dirs: emty_dirs.clone(),
-                priviledge: Priviledge::ReadWrite,
-            };
-            drop(api);
-            let _ = ws_stream.send(Message::binary(rpc.encode().unwrap())).await;
-
-            let (send, res) = ws_stream.split();
-            let send = Arc::new(Mutex::new(send));
-            let client_res = Arc::new(Mutex::new(ClientRes {
-                priviledge: Priviledge::ReadWrite,
-                resever: res,
-                open: true,
-            }));
-
-
+    let Some(Ok(Message::Binary(rpc))) = ws_stream.next().await else {
+        return Err("Invalid message".to_string());
+    };
+    let Ok(RPC::AddUsername(username)) = RPC::decode(rpc.as_slice()) else {
+        return Err("Invalid message".to_string());
+    };
+    let mut api = API.lock().await;
+    let (connected, used) = {
+        let clients = CLIENTS.lock().await;
+        (clients.len(), clients.contains_key(&username))
+    };
+    // the bans, the lock, the max participants and the usernames of the connected clients
+    if let Err(reason) = api.can_join(&username, connected, used) {
+        drop(api);
+        log::warn!("{username} can't join: {reason}");
+        let _ = ws_stream.send(close_frame(&reason)).await;
+        return Err(reason);
+    }
+    let permissions = config().default_permissions.clone();
+    let rpc = connect_rpc(&api, &permissions).await;
 
-            let mut clients_send = CLIENTS_SEND.lock().await;
-            clients_send.insert(username.clone(), send.clone());
-            drop(clients_send);
+    let (send, res) = ws_stream.split();
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let outbox = Arc::new(Outbox::new(config().outbox_size, config().overflow_policy));
+    let _ = outbox.push(rpc.encode().unwrap(), None); // this is the first message so there is space
+    tokio::spawn(write_messages(send, outbox.clone()));
+    // the client is added before reading its messages so none of them is ignored
+    let mut clients = CLIENTS.lock().await;
+    let reader = tokio::spawn(read_messages(username.clone(), id, res));
+    let client = Client {
+        permissions,
+        id,
+        outbox,
+        reader,
+    };
+    clients.insert(username.clone(), client);
+    drop(clients);
+    api.user_joined(&username);
+    drop(api); // the api is locked until the client is added so no change is missed
+    Ok(())
+}
 
-            let mut clients_res = CLIENTS_RES.lock().await;
-            clients_res.insert(username, client_res.clone());
-            drop(clients_res);
+/// the first message of a client, it is also sent again when the client needs a resync
+/// only the root level is sent, the client will request the rest when needed
+pub(crate) async fn connect_rpc(api: &ServerApi, permissions: &Permissions) -> RPC {
+    let listing = api.root_listing();
+    RPC::ResConnect {
+        username: api.username(),
+        users: api.participants().await,
+        files: listing.files,
+        emty_dirs: listing.emty_dirs,
+        dirs: listing.dirs,
+        links: listing.links,
+        priviledge: permissions.priviledge,
+        scopes: permissions.scopes.clone(),
+        roots: api.roots().await,
+    }
+}
 
-            server_send_message(messageing::RESET_WAITING); // that the server should reset the
-            // message waiting reading and sending
-            return Ok(());
+/// the ids of the connections
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
 
+/// read the messages of a client and send them to the api
+async fn read_messages(username: String, id: u64, mut res: SinkRes) {
+    while let Some(message) = res.next().await {
+        match message {
+            Ok(Message::Binary(message)) => match RPC::decode(message.as_slice()) {
+                Ok(rpc) => messageing::send_to_api(Inbox::Client {
+                    username: username.clone(),
+                    rpc,
+                }),
+                Err(e) => log::error!("invalid message from {username}: {e}"),
+            },
+            Ok(Message::Close(_)) | Err(_) => break,
+            Ok(_) => {} // ping and pong
         }
     }
-    Err("Invalid message".to_string())
+    messageing::send_to_api(Inbox::Left { username, id });
 }
-pub(crate) async fn remove_dead_clients() {
-    let mut clients_res = CLIENTS_RES.lock().await;
-    let mut clients_send = CLIENTS_SEND.lock().await;
-    for (username, client) in clients_res.iter() {
-        if !client.lock().await.open {
-            log::info!("Client with username: {} has disconnected", username);
-            clients_send.remove(username);
+
+/// send the messages in the outbox to the client
+/// this stops when the client is removed (the outbox is closed)
+/// if sending fails the outbox is closed so only this client is removed
+async fn write_messages(mut send: SinkSend, outbox: Arc<Outbox>) {
+    while let Some(message) = outbox.recv().await {
+        let closing = matches!(message, Message::Close(_));
+        if let Err(e) = send.send(message).await {
+            log::error!("failed to send a message: {e}");
+            outbox.close();
+            return;
+        }
+        if closing {
+            return; // the close frame is sent
         }
     }
-    clients_res.retain(|username, _client| clients_send.contains_key(username));
+    let _ = send.close().await;
 }
 
-#[derive(
-    Debug, PartialEq, Clone, Copy, bincode::Encode, Decode, serde::Serialize, serde::Deserialize, Eq,
-)] // TODO: add privileges to the api
-pub enum Priviledge {
-    ReadOnly, // TODO: improve this priviledge
-    ReadWrite,
+/// a connected client
+#[derive(Debug)]
+pub(crate) struct Client {
+    pub(crate) permissions: Permissions,
+    /// the id of the connection (the same username could connect again)
+    pub(crate) id: u64,
+    /// the messages that are sent to the client by its writer task
+    pub(crate) outbox: Arc<Outbox>,
+    reader: JoinHandle<()>,
 }
 
-#[derive(Debug)]
-pub(crate) struct ClientRes {
-    pub(crate) priviledge: Priviledge,
-    resever: SinkRes,
-    open: bool,
+#[cfg(test)]
+impl Client {
+    /// a client without a connection, the messages stay in its outbox
+    pub(crate) fn detached(outbox: Arc<Outbox>) -> Self {
+        Self {
+            permissions: config().default_permissions.clone(),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            outbox,
+            reader: tokio::spawn(async {}),
+        }
+    }
 }
 
-impl ClientRes {
-    pub(crate) async fn read_message(&mut self) -> Result<RPC, String> {
-        let resever = &mut self.resever;
-        let error = Err("Failed to read message\n from client".to_string());
-        let x = resever.next().await;
-        let in_message = if let Some(Ok(Message::Binary(message))) = x {
-            message
-        } else {
-            self.open = false;
-            return error;
-        };
-        let rpc = RPC::decode(in_message.as_slice()).unwrap();
-        Ok(rpc)
+impl Drop for Client {
+    /// the writer stops after sending the waiting messages
+    fn drop(&mut self) {
+        self.outbox.close();
+        self.reader.abort();
     }
 }
@@ -0,0 +1,200 @@
+use std::fs::{self, Metadata};
+use std::hash::{DefaultHasher, Hash as _, Hasher as _};
+use std::io::Read as _;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use walkdir::WalkDir;
+
+use super::{
+    config::{config, SymlinkPolicy},
+    roots::{self, Mount},
+};
+use crate::communication::{
+    file_entry::{FileMeta, SNIFF_SIZE},
+    file_tree::Entry,
+    project_path::ProjectPath,
+};
+
+/// what the watcher knows about the disk, it is simulated in the tests
+/// the paths are like the paths of the project (`./dir/file`)
+pub(crate) trait Disk {
+    /// `None` if the path doesn't exist or it is not shared
+    fn entry(&self, path: &str) -> Option<Entry>;
+    /// every entry under `dir` (the directories end with `/`)
+    fn walk(&self, dir: &str) -> Vec<(String, Entry)>;
+    /// the content of a file (it is used to find the moved files)
+    fn read(&self, path: &str) -> Option<Vec<u8>>;
+    /// the hash of the content of a file (see `hash`)
+    fn hash(&self, path: &str) -> Option<u64> {
+        self.read(path).map(|content| hash(&content))
+    }
+    /// the entry as it is now, the other entries could be read before the tree was changed
+    fn current(&self, path: &str) -> Option<Entry> {
+        self.entry(path)
+    }
+}
+
+pub(crate) fn hash(content: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// the shared directories with the symlink policy and the ignore rules of the config
+#[derive(Clone, Copy)]
+pub(crate) struct RealDisk;
+
+impl Disk for RealDisk {
+    fn entry(&self, path: &str) -> Option<Entry> {
+        if path == "./" && roots::is_multi_root() {
+            // the roots are in a directory that is not on the disk
+            return Some(Entry::Dir);
+        }
+        let mount = roots::mount_of(path)?;
+        if mount.is_ignored(path) {
+            return None;
+        }
+        let full = mount.full(path.trim_end_matches('/'));
+        let meta = fs::symlink_metadata(&full).ok()?;
+        if !meta.file_type().is_symlink() {
+            return entry(&full, &meta);
+        }
+        match config().symlinks {
+            SymlinkPolicy::Ignore => None,
+            SymlinkPolicy::Link => link(&full),
+            SymlinkPolicy::Follow => {
+                let target = full.canonicalize().ok()?;
+                let parent = full.parent()?.canonicalize().ok()?;
+                // a link to one of its parents is a cycle
+                let inside = target.starts_with(mount.disk.canonicalize().ok()?);
+                if !inside || parent.starts_with(&target) {
+                    return None;
+                }
+                entry(&target, &fs::metadata(&target).ok()?)
+            }
+        }
+    }
+
+    fn walk(&self, dir: &str) -> Vec<(String, Entry)> {
+        walk_tree(dir)
+            .into_iter()
+            .map(|(path, entry)| (path.into(), entry))
+            .collect()
+    }
+
+    fn read(&self, path: &str) -> Option<Vec<u8>> {
+        fs::read(roots::mount_of(path)?.full(path)).ok()
+    }
+}
+
+/// the shared entries under a directory of the tree (with the roots if it is `./`)
+/// the parents are before their content
+pub(crate) fn walk_tree(dir: &str) -> Vec<(ProjectPath, Entry)> {
+    if dir != "./" || !roots::is_multi_root() {
+        return match roots::mount_of(dir) {
+            Some(mount) if !mount.is_ignored(dir) => walk(mount, &mount.full(dir)),
+            _ => vec![],
+        };
+    }
+    let mut entries = vec![];
+    for mount in roots::mounts() {
+        if mount.disk.is_dir() {
+            entries.push((mount.dir.clone(), Entry::Dir));
+            entries.extend(walk(mount, &mount.disk));
+        }
+    }
+    entries
+}
+
+/// the shared entries under `dir` (on the disk in the root `mount`) with the symlink policy of the config
+/// the parents are before their content
+pub(crate) fn walk(mount: &Mount, dir: &Path) -> Vec<(ProjectPath, Entry)> {
+    let follow = config().symlinks == SymlinkPolicy::Follow;
+    let canonical_root = mount.disk.canonicalize().ok();
+    let walker = WalkDir::new(dir).min_depth(1).follow_links(follow);
+    walker
+        .into_iter()
+        // the ignored directories are not walked
+        // and the links that point outside of the root are not followed
+        .filter_entry(|entry| {
+            let path = mount.project_path(entry.path(), entry.file_type().is_dir());
+            if !path.is_ok_and(|path| !mount.is_ignored(&path)) {
+                return false;
+            }
+            !(follow && entry.path_is_symlink())
+                || entry.path().canonicalize().is_ok_and(|target| {
+                    canonical_root
+                        .as_ref()
+                        .is_some_and(|root| target.starts_with(root))
+                })
+        })
+        // the cycles are errors (when the links are followed)
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let entry_kind = if entry.path_is_symlink() && !follow {
+                match config().symlinks {
+                    SymlinkPolicy::Link => link(entry.path())?,
+                    _ => return None,
+                }
+            } else {
+                self::entry(entry.path(), &entry.metadata().ok()?)?
+            };
+            let is_dir = entry_kind == Entry::Dir;
+            let path = mount.project_path(entry.path(), is_dir).ok()?;
+            Some((path, entry_kind))
+        })
+        .collect()
+}
+
+/// files and directories, the special files are not shared
+fn entry(path: &Path, meta: &Metadata) -> Option<Entry> {
+    if meta.is_dir() {
+        Some(Entry::Dir)
+    } else if meta.is_file() {
+        Some(Entry::File(file_meta(path, meta)))
+    } else {
+        None
+    }
+}
+
+/// the metadata of a file, the encoding is guessed from its first bytes
+pub(crate) fn file_meta(path: &Path, meta: &Metadata) -> FileMeta {
+    let mtime = meta.modified().ok();
+    let mtime = mtime.and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+    let mut file_meta = FileMeta {
+        size: meta.len(),
+        mtime: mtime.map_or(0, |time| time.as_millis() as u64),
+        mode: mode(meta),
+        ..Default::default()
+    };
+    let mut head = vec![];
+    if let Ok(file) = fs::File::open(path) {
+        let _ = file.take(SNIFF_SIZE as u64).read_to_end(&mut head);
+    }
+    file_meta.sniff(&head, meta.len() <= SNIFF_SIZE as u64);
+    file_meta
+}
+
+#[cfg(unix)]
+fn mode(meta: &Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn mode(meta: &Metadata) -> u32 {
+    if meta.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
+
+fn link(path: &Path) -> Option<Entry> {
+    let target = fs::read_link(path).ok()?;
+    Some(Entry::Link(target.to_string_lossy().into_owned()))
+}
+
+#[cfg(test)]
+mod tests;
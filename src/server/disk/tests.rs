@@ -0,0 +1,43 @@
+use super::*;
+
+#[cfg(unix)]
+#[test]
+fn links_and_special_files() {
+    use std::os::unix::{fs::symlink, net::UnixListener};
+
+    // the tests run in more than one process
+    let dir = format!("./target/disk_walk_{}/", std::process::id());
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(format!("{dir}a.txt"), "a").unwrap();
+    fs::write(format!("{dir}b.bin"), [0xC3, 0x28, 0x00]).unwrap();
+    symlink("a.txt", format!("{dir}link")).unwrap();
+    let _socket = UnixListener::bind(format!("{dir}socket")).unwrap();
+
+    let mut entries = RealDisk.walk(&dir);
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    let socket = RealDisk.entry(&format!("{dir}socket"));
+    let _ = fs::remove_dir_all(&dir);
+    assert_eq!(socket, None);
+    let path = |name: &str| format!("{dir}{name}");
+    let kinds: Vec<(String, bool)> = entries
+        .iter()
+        .map(|(path, entry)| (path.clone(), matches!(entry, Entry::File(_))))
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            (path("a.txt"), true),
+            (path("b.bin"), true),
+            (path("link"), false)
+        ]
+    );
+    assert_eq!(entries[2].1, Entry::Link("a.txt".to_string()));
+
+    // the metadata is read with the entries
+    let (Entry::File(text), Entry::File(binary)) = (&entries[0].1, &entries[1].1) else {
+        unreachable!()
+    };
+    assert_eq!((text.size, text.binary), (1, false));
+    assert_eq!((binary.size, binary.binary), (3, true));
+    assert!(text.mtime > 0);
+}
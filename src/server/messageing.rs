@@ -1,94 +1,213 @@
-use futures::future::select_ok;
-
 use super::*;
+use crate::communication::{project_path::ProjectPath, rpc::RPC};
+use api_server::ServerApi;
+use automerge::ChangeHash;
+use connection::Client;
 
+/// this is returned by the api when there is nothing to broadcast
 pub(crate) const RESET_WAITING: Message = Message::Binary(vec![]);
 
-/// This function will broadcast a message to all connected clients
-/// this is public so that it can be used by the server
-async fn broadcast_message(msg: Message) -> Result<(), String> {
-    if no_client_connected().await {
-        use tokio::time::{sleep, Duration};
-        sleep(Duration::from_millis(1000)).await;
-        return Err("No clients connected".to_string());
+/// the messages that the api handles, they are handled one by one in the order they are sent
+#[derive(Debug)]
+pub(crate) enum Inbox {
+    /// a message from a client
+    Client { username: String, rpc: RPC },
+    /// a message from the server
+    Send { message: Message, route: Route },
+    /// the connection of a client is closed (or it can't get its messages, see `drop_client`)
+    Left { username: String, id: u64 },
+    /// the last chunk of a file is sent to a client, `heads` are the heads of the chunks
+    Loaded {
+        username: String,
+        path: ProjectPath,
+        heads: Vec<ChangeHash>,
+    },
+}
+
+/// who gets a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Route {
+    /// every client, this is used for the changes of the file tree
+    All,
+    /// only these clients (like the ones that have a file open)
+    Users(Vec<String>),
+}
+
+/// send a message to the api (it is ignored if the server is not started)
+pub(crate) fn send_to_api(inbox: Inbox) {
+    match TX.get() {
+        Some(tx) => {
+            let _ = tx.send(inbox); // this only fails if the server is stopped
+        }
+        None => log::error!("the server is not started, {inbox:?} is ignored"),
     }
-    let clients_send = CLIENTS_SEND.lock().await;
-    let mut futures = Vec::with_capacity(clients_send.len());
-    for (_, client) in clients_send.iter() {
-        let client = client.clone();
-        let msg = msg.clone();
-        futures.push(async move { client.lock().await.send(msg).await });
+}
+
+/// This function will broadcast a message to all connected clients
+/// `cursor` is the user that moved the cursor if the message is a cursor update
+/// a client that can't keep up is handled with the overflow policy,
+/// only the clients that fail are removed
+async fn broadcast_message(msg: Message, cursor: Option<&str>) {
+    let clients = CLIENTS.lock().await;
+    for (username, client) in clients.iter() {
+        if let Err(e) = client.outbox.push(msg.clone(), cursor) {
+            drop_client(username, client, &e);
+        }
     }
-    // drop(clients_send);// freeing the client send
-    let futures = futures::future::join_all(futures).await;
-    for i in futures.into_iter() {
-        i.map_err(|e| e.to_string())?
+}
+
+/// send a message to the clients of `route`
+async fn route_message(msg: Message, route: Route, cursor: Option<&str>) {
+    let users = match route {
+        Route::All => return broadcast_message(msg, cursor).await,
+        Route::Users(users) => users,
+    };
+    let clients = CLIENTS.lock().await;
+    for username in users {
+        let Some(client) = clients.get(&username) else {
+            continue; // the client left
+        };
+        if let Err(e) = client.outbox.push(msg.clone(), cursor) {
+            drop_client(&username, client, &e);
+        }
     }
-    Ok(())
 }
-async fn handle_message() -> Result<Message, String> {
-    let client_res = CLIENTS_RES.lock().await;
-    let mut futrs = Vec::with_capacity(client_res.len());
-    if client_res.is_empty() {
-        drop(client_res); // free the lock
 
-        use tokio::time::{sleep, Duration};
-        sleep(Duration::from_millis(200)).await;
+/// send a message to only one client without waiting (the overflow policy is used)
+pub(crate) async fn send_to_client(username: &str, msg: Message) -> Result<(), String> {
+    let clients = CLIENTS.lock().await;
+    let client = clients
+        .get(username)
+        .ok_or_else(|| format!("Client {username} not found"))?;
+    let result = client.outbox.push(msg, None);
+    result.map_err(|e| {
+        drop_client(username, client, &e);
+        format!("Client {username} is disconnected")
+    })
+}
 
-        return Err("No clients connected".to_string());
+/// close the outbox of a client that can't get its messages
+/// the actor removes it like when its connection is closed, it is not removed here
+/// as the api could be locked (`send_to_client` is called by the api)
+fn drop_client(username: &str, client: &Client, e: &str) {
+    if client.outbox.is_closed() {
+        return; // it is already being removed
     }
-    // read message from all clients
-    for (username, client) in client_res.iter() {
-        let client = client.clone();
-        let username = username.clone();
-        futrs.push(Box::pin(async move {
-            let priviledge = client.lock().await.priviledge;
-            let rpc = client.lock().await.read_message().await?;
-            API.lock()
-                .await
-                .read_rpc(rpc, priviledge, &username)
-                .await
-                .map_err(|_| "error reading the message".to_string())
-        }));
-    }
-    drop(client_res); //free the lock to
-                      // this will return the first message it gets
-    let res = match select_ok(futrs).await {
-        Ok((message, _)) => Ok(message),
-        Err(e) => Err(e),
-    };
+    log::error!("removing {username}: {e}");
+    client.outbox.close();
+    send_to_api(Inbox::Left {
+        username: username.to_string(),
+        id: client.id,
+    });
+}
+
+/// remove a client and tell the others that it left, `None` if it is not connected
+/// every removal goes through here so the api always forgets the client
+pub(crate) fn disconnect(
+    api: &mut ServerApi,
+    clients: &mut HashMap<String, Client>,
+    username: &str,
+) -> Option<Client> {
+    let client = clients.remove(username)?;
+    log::info!("Client with username: {} has disconnected", username);
+    api.user_disconnected(username);
+    Some(client)
+}
+
+/// send a message to only one client, this waits if the outbox of the client is full
+/// it should not be used by the api (one slow client would block the others)
+pub(crate) async fn send_to_client_when_ready(username: &str, msg: Message) -> Result<(), String> {
+    let outbox = CLIENTS
+        .lock()
+        .await
+        .get(username)
+        .ok_or_else(|| format!("Client {username} not found"))?
+        .outbox
+        .clone();
+    outbox
+        .send(msg)
+        .await
+        .map_err(|_| format!("Client {username} is disconnected"))
+}
 
-    if res.is_err() {
-        // could be all clients are closed
-        connection::remove_dead_clients().await;
-        return Err("all client send error".to_string());
+/// send the state of the server to the clients that lost messages
+async fn resync_clients() {
+    if !CLIENTS
+        .lock()
+        .await
+        .values()
+        .any(|client| client.outbox.needs_resync())
+    {
+        return;
+    }
+    // the api is locked first like in `connect_to_server`
+    let api = API.lock().await;
+    let clients = CLIENTS.lock().await;
+    for (username, client) in clients.iter() {
+        if client.outbox.needs_resync() {
+            log::warn!("{username} can't keep up, sending the state again");
+            let rpc = connection::connect_rpc(&api, &client.permissions).await;
+            client.outbox.resync(rpc.encode().unwrap());
+        }
     }
-    res
 }
 
-pub(crate) async fn handle_messages() -> ! {
-    let (tx, mut rx) = mpsc::unbounded_channel();
-    TX.set(tx.clone()).unwrap();
-    loop {
-        async {
-            connection::remove_dead_clients().await;
-            let message = tokio::select! {
-                client_message = handle_message() => client_message?,
-                Some(server_message) = rx.recv() => server_message ,
-            };
-            if message.is_empty() {
-                return Ok(());
+/// the api actor, it handles the messages of the clients and the server in order
+pub(crate) async fn handle_messages(mut rx: mpsc::UnboundedReceiver<Inbox>) {
+    while let Some(inbox) = rx.recv().await {
+        let (message, route, cursor) = match inbox {
+            Inbox::Client { username, rpc } => {
+                let permissions = CLIENTS
+                    .lock()
+                    .await
+                    .get(&username)
+                    .map(|c| c.permissions.clone());
+                let Some(permissions) = permissions else {
+                    continue; // the client is removed
+                };
+                let cursor = matches!(rpc, RPC::ReqMoveCursor { .. }).then_some(username.clone());
+                match API
+                    .lock()
+                    .await
+                    .read_rpc(rpc, &permissions, &username)
+                    .await
+                {
+                    Ok((message, route)) => (message, route, cursor),
+                    Err(()) => continue, // the rejection could not be encoded
+                }
             }
-            broadcast_message(message).await?;
-            Ok::<(), String>(())
+            Inbox::Send { message, route } => (message, route, None),
+            Inbox::Loaded {
+                username,
+                path,
+                heads,
+            } => match API.lock().await.loaded(&username, path, &heads) {
+                Some(message) => (message, Route::Users(vec![username]), None),
+                None => continue,
+            },
+            Inbox::Left { username, id } => {
+                let mut api = API.lock().await;
+                let mut clients = CLIENTS.lock().await;
+                // the username could be used by a new connection
+                if clients.get(&username).is_some_and(|c| c.id == id) {
+                    disconnect(&mut api, &mut clients, &username);
+                }
+                continue;
+            }
+        };
+        if !message.is_empty() {
+            route_message(message, route, cursor.as_deref()).await;
         }
-        .await
-        .unwrap_or_else(|err| {
-            log::error!("{}", err);
-        });
+        resync_clients().await;
     }
 }
 
+/// send a message to every client
 pub(crate) fn server_send_message(msg: Message) {
-    TX.get().unwrap().send(msg).unwrap(); // todo: remove this panic
+    send_routed(msg, Route::All);
+}
+
+/// send a message to the clients of `route` (in order with the other messages)
+pub(crate) fn send_routed(message: Message, route: Route) {
+    send_to_api(Inbox::Send { message, route });
 }
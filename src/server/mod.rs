@@ -11,10 +11,11 @@ use tokio::{
 use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
 // use tokio_tungstenite::tungstenite;
 
+use config::ServerConfig;
 use variables::*;
 
 lazy_static! {
-    static ref CURRENT_DIR: String = std::env::current_dir()
+    pub(crate) static ref CURRENT_DIR: String = std::env::current_dir()
         .unwrap()
         .to_str()
         .unwrap()
@@ -24,7 +25,13 @@ lazy_static! {
 type SinkSend = SplitSink<WebSocketStream<TcpStream>, Message>;
 type SinkRes = SplitStream<WebSocketStream<TcpStream>>;
 
+/// start the server with the default config
 pub async fn start_server(port: u16) {
+    start_server_with_config(port, ServerConfig::default()).await
+}
+
+/// start the server with `config` (it can only be set once)
+pub async fn start_server_with_config(port: u16, config: ServerConfig) {
     {
         use simplelog::{CombinedLogger, Config, LevelFilter, WriteLogger};
         use std::env;
@@ -35,28 +42,50 @@ pub async fn start_server(port: u16) {
         )])
         .unwrap();
     } // init logger
-      // main point
+    if CONFIG.set(config).is_err() {
+        log::error!("the config is already set, the new one is ignored");
+    }
+    // main point
     let url = format!("127.0.0.1:{}", port);
     let listener = TcpListener::bind(&url).await.unwrap(); // panic is needed
                                                            // when there is a connection made to the server
-    tokio::spawn(messageing::handle_messages());
+    let (tx, rx) = mpsc::unbounded_channel();
+    if TX.set(tx).is_err() {
+        panic!("the server is already started");
+    }
+    tokio::spawn(messageing::handle_messages(rx));
     tokio::spawn(watcher::watch_file_change());
-    while let Ok((socket, _)) = listener.accept().await {
-        log::info!("New connection from {:?}", socket.peer_addr().unwrap());
-        tokio::spawn(connection::connect_to_server(socket));
+    if let Some(interval) = config::config().rescan_interval {
+        tokio::spawn(watcher::rescan_periodically(interval));
     }
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let Ok((socket, _)) = accepted else {
+                    break;
+                };
+                log::info!("New connection from {:?}", socket.peer_addr().unwrap());
+                tokio::spawn(connection::connect_to_server(socket));
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    API.lock().await.shutdown().await;
 }
 
-
 pub(crate) async fn no_client_connected() -> bool {
     // this is pub for integration tests
-    CLIENTS_RES.lock().await.is_empty()
+    CLIENTS.lock().await.is_empty()
 }
 
 pub(crate) mod api_server;
+pub(crate) mod config;
 pub(crate) mod connection;
+pub(crate) mod disk;
 pub(crate) mod messageing;
+pub(crate) mod outbox;
+pub(crate) mod roots;
 #[cfg(test)]
 pub(crate) mod test;
-pub(crate) mod watcher;
 pub(crate) mod variables;
+pub(crate) mod watcher;
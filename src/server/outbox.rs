@@ -0,0 +1,255 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex as SyncMutex;
+
+use tokio::sync::Notify;
+use tokio_tungstenite::tungstenite::Message;
+
+/// what to do when the outbox of a client is full (the client can't keep up)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// disconnect the client
+    Disconnect,
+    /// drop the cursor updates that have a newer one in the outbox
+    /// the client is disconnected if there is nothing to drop
+    #[default]
+    CoalesceCursors,
+    /// drop every waiting message and send the state of the server again (`ResConnect`)
+    /// the client has to request the files it needs again
+    Resync,
+}
+
+/// the stats of the outbox of a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueueStats {
+    /// the number of messages waiting to be sent
+    pub depth: usize,
+    /// the biggest depth so far
+    pub max_depth: usize,
+    /// the number of messages sent to the client
+    pub sent: u64,
+    /// the number of cursor updates dropped as there was a newer one
+    pub coalesced: u64,
+    /// the number of messages dropped by resyncs
+    pub dropped: u64,
+    /// the number of times the state was sent again
+    pub resyncs: u64,
+}
+
+#[derive(Debug)]
+struct Queued {
+    message: Message,
+    /// the user that moved the cursor if this is a cursor update
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    queue: VecDeque<Queued>,
+    closed: bool,
+    stats: QueueStats,
+}
+
+/// the messages waiting to be sent to a client
+/// it is filled by the api without waiting and drained by the writer task of the client
+#[derive(Debug)]
+pub(crate) struct Outbox {
+    capacity: usize,
+    policy: OverflowPolicy,
+    state: SyncMutex<State>,
+    /// the waiting messages were dropped and the state should be sent again
+    resync: AtomicBool,
+    /// notified when a message is added or the outbox is closed
+    ready: Notify,
+    /// notified when a message is sent
+    space: Notify,
+}
+
+impl Outbox {
+    pub(crate) fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            policy,
+            state: SyncMutex::new(State::default()),
+            resync: AtomicBool::new(false),
+            ready: Notify::new(),
+            space: Notify::new(),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        // the lock is never held across a panic
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// add a message without waiting
+    /// `cursor` is the user that moved the cursor if the message is a cursor update
+    /// if the outbox is full the overflow policy is used,
+    /// an error means that the client should be disconnected
+    pub(crate) fn push(&self, message: Message, cursor: Option<&str>) -> Result<(), String> {
+        let mut state = self.state();
+        if state.closed {
+            return Err("the client is disconnected".to_string());
+        }
+        if self.resync.load(Ordering::Relaxed) {
+            state.stats.dropped += 1; // the state will be sent again
+            return Ok(());
+        }
+        if state.queue.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::Disconnect => return Err("the outbox is full".to_string()),
+                OverflowPolicy::CoalesceCursors => {
+                    if !Self::coalesce(&mut state, cursor) {
+                        return Err("the outbox is full".to_string());
+                    }
+                }
+                OverflowPolicy::Resync => {
+                    state.stats.dropped += state.queue.len() as u64 + 1;
+                    state.queue.clear();
+                    state.stats.depth = 0;
+                    self.resync.store(true, Ordering::Relaxed);
+                    return Ok(());
+                }
+            }
+        }
+        Self::enqueue(&mut state, message, cursor);
+        drop(state);
+        self.ready.notify_one();
+        Ok(())
+    }
+
+    /// add a message, waiting while the outbox is full
+    /// this is used for the messages that can't be dropped like the chunks of a file
+    pub(crate) async fn send(&self, message: Message) -> Result<(), String> {
+        loop {
+            let space = self.space.notified();
+            tokio::pin!(space);
+            space.as_mut().enable(); // so a message sent after the check is not missed
+            {
+                let mut state = self.state();
+                if state.closed {
+                    return Err("the client is disconnected".to_string());
+                }
+                if self.resync.load(Ordering::Relaxed) {
+                    state.stats.dropped += 1;
+                    return Ok(());
+                }
+                if state.queue.len() < self.capacity {
+                    Self::enqueue(&mut state, message, None);
+                    drop(state);
+                    self.ready.notify_one();
+                    return Ok(());
+                }
+            }
+            space.await;
+        }
+    }
+
+    /// take the next message, `None` if the outbox is closed and empty
+    pub(crate) async fn recv(&self) -> Option<Message> {
+        loop {
+            {
+                let mut state = self.state();
+                if let Some(queued) = state.queue.pop_front() {
+                    state.stats.depth = state.queue.len();
+                    state.stats.sent += 1;
+                    drop(state);
+                    self.space.notify_waiters();
+                    return Some(queued.message);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            // `notify_one` keeps a permit so a message added after the check is not missed
+            self.ready.notified().await;
+        }
+    }
+
+    /// drop the waiting messages, send `message` and close the outbox
+    /// this is used to send a close frame
+    pub(crate) fn close_with(&self, message: Message) {
+        let mut state = self.state();
+        state.stats.dropped += state.queue.len() as u64;
+        state.queue.clear();
+        Self::enqueue(&mut state, message, None);
+        state.closed = true;
+        drop(state);
+        self.ready.notify_one();
+        self.space.notify_waiters();
+    }
+
+    /// no more messages are accepted, the waiting ones are still sent
+    pub(crate) fn close(&self) {
+        self.state().closed = true;
+        self.ready.notify_one();
+        self.space.notify_waiters();
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.state().closed
+    }
+
+    /// `true` if messages were dropped and the state should be sent again with `resync`
+    pub(crate) fn needs_resync(&self) -> bool {
+        self.resync.load(Ordering::Relaxed)
+    }
+
+    /// send the state of the server (it is the first message after the dropped ones)
+    pub(crate) fn resync(&self, message: Message) {
+        let mut state = self.state();
+        state.stats.dropped += state.queue.len() as u64;
+        state.queue.clear();
+        state.stats.resyncs += 1;
+        self.resync.store(false, Ordering::Relaxed);
+        Self::enqueue(&mut state, message, None);
+        drop(state);
+        self.ready.notify_one();
+    }
+
+    pub(crate) fn stats(&self) -> QueueStats {
+        self.state().stats
+    }
+
+    fn enqueue(state: &mut State, message: Message, cursor: Option<&str>) {
+        state.queue.push_back(Queued {
+            message,
+            cursor: cursor.map(str::to_string),
+        });
+        state.stats.depth = state.queue.len();
+        state.stats.max_depth = state.stats.max_depth.max(state.stats.depth);
+    }
+
+    /// remove a cursor update that is not needed anymore to make space for a new message
+    /// returns `false` if there is none
+    fn coalesce(state: &mut State, cursor: Option<&str>) -> bool {
+        let index = match cursor {
+            // the new message replaces the old cursor of the same user
+            Some(user) => state
+                .queue
+                .iter()
+                .position(|queued| queued.cursor.as_deref() == Some(user)),
+            None => None,
+        }
+        .or_else(|| {
+            // a cursor update that has a newer one in the queue
+            let mut newer = HashSet::new();
+            let from_end = state.queue.iter().rev().position(|queued| {
+                queued
+                    .cursor
+                    .as_deref()
+                    .is_some_and(|user| !newer.insert(user))
+            })?;
+            Some(state.queue.len() - 1 - from_end)
+        });
+        let Some(index) = index else {
+            return false;
+        };
+        state.queue.remove(index);
+        state.stats.coalesced += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests;
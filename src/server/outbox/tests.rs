@@ -0,0 +1,82 @@
+use super::*;
+use std::time::Duration;
+
+fn msg(n: u8) -> Message {
+    Message::Binary(vec![n])
+}
+
+async fn drain(outbox: &Outbox) -> Vec<Message> {
+    outbox.close();
+    let mut messages = vec![];
+    while let Some(message) = outbox.recv().await {
+        messages.push(message);
+    }
+    messages
+}
+
+#[tokio::test]
+async fn disconnect_when_full() {
+    let outbox = Outbox::new(2, OverflowPolicy::Disconnect);
+    outbox.push(msg(0), None).unwrap();
+    outbox.push(msg(1), Some("a")).unwrap();
+    assert!(outbox.push(msg(2), None).is_err());
+    let stats = outbox.stats();
+    assert_eq!((stats.depth, stats.max_depth), (2, 2));
+    assert_eq!(drain(&outbox).await, [msg(0), msg(1)]);
+    assert!(outbox.push(msg(3), None).is_err()); // closed
+}
+
+#[tokio::test]
+async fn coalesce_cursors() {
+    let outbox = Outbox::new(3, OverflowPolicy::CoalesceCursors);
+    outbox.push(msg(0), Some("a")).unwrap();
+    outbox.push(msg(1), Some("b")).unwrap();
+    outbox.push(msg(2), None).unwrap();
+    // the old cursor of `a` is dropped
+    outbox.push(msg(3), Some("a")).unwrap();
+    // there is no cursor update that has a newer one
+    outbox.push(msg(4), Some("c")).unwrap_err();
+    assert_eq!(outbox.stats().coalesced, 1);
+    assert_eq!(drain(&outbox).await, [msg(1), msg(2), msg(3)]);
+
+    let outbox = Outbox::new(3, OverflowPolicy::CoalesceCursors);
+    outbox.push(msg(0), Some("a")).unwrap();
+    outbox.push(msg(1), Some("b")).unwrap();
+    outbox.push(msg(2), Some("a")).unwrap();
+    // edits can't be dropped but the first cursor of `a` has a newer one
+    outbox.push(msg(3), None).unwrap();
+    assert_eq!(drain(&outbox).await, [msg(1), msg(2), msg(3)]);
+}
+
+#[tokio::test]
+async fn resync_when_full() {
+    let outbox = Outbox::new(2, OverflowPolicy::Resync);
+    outbox.push(msg(0), None).unwrap();
+    outbox.push(msg(1), None).unwrap();
+    outbox.push(msg(2), None).unwrap();
+    assert!(outbox.needs_resync());
+    // the messages are ignored until the state is sent
+    outbox.push(msg(3), None).unwrap();
+    outbox.resync(msg(4));
+    outbox.push(msg(5), None).unwrap();
+    assert!(!outbox.needs_resync());
+    let stats = outbox.stats();
+    assert_eq!((stats.dropped, stats.resyncs), (4, 1));
+    assert_eq!(drain(&outbox).await, [msg(4), msg(5)]);
+}
+
+#[tokio::test]
+async fn send_waits_for_space() {
+    let outbox = std::sync::Arc::new(Outbox::new(1, OverflowPolicy::Disconnect));
+    outbox.send(msg(0)).await.unwrap();
+    let sender = {
+        let outbox = outbox.clone();
+        tokio::spawn(async move { outbox.send(msg(1)).await })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!sender.is_finished());
+    assert_eq!(outbox.recv().await, Some(msg(0)));
+    sender.await.unwrap().unwrap();
+    assert_eq!(outbox.recv().await, Some(msg(1)));
+    assert_eq!(outbox.stats().sent, 2);
+}
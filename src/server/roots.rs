@@ -0,0 +1,198 @@
+use std::io::{self, Error};
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+
+use super::{
+    config::{config, SharedRoot},
+    CURRENT_DIR,
+};
+use crate::communication::{
+    permissions::glob_match, project_path::ProjectPath, rpc::WorkspaceRoot,
+};
+
+type Res<T> = io::Result<T>;
+
+lazy_static! {
+    static ref MOUNTS: Vec<Mount> = Mount::all(&config().roots);
+}
+
+/// a shared directory on the disk and the directory of the tree where its content is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Mount {
+    pub(crate) name: String,
+    /// `./` if only the current directory is shared, `./<name>/` if there are roots in the config
+    pub(crate) dir: ProjectPath,
+    pub(crate) disk: PathBuf,
+    ignore: Vec<String>,
+}
+
+impl Mount {
+    /// the roots of the config, or the current directory if there are none
+    /// the roots with a name that is not valid or used twice are skipped
+    fn all(roots: &[SharedRoot]) -> Vec<Self> {
+        if roots.is_empty() {
+            let disk = PathBuf::from(&*CURRENT_DIR);
+            let name = disk.file_name().map(|name| name.to_string_lossy());
+            return vec![Self {
+                name: name.unwrap_or_default().into_owned(),
+                dir: ProjectPath::root(),
+                disk,
+                ignore: vec![],
+            }];
+        }
+        let mut mounts: Vec<Self> = vec![];
+        for root in roots {
+            let dir = ProjectPath::new(&root.name).map(ProjectPath::into_dir);
+            match dir {
+                Ok(dir)
+                    if !dir.is_root()
+                        && dir.parent().is_root()
+                        && mounts.iter().all(|mount| mount.dir != dir) =>
+                {
+                    mounts.push(Self {
+                        name: root.name.clone(),
+                        dir,
+                        disk: root.path.canonicalize().unwrap_or(root.path.clone()),
+                        ignore: root.ignore.clone(),
+                    })
+                }
+                _ => log::error!(
+                    "the root {:?} is not shared, its name should be a unique directory name",
+                    root.name
+                ),
+            }
+        }
+        mounts
+    }
+
+    /// the part of a path of the tree that is in this root (`./b` for `./a/b` in `./a/`)
+    fn relative(&self, path: &str) -> Option<ProjectPath> {
+        if self.dir.is_root() {
+            return ProjectPath::new(path).ok();
+        }
+        let rest = if path == self.dir.trim_end_matches('/') {
+            ""
+        } else {
+            path.strip_prefix(self.dir.as_str())?
+        };
+        let relative = ProjectPath::new(rest).ok()?;
+        Some(if path.ends_with('/') {
+            relative.into_dir()
+        } else {
+            relative
+        })
+    }
+
+    /// the path on the disk of a path of the tree (the symlinks are not checked)
+    pub(crate) fn full(&self, path: &str) -> PathBuf {
+        let relative = self.relative(path).unwrap_or_else(ProjectPath::root);
+        self.disk.join(relative.trim_start_matches("./"))
+    }
+
+    /// the path on the disk of a path of the tree (it should be inside of the root)
+    pub(crate) fn resolve(&self, path: &ProjectPath) -> Res<PathBuf> {
+        let relative = self.relative(path).ok_or_else(|| {
+            Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{path} is not in {}", self.dir),
+            )
+        })?;
+        relative.resolve(&self.disk)
+    }
+
+    /// the path of the tree of a path on the disk (in this root)
+    pub(crate) fn project_path(&self, path: &Path, is_dir: bool) -> Res<ProjectPath> {
+        let relative = ProjectPath::from_disk(path, &self.disk, is_dir)?;
+        let path = ProjectPath::new(&format!("{}{}", self.dir, &relative[2..]))?;
+        Ok(if is_dir { path.into_dir() } else { path })
+    }
+
+    /// the ignore rules of the root match the path or one of its parents
+    pub(crate) fn is_ignored(&self, path: &str) -> bool {
+        let Some(relative) = self.relative(path) else {
+            return false;
+        };
+        let relative = relative.trim_start_matches("./").trim_end_matches('/');
+        if relative.is_empty() {
+            return false;
+        }
+        let parts: Vec<&str> = relative.split('/').collect();
+        (1..=parts.len()).any(|len| {
+            let path = parts[..len].join("/");
+            self.ignore.iter().any(|glob| {
+                if glob.trim_end_matches('/').contains('/') {
+                    glob_match(glob, &path)
+                } else {
+                    glob_match(glob, parts[len - 1])
+                }
+            })
+        })
+    }
+}
+
+/// the shared directories
+pub(crate) fn mounts() -> &'static [Mount] {
+    &MOUNTS
+}
+
+/// the roots of the config are directories of the tree (`./` has no directory on the disk)
+pub(crate) fn is_multi_root() -> bool {
+    MOUNTS.first().is_some_and(|mount| !mount.dir.is_root())
+}
+
+/// the roots (and `./` if there are more than one) are the directories of the config,
+/// they can't be moved, deleted or be the source or destination of a copy
+pub(crate) fn check_not_root(path: &str) -> Res<()> {
+    not_a_root(&MOUNTS, path)
+}
+
+fn not_a_root(mounts: &[Mount], path: &str) -> Res<()> {
+    let multi_root = mounts.first().is_some_and(|mount| !mount.dir.is_root());
+    if multi_root && (path == "./" || mounts.iter().any(|mount| mount.dir.as_str() == path)) {
+        return Err(Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{path} is a shared root"),
+        ));
+    }
+    Ok(())
+}
+
+/// the root of a path of the tree (`None` for `./` if there are more than one)
+pub(crate) fn mount_of(path: &str) -> Option<&'static Mount> {
+    find(&MOUNTS, path)
+}
+
+fn find<'a>(mounts: &'a [Mount], path: &str) -> Option<&'a Mount> {
+    mounts
+        .iter()
+        .find(|mount| mount.dir.is_root() || mount.relative(path).is_some())
+}
+
+/// the path of the tree of a path on the disk (like the paths of the events of the watchers)
+/// the deepest root is used if a root is inside of another one
+pub(crate) fn project_path(path: &Path, is_dir: bool) -> Res<ProjectPath> {
+    let mounts = MOUNTS.iter().filter(|mount| path.starts_with(&mount.disk));
+    let mount = mounts.max_by_key(|mount| mount.disk.components().count());
+    let mount = mount.ok_or_else(|| {
+        Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is outside of the shared directories", path.display()),
+        )
+    })?;
+    mount.project_path(path, is_dir)
+}
+
+/// the roots as they are sent to the clients
+pub(crate) fn workspace_roots() -> Vec<WorkspaceRoot> {
+    let mounts = MOUNTS.iter();
+    mounts
+        .map(|mount| WorkspaceRoot {
+            name: mount.name.clone(),
+            path: mount.dir.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,64 @@
+use super::*;
+
+fn two_roots() -> Vec<Mount> {
+    let roots = [
+        SharedRoot::new("app", "/work/app").with_ignore("target"),
+        SharedRoot::new("lib", "/work/lib").with_ignore("docs/build/**"),
+        // the names should be unique directory names
+        SharedRoot::new("app", "/work/other"),
+        SharedRoot::new("a/b", "/work/a/b"),
+        SharedRoot::new("", "/work"),
+    ];
+    Mount::all(&roots)
+}
+
+#[test]
+fn paths_are_in_the_directory_of_their_root() {
+    let mounts = two_roots();
+    let dirs: Vec<&str> = mounts.iter().map(|mount| mount.dir.as_str()).collect();
+    assert_eq!(dirs, ["./app/", "./lib/"]);
+
+    let lib = find(&mounts, "./lib/src/a.rs").unwrap();
+    assert_eq!(lib.name, "lib");
+    assert_eq!(lib.full("./lib/src/a.rs"), Path::new("/work/lib/src/a.rs"));
+    assert_eq!(lib.full("./lib/"), Path::new("/work/lib/"));
+    assert!(find(&mounts, "./").is_none());
+    assert!(find(&mounts, "./library/a.rs").is_none());
+
+    let path = lib.project_path(Path::new("/work/lib/src"), true).unwrap();
+    assert_eq!(path.as_str(), "./lib/src/");
+    let path = lib.project_path(Path::new("/work/lib"), true).unwrap();
+    assert_eq!(path.as_str(), "./lib/");
+    assert!(lib
+        .project_path(Path::new("/work/app/a.rs"), false)
+        .is_err());
+}
+
+#[test]
+fn roots_are_not_changed() {
+    let mounts = two_roots();
+    for path in ["./", "./app/", "./lib/"] {
+        assert!(not_a_root(&mounts, path).is_err());
+    }
+    assert!(not_a_root(&mounts, "./app/src/").is_ok());
+    assert!(not_a_root(&mounts, "./other/").is_ok());
+    // the current directory is not a root of the config
+    let single = Mount::all(&[]);
+    assert!(not_a_root(&single, "./").is_ok());
+    assert!(not_a_root(&single, "./app/").is_ok());
+}
+
+#[test]
+fn ignore_rules_are_per_root() {
+    let mounts = two_roots();
+    let (app, lib) = (&mounts[0], &mounts[1]);
+    assert!(app.is_ignored("./app/target/"));
+    assert!(app.is_ignored("./app/crate/target/debug/a.o"));
+    assert!(!app.is_ignored("./app/"));
+    assert!(!app.is_ignored("./app/targets.txt"));
+    assert!(!lib.is_ignored("./lib/target/"));
+
+    assert!(lib.is_ignored("./lib/docs/build/index.html"));
+    assert!(!lib.is_ignored("./lib/docs/index.html"));
+    assert!(!lib.is_ignored("./lib/src/docs/build/a.html"));
+}
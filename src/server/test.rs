@@ -166,3 +166,116 @@ fn watcher_delete_dir() {
     })
     .unwrap();
 }
+
+async fn join(username: &str) -> WebSocketStream<tokio_tungstenite::MaybeTlsStream<TcpStream>> {
+    use crate::communication::rpc::RPC;
+    let (mut ws, _) = tokio_tungstenite::connect_async("ws://127.0.0.1:8080")
+        .await
+        .unwrap();
+    let rpc = RPC::AddUsername(username.to_string());
+    ws.send(rpc.encode().unwrap()).await.unwrap();
+    ws
+}
+
+/// the reason of the close frame that the server sent
+async fn close_reason(
+    ws: &mut WebSocketStream<tokio_tungstenite::MaybeTlsStream<TcpStream>>,
+) -> String {
+    use futures::StreamExt as _;
+    while let Some(message) = ws.next().await {
+        if let Ok(Message::Close(Some(frame))) = message {
+            return frame.reason.to_string();
+        }
+    }
+    panic!("the connection is closed without a close frame");
+}
+
+#[test]
+#[serial]
+fn locked_session_and_kick() {
+    RT.block_on(async {
+        API.lock().await.lock_session().await;
+        let mut ws = join("late").await;
+        assert_eq!(close_reason(&mut ws).await, "the session is locked");
+        API.lock().await.unlock_session().await;
+
+        let mut ws = join("kicked").await;
+        sleep(Duration::from_millis(500)).await;
+        API.lock().await.ban("kicked", "spam").await;
+        assert_eq!(close_reason(&mut ws).await, "spam");
+        let mut ws = join("kicked").await;
+        assert_eq!(close_reason(&mut ws).await, "you are banned: spam");
+        assert!(API.lock().await.unban("kicked").await);
+    });
+}
+
+#[test]
+#[serial]
+fn duplicate_username() {
+    RT.block_on(async {
+        let _first = join("twice").await;
+        sleep(Duration::from_millis(500)).await;
+        let id = CLIENTS.lock().await.get("twice").map(|client| client.id);
+        let mut ws = join("twice").await;
+        assert_eq!(close_reason(&mut ws).await, "the username is used");
+        // the first connection is kept
+        assert_eq!(
+            CLIENTS.lock().await.get("twice").map(|client| client.id),
+            id
+        );
+        assert!(id.is_some());
+        API.lock().await.kick("twice", "done").await.unwrap();
+    });
+}
+
+#[test]
+#[serial]
+fn overflowing_client_leaves() {
+    use crate::communication::{project_path::ProjectPath, rpc::RPC};
+    use connection::Client;
+    use messageing::{send_routed, send_to_api, Inbox, Route};
+    use outbox::{Outbox, OverflowPolicy};
+    use std::sync::Arc;
+    RT.block_on(async {
+        let slow = Arc::new(Outbox::new(100, OverflowPolicy::Disconnect));
+        let peer = Arc::new(Outbox::new(1000, OverflowPolicy::Disconnect));
+        {
+            let mut clients = CLIENTS.lock().await;
+            clients.insert("slow".to_string(), Client::detached(slow.clone()));
+            clients.insert("peer".to_string(), Client::detached(peer.clone()));
+        }
+        let path = ProjectPath::new("./Cargo.toml").unwrap();
+        send_to_api(Inbox::Client {
+            username: "slow".to_string(),
+            rpc: RPC::OpenFile { path },
+        });
+        sleep(Duration::from_millis(500)).await;
+        let open = || async {
+            let api = API.lock().await;
+            api.who_has_open("./Cargo.toml".to_string()).await.unwrap()
+        };
+        assert_eq!(open().await, ["slow"]);
+
+        // the other tests could broadcast too so the outbox is filled here
+        while slow.push(Message::Binary(vec![]), None).is_ok() {}
+        send_routed(
+            Message::Binary(vec![1]),
+            Route::Users(vec!["slow".to_string()]),
+        );
+        let left = async {
+            while let Some(message) = peer.recv().await {
+                if let Message::Binary(bytes) = message {
+                    if let Ok(RPC::UserLeft { username }) = RPC::decode(&bytes) {
+                        return username;
+                    }
+                }
+            }
+            panic!("peer is removed");
+        };
+        let left = tokio::time::timeout(Duration::from_secs(5), left).await;
+        assert_eq!(left.unwrap(), "slow");
+        assert!(open().await.is_empty());
+        assert!(!CLIENTS.lock().await.contains_key("slow"));
+        API.lock().await.kick("peer", "done").await.unwrap();
+    });
+}
@@ -1,15 +1,17 @@
 use crate::server::api_server::ServerApi;
 
 use super::*;
-use connection::ClientRes;
-use std::sync::{Arc, OnceLock};
+use config::ServerConfig;
+use connection::Client;
+use messageing::Inbox;
+use std::sync::OnceLock;
 
 lazy_static! {
     pub static ref API: Mutex<ServerApi> = Mutex::new(ServerApi::new_server());
-    pub(super) static ref CLIENTS_RES: Mutex<HashMap<String, Arc<Mutex<ClientRes>>>> =
-        Mutex::new(HashMap::new());
-    pub(crate) static ref CLIENTS_SEND: Mutex<HashMap<String, Arc<Mutex<SinkSend>>>> =
-        Mutex::new(HashMap::new());
+    /// the connected clients
+    pub(crate) static ref CLIENTS: Mutex<HashMap<String, Client>> = Mutex::new(HashMap::new());
 }
 
-pub(super) static TX: OnceLock<mpsc::UnboundedSender<Message>> = OnceLock::new();
+/// the inbox of the api
+pub(super) static TX: OnceLock<mpsc::UnboundedSender<Inbox>> = OnceLock::new();
+pub(super) static CONFIG: OnceLock<ServerConfig> = OnceLock::new();
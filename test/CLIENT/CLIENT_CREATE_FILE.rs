@@ -5,7 +5,7 @@ pub async fn run_test() {
     println!("test_is_running");
 
     let rpc = RPC::CreateFile {
-        path: "./this.c".to_string(),
+        path: "./this.c".try_into().unwrap(),
     };
 
     set_and_await(Some(rpc.encode().unwrap())).await;
@@ -6,7 +6,7 @@ pub async fn run_test() {
     let client_api = crate::client::API.get().unwrap();
     println!("test_is_running");
 
-    let rpc = RPC::CreateFile { path: "./this.c".to_string() };
+    let rpc = RPC::CreateFile { path: "./this.c".try_into().unwrap() };
     
 
     set_and_await(Some( rpc.encode().unwrap() )).await;
@@ -11,7 +11,7 @@ pub async fn run_test(){
     // awaiting message is only for the client and not for the server
 
 
-    let rpc = RPC::CreateFile { path: "./this.c".to_string() };
+    let rpc = RPC::CreateFile { path: "./this.c".try_into().unwrap() };
     let message = rpc.encode();
 
     crate::server::messageing::server_send_message(message.unwrap());
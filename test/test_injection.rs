@@ -9,7 +9,9 @@ pub async fn run_test() {
     // await_message(message.clone()).await;
 
     let rpc = RPC::CreateFile {
-        path: "./test.txt".to_string(),
+        path: "./test.txt".try_into().unwrap(),
+        mode: None,
+        content: None,
     };
 
     set_and_await(Some( rpc.encode().unwrap() )).await;
@@ -40,7 +42,7 @@ pub async fn run_test() {
 
     set_and_await(
         Some( RPC::FileSaved {
-            path: "./test.txt".to_string(),
+            path: "./test.txt".try_into().unwrap(),
         }
         .encode()
         .unwrap(), )
@@ -51,7 +53,7 @@ pub async fn run_test() {
         .lock()
         .await
         .send_rpc(RPC::ReqSaveFile {
-            path: "./test.txt".to_string(),
+            path: "./test.txt".try_into().unwrap(),
         })
         .await;
     await_the_last_message().await;